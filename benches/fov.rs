@@ -0,0 +1,215 @@
+// Times the shadowcasting field of view against the bresenham lines it replaced, from every
+// floor tile of a few generated levels. Run with `cargo bench --bench fov`.
+use std::time::Instant;
+
+use map_gen_2d::Point;
+use p01_rogue::{fov::field_of_view, map::Level, tiles::TileType};
+
+const SEEDS: [u64; 3] = [5, 6, 7];
+const RANGE: i32 = 8;
+
+// What update_tile_vis_and_explore did for one mob before shadowcasting: a bresenham line to
+// every tile in a square around it, keeping the ones on the map.
+fn bresenham_fov(origin: (i32, i32), range: i32, map: &Level) -> Vec<((i32, i32), i32)> {
+    let mut visible = Vec::new();
+    for x in (origin.0 - range)..(origin.0 + range) {
+        for y in (origin.1 - range)..(origin.1 + range) {
+            if let Some(light) = bresenham_line_enhanced(&origin, &(x, y), map) {
+                visible.push(((x, y), light));
+            }
+        }
+    }
+    visible.retain(|p| {
+        p.0 .0 >= 0
+            && p.0 .0 < map.size.0.try_into().unwrap()
+            && p.0 .1 >= 0
+            && p.0 .1 < map.size.1.try_into().unwrap()
+    });
+    visible
+}
+
+// The line of sight from src/rendering.rs before shadowcasting, unchanged.
+
+// Returns none if line is out of range / not visible
+// Returns some(i32) where i32 is light level
+// https://sites.google.com/site/jicenospam/visibilitydetermination
+// https://en.wikipedia.org/wiki/Bresenham%27s_line_algorithm#Algorithm_for_integer_arithmetic
+fn bresenham_line_enhanced(
+    position: &(i32, i32),
+    tile_position: &(i32, i32),
+    map: &Level,
+) -> Option<i32> {
+    // position = (x0,y0) and tile_position = (x1,y1)
+
+    if (tile_position.1 - position.1).abs() < (tile_position.0 - position.0) {
+        if position.0 > tile_position.0 {
+            bresenham_line_enhanced_low(tile_position, position, map)
+        } else {
+            bresenham_line_enhanced_low(position, tile_position, map)
+        }
+    } else if position.1 > tile_position.1 {
+        bresenham_line_enhanced_high(tile_position, position, map)
+    } else {
+        bresenham_line_enhanced_high(position, tile_position, map)
+    }
+}
+
+fn bresenham_line_enhanced_high(
+    position: &(i32, i32),
+    tile_position: &(i32, i32),
+    map: &Level,
+) -> Option<i32> {
+    // Make sure all positions are positive
+    if position.0 < 0 || position.1 < 0 || tile_position.0 < 0 || tile_position.1 < 0 {
+        return None;
+    }
+    // If we were passed a wall, obivously we can see the wall so pass Some(distance)
+    if let Some(tile) = map.tiles.get(&Point {
+        x: position.0.try_into().unwrap(),
+        y: position.1.try_into().unwrap(),
+    }) {
+        if tile.0 == TileType::WALL {
+            let distance =
+                (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
+            return Some(distance);
+        }
+    }
+    // Algorithm
+    let mut dx: i32 = tile_position.0 - position.0;
+    let dy: i32 = tile_position.1 - position.1;
+    let mut xi: i32 = 1;
+    if dx < 0 {
+        xi = -1;
+        dx = -dx
+    }
+    let mut d: i32 = (2 * dx) - dy;
+    let mut x: i32 = position.0;
+    for y in position.1..=tile_position.1 {
+        // If this is a wall, we can not see the next tile.
+        if let Some(tile) = map.tiles.get(&Point {
+            x: x.try_into().unwrap(),
+            y: y.try_into().unwrap(),
+        }) {
+            if tile.0 == TileType::WALL
+                && ((x == tile_position.0 && y == tile_position.1)
+                    || (x == position.0 && y == position.1))
+            {
+                let distance =
+                    (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
+                return Some(distance);
+            } else if tile.0 == TileType::WALL {
+                return None;
+            }
+        } else {
+            return None;
+        }
+        // Progress algorithm
+        if d > 0 {
+            x += xi;
+            d += 2 * (dx - dy)
+        } else {
+            d += 2 * dx;
+        }
+    }
+    // If we have gotten here, we have succesfully reached the tile.
+    let distance = (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
+    Some(distance)
+}
+
+fn bresenham_line_enhanced_low(
+    position: &(i32, i32),
+    tile_position: &(i32, i32),
+    map: &Level,
+) -> Option<i32> {
+    // Make sure all positions are postive
+    if position.0 < 0 || position.1 < 0 || tile_position.0 < 0 || tile_position.1 < 0 {
+        return None;
+    }
+    // If we were passed a wall, obivously we can see the wall so pass Some(distance)
+    if let Some(tile) = map.tiles.get(&Point {
+        x: position.0.try_into().unwrap(),
+        y: position.1.try_into().unwrap(),
+    }) {
+        if tile.0 == TileType::WALL {
+            let distance =
+                (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
+            return Some(distance);
+        }
+    }
+    let dx: i32 = tile_position.0 - position.0;
+    let mut dy: i32 = tile_position.1 - position.1;
+    let mut yi: i32 = 1;
+    if dy < 0 {
+        yi = -1;
+        dy = -dy
+    }
+    let mut d: i32 = (2 * dy) - dx;
+    let mut y: i32 = position.1;
+    for x in position.0..=tile_position.0 {
+        if let Some(tile) = map.tiles.get(&Point {
+            x: x.try_into().unwrap(),
+            y: y.try_into().unwrap(),
+        }) {
+            if tile.0 == TileType::WALL
+                && ((x == tile_position.0 && y == tile_position.1)
+                    || (x == position.0 && y == position.1))
+            {
+                let distance =
+                    (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
+                return Some(distance);
+            } else if tile.0 == TileType::WALL {
+                return None;
+            }
+        } else {
+            return None;
+        }
+        // Progress algorithm
+        if d > 0 {
+            y += yi;
+            d += 2 * (dy - dx)
+        } else {
+            d += 2 * dy;
+        }
+    }
+    // If we have gotten here, we have succesfully reached the tile.
+    let distance = (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
+    Some(distance)
+}
+
+fn main() {
+    for seed in SEEDS {
+        let level = Level::generate(seed, 1);
+        let floors: Vec<(i32, i32)> = level
+            .tiles
+            .iter()
+            .filter(|tile| tile.1 .0 == TileType::FLOOR)
+            .map(|tile| (tile.0.x as i32, tile.0.y as i32))
+            .collect();
+
+        let start = Instant::now();
+        let seen: usize = floors
+            .iter()
+            .map(|floor| bresenham_fov(*floor, RANGE, &level).len())
+            .sum();
+        println!(
+            "seed {}: bresenham     {:>10.2?} for {} origins, {} tiles seen",
+            seed,
+            start.elapsed(),
+            floors.len(),
+            seen
+        );
+
+        let start = Instant::now();
+        let seen: usize = floors
+            .iter()
+            .map(|floor| field_of_view(*floor, RANGE, |x, y| level.blocks_sight(x, y)).len())
+            .sum();
+        println!(
+            "seed {}: shadowcasting {:>10.2?} for {} origins, {} tiles seen",
+            seed,
+            start.elapsed(),
+            floors.len(),
+            seen
+        );
+    }
+}
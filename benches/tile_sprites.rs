@@ -0,0 +1,87 @@
+// Walks the player across a 200 by 200 level, drawn the way the game draws it, and prints how
+// many entities there are and how long turns take as more of the level is explored.
+// Run with `cargo bench --bench tile_sprites`.
+use std::time::{Duration, Instant};
+
+use map_gen_2d::Point;
+use p01_rogue::{
+    actions::PlayerAction,
+    components::{MainCamera, MobType},
+    headless::Simulation,
+    map::Level,
+    rendering::RenderingPlugin,
+};
+
+const SIZE: usize = 200;
+// Rows the player walks along, far enough apart that each one shows new tiles
+const LANE_GAP: usize = 10;
+
+// A walled room with a pillar on every fifth tile, so sight lines are broken up.
+fn pillared_room() -> Level {
+    let rows: Vec<String> = (0..SIZE)
+        .map(|y| {
+            (0..SIZE)
+                .map(|x| {
+                    let edge = x == 0 || y == 0 || x == SIZE - 1 || y == SIZE - 1;
+                    if edge || (x % 5 == 0 && y % 5 == 0) {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+    let mut level = Level::from_ascii(&rows);
+    level.mobs.push((Point::new(2, 2), MobType::PLAYER));
+    level
+}
+
+fn main() {
+    let mut simulation = Simulation::from_level(pillared_room());
+    simulation.app.add_plugin(RenderingPlugin);
+    simulation
+        .app
+        .world
+        .spawn((bevy::prelude::Transform::default(), MainCamera));
+    simulation.app.update();
+    let first_count = simulation.app.world.entities().len();
+
+    println!(
+        "{:>6} {:>9} {:>9} {:>10} {:>10}",
+        "moves", "revealed", "entities", "mean turn", "worst turn"
+    );
+    let mut moves = 0;
+    let mut dx = 1;
+    for lane in (2..SIZE - 2).step_by(LANE_GAP) {
+        let mut times = Vec::new();
+        let mut walk = |simulation: &mut Simulation, dx: i32, dy: i32, steps: usize| {
+            for _ in 0..steps {
+                let start = Instant::now();
+                simulation.act(PlayerAction::Move { dx, dy });
+                times.push(start.elapsed());
+            }
+        };
+        walk(&mut simulation, dx, 0, SIZE - 5);
+        if lane + LANE_GAP < SIZE - 2 {
+            walk(&mut simulation, 0, 1, LANE_GAP);
+        }
+        dx = -dx;
+        moves += times.len();
+        let world = &simulation.app.world;
+        println!(
+            "{:>6} {:>9} {:>9} {:>10.2?} {:>10.2?}",
+            moves,
+            world.resource::<Level>().revealed_tiles.len(),
+            world.entities().len(),
+            times.iter().sum::<Duration>() / times.len() as u32,
+            times.iter().max().unwrap(),
+        );
+    }
+    let last_count = simulation.app.world.entities().len();
+    assert_eq!(
+        first_count, last_count,
+        "exploring should not spawn more entities"
+    );
+}
@@ -0,0 +1,90 @@
+// Everything the player can do that changes the game.
+// Keys are turned into PlayerActions here, through the Keymap, and the game only reacts to
+// the actions, so recording the actions is enough to play a game back exactly.
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    direction::Direction,
+    keymap::{Action, Keymap},
+    replay::Replay,
+    turns::GameState,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PlayerAction {
+    /// Step to a neighbouring tile, attacking whatever is there. Diagonal steps can not cut
+    /// the corner of a wall.
+    Move {
+        dx: i32,
+        dy: i32,
+    },
+    /// Let a turn pass.
+    Wait,
+    PickUp,
+    /// Drop the item in this inventory slot.
+    Drop(usize),
+    Descend,
+    Ascend,
+    /// Close an open door next to the player.
+    CloseDoor,
+}
+
+impl PlayerAction {
+    /// A step in `direction`.
+    pub fn step(direction: Direction) -> Self {
+        let (dx, dy) = direction.delta();
+        PlayerAction::Move { dx, dy }
+    }
+}
+
+/// Systems that send PlayerActions. Systems handling them run after this.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ActionInput;
+
+// ============================
+// ========== PLUGIN ==========
+pub struct ActionPlugin;
+impl Plugin for ActionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PlayerAction>();
+    }
+}
+
+/// Turns key presses into PlayerActions.
+pub struct KeyboardPlugin;
+impl Plugin for KeyboardPlugin {
+    fn build(&self, app: &mut App) {
+        // Tests and other callers may bring their own keys
+        if !app.world.contains_resource::<Keymap>() {
+            app.insert_resource(Keymap::load());
+        }
+        app.add_system_set(
+            SystemSet::on_update(GameState::AwaitingInput)
+                .with_system(keyboard_actions.label(ActionInput)),
+        );
+    }
+}
+
+fn keyboard_actions(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    replay: Option<Res<Replay>>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    // The recording is in control
+    if replay.is_some() {
+        return;
+    }
+    let Some(action) = Action::ALL
+        .iter()
+        .filter(|action| keymap.just_pressed(**action, &keyboard_input))
+        .find_map(|action| action.player_action())
+    else {
+        return;
+    };
+    // The state change may run this system again in the same frame,
+    // so make sure the key press is only handled once.
+    keyboard_input.clear();
+    actions.send(action);
+}
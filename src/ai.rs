@@ -0,0 +1,379 @@
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use map_gen_2d::Point;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::{melee, CombatEvent, CombatStats},
+    components::{Mob, MobType, Position, POV},
+    direction::{is_next_to, neighbours},
+    doors::open_door,
+    enemies::Enemy,
+    health::Health,
+    map::{Level, TileChanged},
+    pathfinding::{a_star, DijkstraMap},
+    player::Player,
+    tiles::{TileType, TILESIZE},
+    turns::{Energy, GameState, TurnLabel, TurnScheduler, ACTION_COST},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AiProfile {
+    /// Runs away once its health drops to `flee_at` or lower.
+    Cowardly { flee_at: i32 },
+    /// Never retreats.
+    Berserk,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AiState {
+    // Has not seen the player, wanders around
+    Idle,
+    // Can see the player and is closing in
+    Hunting,
+    // Lost sight of the player, heading to where it was last seen
+    Searching,
+    // Trying to get away from the player
+    Fleeing,
+}
+
+/// Decides what an enemy does on its turn.
+#[derive(Component, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Brain {
+    pub profile: AiProfile,
+    pub state: AiState,
+    /// Where the player was when this enemy last saw them.
+    pub last_seen: Option<Position>,
+}
+
+impl Brain {
+    pub fn new(profile: AiProfile) -> Self {
+        Brain {
+            profile,
+            state: AiState::Idle,
+            last_seen: None,
+        }
+    }
+}
+
+// ============================
+// ========== PLUGIN ==========
+pub struct AiPlugin;
+impl Plugin for AiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::MonsterTurn).with_system(
+                take_turns
+                    .label(TurnLabel::MonsterAct)
+                    .after(TurnLabel::Schedule),
+            ),
+        );
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn take_turns(
+    mut scheduler: ResMut<TurnScheduler>,
+    mut map: ResMut<Level>,
+    mut player_query: Query<(Entity, &Position, &mut Health, &CombatStats), With<Player>>,
+    mut enemy_query: Query<
+        (
+            &mut Position,
+            &mut Transform,
+            &mut Energy,
+            &mut Brain,
+            &POV,
+            &Health,
+            &Mob,
+            &CombatStats,
+        ),
+        (With<Enemy>, Without<Player>),
+    >,
+    mut combat_events: EventWriter<CombatEvent>,
+    mut tile_changes: EventWriter<TileChanged>,
+) {
+    let Ok((player, player_position, mut player_health, player_stats)) =
+        player_query.get_single_mut()
+    else {
+        return;
+    };
+    let player_position = *player_position;
+    let mut occupied: HashSet<Position> = enemy_query.iter().map(|enemy| *enemy.0).collect();
+    occupied.insert(player_position);
+
+    while let Some(entity) = scheduler.next_monster(player) {
+        let Ok((mut position, mut transform, mut energy, mut brain, pov, health, mob, stats)) =
+            enemy_query.get_mut(entity)
+        else {
+            continue;
+        };
+        energy.0 -= ACTION_COST;
+        // Dead enemies are waiting to be despawned
+        if health.0 <= 0 {
+            continue;
+        }
+
+        let sees_player = pov
+            .visible_tiles
+            .iter()
+            .any(|tile| tile.0 == player_position);
+        if sees_player {
+            brain.last_seen = Some(player_position);
+            brain.state = match brain.profile {
+                AiProfile::Cowardly { flee_at } if health.0 <= flee_at => AiState::Fleeing,
+                _ => AiState::Hunting,
+            };
+        } else if brain.state == AiState::Hunting {
+            brain.state = AiState::Searching;
+        } else if brain.state == AiState::Fleeing {
+            // Got away, calm down
+            brain.state = AiState::Idle;
+            brain.last_seen = None;
+        }
+
+        let destination = match brain.state {
+            AiState::Hunting => {
+                if is_next_to(&map, &position, &player_position) {
+                    combat_events.send(melee(
+                        (entity, mob.0.clone(), stats),
+                        (player, MobType::PLAYER, player_stats),
+                        &mut player_health,
+                        &mut map.rng,
+                    ));
+                    None
+                } else {
+                    step_towards(&position, &player_position, &map, &occupied)
+                }
+            }
+            AiState::Fleeing => {
+                let step = step_away(&position, &player_position, &map, &occupied);
+                if step.is_none() && is_next_to(&map, &position, &player_position) {
+                    // Cornered, so fight back
+                    combat_events.send(melee(
+                        (entity, mob.0.clone(), stats),
+                        (player, MobType::PLAYER, player_stats),
+                        &mut player_health,
+                        &mut map.rng,
+                    ));
+                }
+                step
+            }
+            AiState::Searching => {
+                let last_seen = brain.last_seen.unwrap_or(*position);
+                let step = step_towards(&position, &last_seen, &map, &occupied);
+                if step.is_none() || step == Some(last_seen) {
+                    // Reached the spot (or can not get closer) and the player is gone
+                    brain.state = AiState::Idle;
+                    brain.last_seen = None;
+                }
+                step
+            }
+            AiState::Idle => wander(&position, &mut map, &occupied),
+        };
+
+        if let Some(destination) = destination {
+            if map.tile_at(destination.x, destination.y) == Some(TileType::CLOSEDDOOR) {
+                // Opening the door takes the whole turn
+                let point = Point::new(destination.x as usize, destination.y as usize);
+                open_door(&mut map, point, None);
+                tile_changes.send(TileChanged(point));
+                continue;
+            }
+            energy.0 -= map.move_cost(destination.x, destination.y) - ACTION_COST;
+            occupied.remove(&position);
+            occupied.insert(destination);
+            *position = destination;
+            transform.translation.x = destination.x as f32 * TILESIZE as f32;
+            transform.translation.y = destination.y as f32 * TILESIZE as f32;
+        }
+    }
+}
+
+// Returns the open neighbouring tiles of a position.
+fn open_neighbours(
+    position: &Position,
+    map: &Level,
+    occupied: &HashSet<Position>,
+) -> Vec<Position> {
+    neighbours(map, *position)
+        .map(|(_, next)| next)
+        .filter(|next| map.is_walkable(next.x, next.y) && !occupied.contains(next))
+        .collect()
+}
+
+// First step of the shortest path to the target, if there is one.
+fn step_towards(
+    position: &Position,
+    target: &Position,
+    map: &Level,
+    occupied: &HashSet<Position>,
+) -> Option<Position> {
+    a_star(map, *position, *target, occupied)?
+        .first()
+        .copied()
+        // Do not walk into whoever is standing on the target
+        .filter(|step| !occupied.contains(step))
+}
+
+// Step that leads away from the threat, if there is one. Ways out over slow ground
+// count as longer, just like paths towards the player do.
+fn step_away(
+    position: &Position,
+    threat: &Position,
+    map: &Level,
+    occupied: &HashSet<Position>,
+) -> Option<Position> {
+    let no_obstacles = HashSet::new();
+    DijkstraMap::new(map, &[*threat], &no_obstacles)
+        .flee(map, &no_obstacles)
+        .downhill(map, position, occupied)
+}
+
+// Random step, or stay put.
+fn wander(position: &Position, map: &mut Level, occupied: &HashSet<Position>) -> Option<Position> {
+    let neighbours = open_neighbours(position, map, occupied);
+    // Roll one extra option so idle enemies sometimes stand still
+    let roll = map.rng.gen_range(0..=neighbours.len());
+    neighbours.get(roll).copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::combat::Dice;
+
+    // Always hits, for one point of damage
+    const STATS: CombatStats = CombatStats {
+        attack: 100,
+        defense: 0,
+        armor: 0,
+        damage: Dice::new(1, 1, 0),
+    };
+
+    const ROOM: [&str; 5] = [
+        "############",
+        "#..........#",
+        "#..........#",
+        "#..........#",
+        "############",
+    ];
+
+    fn spawn(world: &mut World, profile: AiProfile, at: Position, health: i32) -> (Entity, Entity) {
+        world.insert_resource(Level::from_ascii(&ROOM));
+        world.init_resource::<TurnScheduler>();
+        world.init_resource::<Events<CombatEvent>>();
+        world.init_resource::<Events<TileChanged>>();
+        let player = world
+            .spawn((Player, Position { x: 1, y: 2 }, Health(1000), STATS))
+            .id();
+        let enemy = world
+            .spawn((
+                Enemy,
+                Mob(MobType::MONSTER("goblin".to_string())),
+                at,
+                Transform::default(),
+                Energy(0),
+                Brain::new(profile),
+                POV {
+                    visible_tiles: Vec::new(),
+                    newly_revealed_tiles: Vec::new(),
+                    range: 10,
+                },
+                Health(health),
+                STATS,
+            ))
+            .id();
+        (player, enemy)
+    }
+
+    // Gives the enemy one turn, seeing the player or not.
+    fn act(world: &mut World, player: Entity, enemy: Entity, sees_player: bool) {
+        let player_position = *world.get::<Position>(player).unwrap();
+        world.get_mut::<POV>(enemy).unwrap().visible_tiles = if sees_player {
+            vec![(player_position, 1)]
+        } else {
+            Vec::new()
+        };
+        world.get_mut::<Energy>(enemy).unwrap().0 = ACTION_COST;
+        world.resource_mut::<TurnScheduler>().ready.push_back(enemy);
+        SystemStage::single(take_turns).run(world);
+    }
+
+    fn brain(world: &World, enemy: Entity) -> (AiState, Option<Position>) {
+        let brain = world.get::<Brain>(enemy).unwrap();
+        (brain.state, brain.last_seen)
+    }
+
+    fn distance(world: &World, player: Entity, enemy: Entity) -> i32 {
+        let player = world.get::<Position>(player).unwrap();
+        world.get::<Position>(enemy).unwrap().distance(player)
+    }
+
+    #[test]
+    fn enemies_hunt_then_search_where_the_player_was_seen() {
+        let mut world = World::new();
+        let (player, goblin) = spawn(
+            &mut world,
+            AiProfile::Cowardly { flee_at: 1 },
+            Position { x: 8, y: 2 },
+            3,
+        );
+        act(&mut world, player, goblin, false);
+        assert_eq!(brain(&world, goblin), (AiState::Idle, None));
+
+        let seen_at = Position { x: 1, y: 2 };
+        act(&mut world, player, goblin, true);
+        assert_eq!(brain(&world, goblin), (AiState::Hunting, Some(seen_at)));
+
+        // The player slips away
+        *world.get_mut::<Position>(player).unwrap() = Position { x: 1, y: 3 };
+        act(&mut world, player, goblin, false);
+        assert_eq!(brain(&world, goblin), (AiState::Searching, Some(seen_at)));
+
+        // It gives up once it gets to where the player was
+        for _ in 0..10 {
+            if brain(&world, goblin).0 != AiState::Searching {
+                break;
+            }
+            act(&mut world, player, goblin, false);
+        }
+        assert_eq!(brain(&world, goblin), (AiState::Idle, None));
+        assert_eq!(*world.get::<Position>(goblin).unwrap(), seen_at);
+    }
+
+    #[test]
+    fn hurt_cowards_flee_until_out_of_sight() {
+        let mut world = World::new();
+        let (player, goblin) = spawn(
+            &mut world,
+            AiProfile::Cowardly { flee_at: 1 },
+            Position { x: 4, y: 2 },
+            1,
+        );
+        let start = distance(&world, player, goblin);
+        act(&mut world, player, goblin, true);
+        assert_eq!(brain(&world, goblin).0, AiState::Fleeing);
+        assert!(distance(&world, player, goblin) > start);
+
+        act(&mut world, player, goblin, false);
+        assert_eq!(brain(&world, goblin), (AiState::Idle, None));
+    }
+
+    #[test]
+    fn berserkers_never_retreat() {
+        let mut world = World::new();
+        let (player, orc) = spawn(&mut world, AiProfile::Berserk, Position { x: 6, y: 2 }, 1);
+        let mut last = distance(&world, player, orc);
+        for _ in 0..6 {
+            act(&mut world, player, orc, true);
+            assert_eq!(brain(&world, orc).0, AiState::Hunting);
+            let now = distance(&world, player, orc);
+            assert!(now <= last);
+            last = now;
+        }
+        assert_eq!(last, 1);
+        assert!(world.get::<Health>(player).unwrap().0 < 1000);
+    }
+}
@@ -0,0 +1,261 @@
+// Melee combat resolution.
+// An attack rolls d20 + attack against 10 + defense. A natural 20 is a critical hit
+// and rolls the damage dice twice. Armor is subtracted from the damage.
+use std::{fmt, str::FromStr};
+
+use bevy::prelude::*;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{components::MobType, health::Health};
+
+/// Dice written as count d sides + bonus, e.g. 1d6+1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Dice {
+    pub count: i32,
+    pub sides: i32,
+    pub bonus: i32,
+}
+
+impl Dice {
+    pub const fn new(count: i32, sides: i32, bonus: i32) -> Self {
+        Dice {
+            count,
+            sides,
+            bonus,
+        }
+    }
+
+    pub fn roll(&self, rng: &mut impl Rng) -> i32 {
+        (0..self.count)
+            .map(|_| rng.gen_range(1..=self.sides))
+            .sum::<i32>()
+            + self.bonus
+    }
+}
+
+impl FromStr for Dice {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid dice '{}', expected e.g. 1d6 or 2d4+1", text);
+        let (count, rest) = text.trim().split_once('d').ok_or_else(invalid)?;
+        let (sides, bonus) = match rest.find(['+', '-']) {
+            Some(sign) => (&rest[..sign], rest[sign..].trim_start_matches('+')),
+            None => (rest, "0"),
+        };
+        let count = count.parse().map_err(|_| invalid())?;
+        let sides = sides.parse().map_err(|_| invalid())?;
+        let bonus = bonus.parse().map_err(|_| invalid())?;
+        if count < 1 || sides < 1 {
+            return Err(invalid());
+        }
+        Ok(Dice::new(count, sides, bonus))
+    }
+}
+
+impl TryFrom<String> for Dice {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl fmt::Display for Dice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)?;
+        if self.bonus != 0 {
+            write!(f, "{:+}", self.bonus)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<Dice> for String {
+    fn from(dice: Dice) -> Self {
+        dice.to_string()
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CombatStats {
+    /// Added to the to-hit roll.
+    pub attack: i32,
+    /// Added to the number an attacker has to roll to hit.
+    pub defense: i32,
+    /// Subtracted from the damage of every hit.
+    pub armor: i32,
+    pub damage: Dice,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AttackOutcome {
+    Miss,
+    Hit { damage: i32, critical: bool },
+}
+
+/// Sent for every attack, hit or miss.
+#[derive(Clone, Debug)]
+pub struct CombatEvent {
+    pub attacker: Entity,
+    pub attacker_kind: MobType,
+    pub target: Entity,
+    pub target_kind: MobType,
+    pub outcome: AttackOutcome,
+}
+
+// ============================
+// ========== PLUGIN ==========
+pub struct CombatPlugin;
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CombatEvent>()
+            .add_system_to_stage(CoreStage::PostUpdate, log_combat_events);
+    }
+}
+
+/// Rolls an attack without applying it.
+pub fn roll_attack(
+    attacker: &CombatStats,
+    defender: &CombatStats,
+    rng: &mut impl Rng,
+) -> AttackOutcome {
+    let roll = rng.gen_range(1..=20);
+    let critical = roll == 20;
+    if !critical && roll + attacker.attack < 10 + defender.defense {
+        return AttackOutcome::Miss;
+    }
+    let mut damage = attacker.damage.roll(rng);
+    if critical {
+        damage += attacker.damage.roll(rng);
+    }
+    AttackOutcome::Hit {
+        damage: (damage - defender.armor).max(0),
+        critical,
+    }
+}
+
+/// Resolves a melee attack and applies the damage. Player and enemies both attack through here.
+pub fn melee(
+    attacker: (Entity, MobType, &CombatStats),
+    target: (Entity, MobType, &CombatStats),
+    target_health: &mut Health,
+    rng: &mut impl Rng,
+) -> CombatEvent {
+    let outcome = roll_attack(attacker.2, target.2, rng);
+    if let AttackOutcome::Hit { damage, .. } = outcome {
+        target_health.0 -= damage;
+    }
+    CombatEvent {
+        attacker: attacker.0,
+        attacker_kind: attacker.1,
+        target: target.0,
+        target_kind: target.1,
+        outcome,
+    }
+}
+
+fn log_combat_events(mut combat_events: EventReader<CombatEvent>) {
+    for event in combat_events.iter() {
+        debug!(
+            "{} {:?} attacks {} {:?}: {:?}",
+            event.attacker_kind.name(),
+            event.attacker,
+            event.target_kind.name(),
+            event.target,
+            event.outcome
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(attack: i32, defense: i32, armor: i32, damage: Dice) -> CombatStats {
+        CombatStats {
+            attack,
+            defense,
+            armor,
+            damage,
+        }
+    }
+
+    #[test]
+    fn only_a_natural_20_gets_past_a_high_defense() {
+        let attacker = stats(0, 0, 0, Dice::new(1, 1, 0));
+        let defender = stats(0, 100, 0, Dice::new(1, 1, 0));
+        let mut rng = StdRng::seed_from_u64(3);
+        let outcomes: Vec<AttackOutcome> = (0..200)
+            .map(|_| roll_attack(&attacker, &defender, &mut rng))
+            .collect();
+        assert!(outcomes.contains(&AttackOutcome::Miss));
+        // Criticals roll the damage twice
+        let critical = AttackOutcome::Hit {
+            damage: 2,
+            critical: true,
+        };
+        assert!(outcomes.contains(&critical));
+        assert!(outcomes
+            .iter()
+            .all(|outcome| *outcome == AttackOutcome::Miss || *outcome == critical));
+    }
+
+    #[test]
+    fn armor_never_heals() {
+        let attacker = stats(100, 0, 0, Dice::new(1, 4, 0));
+        let defender = stats(0, 0, 10, Dice::new(1, 1, 0));
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut health = Health(5);
+        for _ in 0..200 {
+            let event = melee(
+                (Entity::from_raw(0), MobType::PLAYER, &attacker),
+                (Entity::from_raw(1), MobType::PLAYER, &defender),
+                &mut health,
+                &mut rng,
+            );
+            assert!(matches!(
+                event.outcome,
+                AttackOutcome::Hit { damage: 0, .. }
+            ));
+        }
+        assert_eq!(health.0, 5);
+    }
+
+    #[test]
+    fn the_same_seed_fights_the_same() {
+        let attacker = stats(2, 1, 1, Dice::new(1, 8, 1));
+        let defender = stats(1, 2, 1, Dice::new(1, 6, 0));
+        let fight = |seed: u64| -> (Vec<AttackOutcome>, i32) {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut health = Health(1000);
+            let outcomes = (0..100)
+                .map(|_| {
+                    let event = melee(
+                        (Entity::from_raw(0), MobType::PLAYER, &attacker),
+                        (Entity::from_raw(1), MobType::PLAYER, &defender),
+                        &mut health,
+                        &mut rng,
+                    );
+                    event.outcome
+                })
+                .collect();
+            (outcomes, health.0)
+        };
+        assert_eq!(fight(9), fight(9));
+        assert_ne!(fight(9), fight(10));
+    }
+
+    #[test]
+    fn parse_dice() {
+        assert_eq!("1d6".parse(), Ok(Dice::new(1, 6, 0)));
+        assert_eq!("2d4+1".parse(), Ok(Dice::new(2, 4, 1)));
+        assert_eq!(" 1d8-2 ".parse(), Ok(Dice::new(1, 8, -2)));
+        for bad in ["", "d6", "1d", "0d6", "1x6", "1d6+"] {
+            assert!(bad.parse::<Dice>().is_err(), "'{}' was read as dice", bad);
+        }
+        assert_eq!(Dice::new(2, 4, -1).to_string(), "2d4-1");
+    }
+}
@@ -1,35 +1,49 @@
 use bevy::prelude::*;
-#[derive(Component, Copy, Clone, PartialEq)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Component, Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
 }
-#[derive(Component)]
-pub struct Collision;
 
+impl Position {
+    /// Number of steps between two positions, diagonal steps included.
+    pub fn distance(&self, other: &Position) -> i32 {
+        (self.x - other.x).abs().max((self.y - other.y).abs())
+    }
+}
 #[derive(Component)]
 pub struct MainCamera;
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Component)]
 pub struct POV {
-    // Let the i32 represent light level
-    pub visible_tiles : Vec<(Position, i32)>,
+    // With how many steps away each tile is
+    pub visible_tiles: Vec<(Position, i32)>,
     // Represents the tiles that are revealed on the last move
-    pub newly_revealed_tiles : Vec<Position>,
-    pub range : i32,
+    pub newly_revealed_tiles: Vec<Position>,
+    pub range: i32,
 }
 
 /// Represents player or enemy.
 #[derive(Component)]
 pub struct Mob(pub MobType);
 
-/// Represents a lit tile
-#[derive(Component)]
-pub struct LitTile;
-
-#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum MobType {
     PLAYER,
-    GOBLIN,
-    ORC
-}
\ No newline at end of file
+    /// Named after its MonsterTemplate.
+    MONSTER(String),
+}
+
+impl MobType {
+    /// Lowercase name used in text shown to the player.
+    pub fn name(&self) -> &str {
+        match self {
+            MobType::PLAYER => "player",
+            MobType::MONSTER(name) => name,
+        }
+    }
+}
@@ -0,0 +1,181 @@
+// The eight directions to a neighbouring tile, and which steps are allowed.
+// A diagonal step can not cut the corner of a wall or squeeze between two walls, and doors
+// are only gone through straight on. The player, monsters and pathfinding all step by the
+// same rule, so monsters never take a path the player could not.
+use serde::{Deserialize, Serialize};
+
+use crate::{components::Position, map::Level, tiles::TileType};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Direction {
+    North,
+    NorthEast,
+    East,
+    SouthEast,
+    South,
+    SouthWest,
+    West,
+    NorthWest,
+}
+
+impl Direction {
+    /// Straight steps first, so ties between equally good steps go to those.
+    pub const ALL: [Direction; 8] = [
+        Direction::North,
+        Direction::West,
+        Direction::South,
+        Direction::East,
+        Direction::NorthWest,
+        Direction::SouthWest,
+        Direction::SouthEast,
+        Direction::NorthEast,
+    ];
+
+    /// Change in x and y of a step this way. North is up the screen, towards higher y.
+    pub fn delta(&self) -> (i32, i32) {
+        match self {
+            Direction::North => (0, 1),
+            Direction::NorthEast => (1, 1),
+            Direction::East => (1, 0),
+            Direction::SouthEast => (1, -1),
+            Direction::South => (0, -1),
+            Direction::SouthWest => (-1, -1),
+            Direction::West => (-1, 0),
+            Direction::NorthWest => (-1, 1),
+        }
+    }
+
+    /// The direction of a step by (dx, dy), if it is a step to a neighbouring tile.
+    pub fn from_delta(dx: i32, dy: i32) -> Option<Direction> {
+        Direction::ALL
+            .into_iter()
+            .find(|direction| direction.delta() == (dx, dy))
+    }
+
+    /// The direction from one position to a neighbouring one.
+    pub fn between(from: &Position, to: &Position) -> Option<Direction> {
+        Direction::from_delta(to.x - from.x, to.y - from.y)
+    }
+
+    pub fn is_diagonal(&self) -> bool {
+        let (dx, dy) = self.delta();
+        dx != 0 && dy != 0
+    }
+
+    /// Where a step this way from `position` ends up.
+    pub fn step(&self, position: &Position) -> Position {
+        let (dx, dy) = self.delta();
+        Position {
+            x: position.x + dx,
+            y: position.y + dy,
+        }
+    }
+}
+
+// Tiles a diagonal step can not go past, into or out of.
+fn blocks_diagonal(tile: Option<TileType>) -> bool {
+    matches!(
+        tile,
+        None | Some(
+            TileType::WALL | TileType::OPENDOOR | TileType::CLOSEDDOOR | TileType::LOCKEDDOOR
+        )
+    )
+}
+
+/// Returns true if the way from `from` to its neighbour in `direction` is not cut off by a
+/// corner. Does not check whether the tile stepped onto can be walked on.
+pub fn can_step(level: &Level, from: &Position, direction: Direction) -> bool {
+    if !direction.is_diagonal() {
+        return true;
+    }
+    let (dx, dy) = direction.delta();
+    let to = direction.step(from);
+    [
+        (from.x, from.y),
+        (to.x, to.y),
+        (from.x + dx, from.y),
+        (from.x, from.y + dy),
+    ]
+    .into_iter()
+    .all(|(x, y)| !blocks_diagonal(level.tile_at(x, y)))
+}
+
+/// Returns true if `to` is one step from `from` that is not cut off by a corner, e.g. to
+/// attack whoever is there.
+pub fn is_next_to(level: &Level, from: &Position, to: &Position) -> bool {
+    Direction::between(from, to).is_some_and(|direction| can_step(level, from, direction))
+}
+
+/// The neighbours of `position` that a step can get to, with the direction of the step.
+pub fn neighbours(
+    level: &Level,
+    position: Position,
+) -> impl Iterator<Item = (Direction, Position)> + '_ {
+    Direction::ALL
+        .into_iter()
+        .filter(move |direction| can_step(level, &position, *direction))
+        .map(move |direction| (direction, direction.step(&position)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pos(x: i32, y: i32) -> Position {
+        Position { x, y }
+    }
+
+    #[test]
+    fn deltas_round_trip() {
+        for direction in Direction::ALL {
+            let (dx, dy) = direction.delta();
+            assert_eq!(Direction::from_delta(dx, dy), Some(direction));
+        }
+        assert_eq!(Direction::from_delta(0, 0), None);
+        assert_eq!(Direction::from_delta(2, 0), None);
+    }
+
+    #[test]
+    fn diagonals_do_not_cut_corners() {
+        let level = Level::from_ascii(&[
+            "######", //
+            "#....#", //
+            "#.#..#", //
+            "#....#", //
+            "######",
+        ]);
+        // Open floor all round
+        assert!(can_step(&level, &pos(3, 2), Direction::NorthEast));
+        // Past the corner of the pillar at (2, 2)
+        assert!(!can_step(&level, &pos(1, 2), Direction::NorthEast));
+        assert!(!can_step(&level, &pos(3, 2), Direction::SouthWest));
+        assert!(!can_step(&level, &pos(2, 1), Direction::NorthEast));
+        // Straight steps are never cut off
+        assert!(can_step(&level, &pos(1, 2), Direction::East));
+    }
+
+    #[test]
+    fn doors_are_gone_through_straight_on() {
+        let level = Level::from_ascii(&[
+            "#####", //
+            "#...#", //
+            "##'##", //
+            "#...#", //
+            "#####",
+        ]);
+        let door = pos(2, 2);
+        assert!(!can_step(&level, &pos(1, 1), Direction::NorthEast));
+        assert!(!can_step(&level, &door, Direction::NorthEast));
+        assert!(can_step(&level, &door, Direction::North));
+        let around: Vec<Direction> = neighbours(&level, door).map(|(d, _)| d).collect();
+        assert_eq!(
+            around,
+            [
+                Direction::North,
+                Direction::West,
+                Direction::South,
+                Direction::East
+            ]
+        );
+    }
+}
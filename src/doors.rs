@@ -0,0 +1,110 @@
+// Doors between corridors and rooms.
+// A door is just a tile. Closed and locked doors block movement and sight. Bumping into a
+// closed door opens it, and a locked door opens if the player has a key to use up.
+use bevy::prelude::*;
+use map_gen_2d::Point;
+
+use crate::{
+    actions::{ActionInput, PlayerAction},
+    components::{Mob, Position},
+    direction::neighbours,
+    items::{Inventory, Item, ItemType},
+    map::{Level, TileChanged},
+    messages::{GameMessage, MessageCategory},
+    player::Player,
+    tiles::{TileType, TileTypeMap},
+    turns::{end_player_turn, Energy, GameState},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DoorOutcome {
+    Opened,
+    /// Opened with a key.
+    Unlocked,
+    Locked,
+}
+
+// ============================
+// ========== PLUGIN ==========
+pub struct DoorPlugin;
+impl Plugin for DoorPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(
+            SystemSet::on_update(GameState::AwaitingInput)
+                .with_system(close_door.after(ActionInput)),
+        );
+    }
+}
+
+/// Opens the closed or locked door at `point`. A locked door uses up a key from `inventory`,
+/// and stays shut if there is none.
+pub fn open_door(map: &mut Level, point: Point, inventory: Option<&mut Inventory>) -> DoorOutcome {
+    let mut outcome = DoorOutcome::Opened;
+    if map.tiles.get(&point).map(|tile| tile.0) == Some(TileType::LOCKEDDOOR) {
+        let Some(inventory) = inventory else {
+            return DoorOutcome::Locked;
+        };
+        let Some(key) = inventory
+            .items
+            .iter()
+            .position(|item| *item == ItemType::KEY)
+        else {
+            return DoorOutcome::Locked;
+        };
+        inventory.items.remove(key);
+        outcome = DoorOutcome::Unlocked;
+    }
+    map.tiles.insert(point, TileTypeMap(TileType::OPENDOOR));
+    outcome
+}
+
+#[allow(clippy::too_many_arguments)]
+fn close_door(
+    mut actions: EventReader<PlayerAction>,
+    mut state: ResMut<State<GameState>>,
+    mut map: ResMut<Level>,
+    mut player_query: Query<(&Position, &mut Energy), With<Player>>,
+    mob_query: Query<&Position, With<Mob>>,
+    item_query: Query<&Position, With<Item>>,
+    mut messages: EventWriter<GameMessage>,
+    mut tile_changes: EventWriter<TileChanged>,
+) {
+    if !actions
+        .iter()
+        .any(|action| *action == PlayerAction::CloseDoor)
+    {
+        return;
+    }
+    for (position, mut energy) in player_query.iter_mut() {
+        // Doors are only closed straight on, like they are gone through
+        let doors: Vec<Position> = neighbours(&map, *position)
+            .map(|(_, next)| next)
+            .filter(|next| map.tile_at(next.x, next.y) == Some(TileType::OPENDOOR))
+            .collect();
+        if doors.is_empty() {
+            messages.send(GameMessage::new(
+                "There is no open door next to you.",
+                MessageCategory::Info,
+            ));
+            continue;
+        }
+        // Nothing can be standing or lying in the doorway
+        let Some(door) = doors.into_iter().find(|door| {
+            !mob_query.iter().any(|mob| mob == door) && !item_query.iter().any(|item| item == door)
+        }) else {
+            messages.send(GameMessage::new(
+                "Something is in the way.",
+                MessageCategory::Info,
+            ));
+            continue;
+        };
+        let point = Point::new(door.x as usize, door.y as usize);
+        map.tiles.insert(point, TileTypeMap(TileType::CLOSEDDOOR));
+        tile_changes.send(TileChanged(point));
+        messages.send(GameMessage::new(
+            "You close the door.",
+            MessageCategory::Info,
+        ));
+        end_player_turn(&mut energy, &mut state);
+    }
+}
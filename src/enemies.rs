@@ -1,92 +1,175 @@
+// Monsters, built from the templates in assets/monsters.ron.
+// Every monster is the same bundle. What kind it is only changes the numbers in it, so new
+// monsters can be added to the file without touching the code.
+use std::fs;
+
 use bevy::prelude::*;
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ai::{AiProfile, Brain},
+    combat::CombatStats,
+    components::{Mob, MobType, Position, POV},
+    health::Health,
+    lighting::LightSource,
+    map::Level,
+    player::SpawnPlayer,
+    resources::{asset_path, GlyphAssets},
+    tiles::TILESIZE,
+    turns::{Energy, Speed, NORMAL_SPEED},
+};
 
-use crate::{components::{Position, POV, Mob, MobType}, tiles::TILESIZE, resources::GlyphAssets, map::Level, health::Health};
+/// Copy of assets/monsters.ron built into the game, for when the file is not around.
+const BUILTIN_MONSTERS: &str = include_str!("../assets/monsters.ron");
 
 #[derive(Component, Default, Debug)]
 pub struct Enemy;
 
-#[derive(Component, Default, Debug)]
-pub struct Orc;
+/// Spawns the monsters of the first level at startup.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SpawnEnemies;
 
-#[derive(Bundle)]
-pub struct OrcBundle {
-    #[bundle]
-    sprite_sheet_bundle: SpriteSheetBundle,
-    orc: Orc,
-    enemy : Enemy,
-    position: Position,
-    pov: POV,
-    mob: Mob,
-    health : Health
+/// One kind of monster, as written in assets/monsters.ron.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MonsterTemplate {
+    /// Lowercase name used in text shown to the player.
+    pub name: String,
+    /// Character drawn on the map.
+    pub glyph: char,
+    /// Red, green and blue from 0 to 1.
+    pub color: (f32, f32, f32),
+    pub health: i32,
+    /// Energy gained per tick.
+    #[serde(default = "normal_speed")]
+    pub speed: i32,
+    /// How many tiles away it can see.
+    pub sight: i32,
+    pub stats: CombatStats,
+    pub ai: AiProfile,
+    /// Shallowest level it shows up on.
+    #[serde(default = "first_level")]
+    pub min_depth: u32,
+    /// Deepest level it shows up on, no limit if None.
+    #[serde(default)]
+    pub max_depth: Option<u32>,
+    /// How often it is picked compared to the other monsters allowed on a level.
+    pub weight: u32,
+    /// Added to the weight for every level deeper than `min_depth`.
+    #[serde(default)]
+    pub depth_weight: u32,
+    /// Light it carries around, if any.
+    #[serde(default)]
+    pub light: Option<LightSource>,
 }
 
-impl OrcBundle {
-    pub fn new(location: (i32, i32), texture_atlas_handle: Handle<TextureAtlas>) -> OrcBundle {
-        OrcBundle {
-            sprite_sheet_bundle: SpriteSheetBundle {
-                texture_atlas: texture_atlas_handle,
-                transform: Transform {
-                    translation: Vec3 {
-                        x: location.0 as f32 * TILESIZE as f32,
-                        y: location.1 as f32 * TILESIZE as f32,
-                        z: 0.0,
-                    },
-                    scale: Vec3 {
-                        x: TILESIZE as f32 / 8.0,
-                        y: TILESIZE as f32 / 8.0,
-                        z: TILESIZE as f32 / 8.0,
-                    },
-                    ..default()
-                },
-                sprite: TextureAtlasSprite {
-                    index: 111,
-                    color : Color::RED,
-                    ..default()
-                },
-                ..default()
-            },
-            orc: Orc,
-            enemy : Enemy,
-            position: Position {
-                x: location.0,
-                y: location.1,
-            },
-            pov: POV {
-                visible_tiles: Vec::new(),
-                newly_revealed_tiles: Vec::new(),
-                range: 8,
-            },
-            mob: Mob(MobType::ORC),
-            health : Health(4),
+fn normal_speed() -> i32 {
+    NORMAL_SPEED
+}
+
+fn first_level() -> u32 {
+    1
+}
+
+impl MonsterTemplate {
+    /// Chance of being picked on a level at `depth`, 0 if it does not show up there.
+    pub fn weight_at(&self, depth: u32) -> u32 {
+        if depth < self.min_depth || self.max_depth.is_some_and(|max| depth > max) {
+            return 0;
         }
+        self.weight + self.depth_weight * (depth - self.min_depth)
     }
 }
 
-#[derive(Component, Default, Debug)]
-pub struct Goblin;
+/// Every kind of monster in the game.
+#[derive(Resource, Clone, Debug)]
+pub struct Bestiary {
+    pub monsters: Vec<MonsterTemplate>,
+}
+
+impl Bestiary {
+    pub fn parse(text: &str) -> Result<Bestiary, String> {
+        let monsters: Vec<MonsterTemplate> = ron::from_str(text).map_err(|err| err.to_string())?;
+        for (i, monster) in monsters.iter().enumerate() {
+            if monsters[..i].iter().any(|other| other.name == monster.name) {
+                return Err(format!("there are two monsters named '{}'", monster.name));
+            }
+            if !monster.glyph.is_ascii() {
+                return Err(format!("the glyph of '{}' is not ASCII", monster.name));
+            }
+            if monster.health < 1 {
+                return Err(format!("'{}' needs at least 1 health", monster.name));
+            }
+        }
+        Ok(Bestiary { monsters })
+    }
+
+    /// Reads assets/monsters.ron, or uses the built in copy if there is no such file or it is
+    /// broken.
+    pub fn load() -> Bestiary {
+        let path = asset_path("monsters.ron");
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Bestiary::builtin();
+        };
+        match Bestiary::parse(&text) {
+            Ok(bestiary) => bestiary,
+            Err(err) => {
+                warn!(
+                    "Could not read {}, using the built in monsters: {}",
+                    path.display(),
+                    err
+                );
+                Bestiary::builtin()
+            }
+        }
+    }
+
+    pub fn builtin() -> Bestiary {
+        Bestiary::parse(BUILTIN_MONSTERS).unwrap()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&MonsterTemplate> {
+        self.monsters.iter().find(|monster| monster.name == name)
+    }
+
+    /// Picks a monster for a level at `depth`. None if nothing lives that deep.
+    pub fn choose(&self, depth: u32, rng: &mut impl Rng) -> Option<&MonsterTemplate> {
+        self.monsters
+            .choose_weighted(rng, |monster| monster.weight_at(depth))
+            .ok()
+    }
+}
 
 #[derive(Bundle)]
-pub struct GoblinBundle {
+pub struct MonsterBundle {
     #[bundle]
     sprite_sheet_bundle: SpriteSheetBundle,
-    goblin: Goblin,
-    enemy : Enemy,
+    enemy: Enemy,
     position: Position,
     pov: POV,
     mob: Mob,
-    health : Health
+    health: Health,
+    energy: Energy,
+    speed: Speed,
+    brain: Brain,
+    stats: CombatStats,
 }
 
-impl GoblinBundle {
-    pub fn new(location: (i32, i32), texture_atlas_handle: Handle<TextureAtlas>) -> GoblinBundle {
-        GoblinBundle {
+impl MonsterBundle {
+    pub fn new(
+        template: &MonsterTemplate,
+        location: (i32, i32),
+        texture_atlas_handle: Handle<TextureAtlas>,
+    ) -> MonsterBundle {
+        let (r, g, b) = template.color;
+        MonsterBundle {
             sprite_sheet_bundle: SpriteSheetBundle {
                 texture_atlas: texture_atlas_handle,
                 transform: Transform {
                     translation: Vec3 {
                         x: location.0 as f32 * TILESIZE as f32,
                         y: location.1 as f32 * TILESIZE as f32,
-                        z: 0.0,
+                        z: 1.0,
                     },
                     scale: Vec3 {
                         x: TILESIZE as f32 / 8.0,
@@ -96,14 +179,13 @@ impl GoblinBundle {
                     ..default()
                 },
                 sprite: TextureAtlasSprite {
-                    index: 103,
-                    color : Color::RED,
+                    index: template.glyph as usize,
+                    color: Color::rgb(r, g, b),
                     ..default()
                 },
                 ..default()
             },
-            goblin: Goblin,
-            enemy : Enemy,
+            enemy: Enemy,
             position: Position {
                 x: location.0,
                 y: location.1,
@@ -111,27 +193,125 @@ impl GoblinBundle {
             pov: POV {
                 visible_tiles: Vec::new(),
                 newly_revealed_tiles: Vec::new(),
-                range: 8,
+                range: template.sight,
             },
-            mob: Mob(MobType::GOBLIN),
-            health : Health(3),
+            mob: Mob(MobType::MONSTER(template.name.clone())),
+            health: Health(template.health),
+            energy: Energy(0),
+            speed: Speed(template.speed),
+            brain: Brain::new(template.ai),
+            stats: template.stats,
         }
     }
 }
 
+// ============================
+// ========== PLUGIN ==========
 pub struct EnemyPlugin;
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup);
+        // Tests may bring their own monsters
+        if !app.world.contains_resource::<Bestiary>() {
+            app.insert_resource(Bestiary::load());
+        }
+        app.add_startup_system(setup.label(SpawnEnemies).after(SpawnPlayer));
     }
 }
 
-fn setup(mut commands: Commands, atlas: Res<GlyphAssets>, map: Res<Level>) {
+fn setup(
+    mut commands: Commands,
+    atlas: Res<GlyphAssets>,
+    bestiary: Res<Bestiary>,
+    map: Res<Level>,
+) {
+    spawn_enemies(&mut commands, &atlas, &bestiary, &map);
+}
+
+pub fn spawn_enemies(
+    commands: &mut Commands,
+    atlas: &GlyphAssets,
+    bestiary: &Bestiary,
+    map: &Level,
+) {
     for mob in map.mobs.iter() {
-        if mob.1 == MobType::ORC {
-            commands.spawn(OrcBundle::new((mob.0.x.try_into().unwrap(), mob.0.y.try_into().unwrap()), atlas.atlas.clone()));
-        } else if mob.1 == MobType::GOBLIN {
-            commands.spawn(GoblinBundle::new((mob.0.x.try_into().unwrap(), mob.0.y.try_into().unwrap()), atlas.atlas.clone()));
-        }
+        spawn_enemy(
+            commands,
+            atlas,
+            bestiary,
+            &mob.1,
+            (mob.0.x.try_into().unwrap(), mob.0.y.try_into().unwrap()),
+        );
+    }
+}
+
+/// Spawns one enemy of the given kind. Returns None for the player and unknown monsters.
+pub fn spawn_enemy(
+    commands: &mut Commands,
+    atlas: &GlyphAssets,
+    bestiary: &Bestiary,
+    kind: &MobType,
+    location: (i32, i32),
+) -> Option<Entity> {
+    let MobType::MONSTER(name) = kind else {
+        return None;
     };
-}
\ No newline at end of file
+    let Some(template) = bestiary.get(name) else {
+        warn!("There is no monster called '{}' in the monster file", name);
+        return None;
+    };
+    let mut monster = commands.spawn(MonsterBundle::new(template, location, atlas.atlas.clone()));
+    if let Some(light) = template.light {
+        monster.insert(light);
+    }
+    Some(monster.id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_chacha::ChaCha12Rng;
+
+    #[test]
+    fn builtin_monsters_are_valid() {
+        let bestiary = Bestiary::builtin();
+        assert!(bestiary.get("goblin").is_some());
+        assert!(bestiary.get("orc").is_some());
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        for depth in 1..20 {
+            assert!(bestiary.choose(depth, &mut rng).is_some());
+        }
+    }
+
+    #[test]
+    fn monsters_only_show_up_at_their_depths() {
+        let text = r#"[
+            (name: "rat", glyph: 'r', color: (0.5, 0.5, 0.5), health: 1, sight: 4,
+             stats: (attack: 0, defense: 0, armor: 0, damage: "1d2"), ai: Berserk,
+             max_depth: Some(2), weight: 1),
+            (name: "troll", glyph: 'T', color: (0.0, 1.0, 0.0), health: 30, speed: 8, sight: 8,
+             stats: (attack: 4, defense: 2, armor: 2, damage: "2d6+1"), ai: Berserk,
+             min_depth: 4, weight: 1, depth_weight: 2),
+        ]"#;
+        let bestiary = Bestiary::parse(text).unwrap();
+        let troll = bestiary.get("troll").unwrap();
+        assert_eq!(troll.speed, 8);
+        assert_eq!(
+            (troll.weight_at(3), troll.weight_at(4), troll.weight_at(6)),
+            (0, 1, 5)
+        );
+        let mut rng = ChaCha12Rng::seed_from_u64(0);
+        for _ in 0..20 {
+            assert_eq!(bestiary.choose(1, &mut rng).unwrap().name, "rat");
+            assert_eq!(bestiary.choose(5, &mut rng).unwrap().name, "troll");
+        }
+        assert!(bestiary.choose(3, &mut rng).is_none());
+    }
+
+    #[test]
+    fn duplicate_names_are_refused() {
+        let monster = r#"(name: "rat", glyph: 'r', color: (0.5, 0.5, 0.5), health: 1, sight: 4,
+            stats: (attack: 0, defense: 0, armor: 0, damage: "1d2"), ai: Berserk, weight: 1)"#;
+        let text = format!("[{}, {}]", monster, monster);
+        assert!(Bestiary::parse(&text).is_err());
+    }
+}
@@ -0,0 +1,249 @@
+// Field of view with symmetric shadowcasting.
+// Does not depend on bevy so it can be tested and benchmarked on its own.
+// https://www.albertford.com/shadowcasting/
+use std::collections::HashSet;
+
+/// Returns every tile visible from `origin` within a circle of `radius`.
+/// Walls that are seen are included. If a tile A sees tile B, then B sees A.
+pub fn field_of_view(
+    origin: (i32, i32),
+    radius: i32,
+    blocks_sight: impl Fn(i32, i32) -> bool,
+) -> HashSet<(i32, i32)> {
+    let mut visible = HashSet::new();
+    visible.insert(origin);
+    for quadrant in [
+        Quadrant::North,
+        Quadrant::East,
+        Quadrant::South,
+        Quadrant::West,
+    ] {
+        let mut scanner = Scanner {
+            origin,
+            radius,
+            quadrant,
+            blocks_sight: &blocks_sight,
+            visible: &mut visible,
+        };
+        scanner.scan(Row {
+            depth: 1,
+            start_slope: Slope::new(-1, 1),
+            end_slope: Slope::new(1, 1),
+        });
+    }
+    visible
+}
+
+/// True if the tile at offset (dx, dy) is inside a circle of `radius`.
+pub fn in_radius(dx: i32, dy: i32, radius: i32) -> bool {
+    // The extra `radius` rounds the circle off so it does not have single tile nubs
+    dx * dx + dy * dy <= radius * radius + radius
+}
+
+#[derive(Clone, Copy)]
+enum Quadrant {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Quadrant {
+    // Converts (depth, column) within the quadrant to map coordinates
+    fn transform(&self, origin: (i32, i32), depth: i32, col: i32) -> (i32, i32) {
+        match self {
+            Quadrant::North => (origin.0 + col, origin.1 + depth),
+            Quadrant::South => (origin.0 + col, origin.1 - depth),
+            Quadrant::East => (origin.0 + depth, origin.1 + col),
+            Quadrant::West => (origin.0 - depth, origin.1 + col),
+        }
+    }
+}
+
+// Exact fraction, so rounding at tile edges is the same in every direction
+#[derive(Clone, Copy)]
+struct Slope {
+    num: i32,
+    den: i32,
+}
+
+impl Slope {
+    fn new(num: i32, den: i32) -> Self {
+        Slope { num, den }
+    }
+
+    // Slope through the near left corner of a tile
+    fn of_tile(depth: i32, col: i32) -> Self {
+        Slope::new(2 * col - 1, 2 * depth)
+    }
+}
+
+struct Row {
+    depth: i32,
+    start_slope: Slope,
+    end_slope: Slope,
+}
+
+impl Row {
+    fn min_col(&self) -> i32 {
+        // round_ties_up(depth * start_slope)
+        (2 * self.depth * self.start_slope.num + self.start_slope.den)
+            .div_euclid(2 * self.start_slope.den)
+    }
+
+    fn max_col(&self) -> i32 {
+        // round_ties_down(depth * end_slope)
+        -(-(2 * self.depth * self.end_slope.num - self.end_slope.den))
+            .div_euclid(2 * self.end_slope.den)
+    }
+
+    // Floor tiles are only seen if their center is inside the row's slopes
+    fn is_symmetric(&self, col: i32) -> bool {
+        col * self.start_slope.den >= self.depth * self.start_slope.num
+            && col * self.end_slope.den <= self.depth * self.end_slope.num
+    }
+
+    fn next(&self) -> Row {
+        Row {
+            depth: self.depth + 1,
+            start_slope: self.start_slope,
+            end_slope: self.end_slope,
+        }
+    }
+}
+
+struct Scanner<'a, F: Fn(i32, i32) -> bool> {
+    origin: (i32, i32),
+    radius: i32,
+    quadrant: Quadrant,
+    blocks_sight: &'a F,
+    visible: &'a mut HashSet<(i32, i32)>,
+}
+
+impl<'a, F: Fn(i32, i32) -> bool> Scanner<'a, F> {
+    fn is_wall(&self, depth: i32, col: i32) -> bool {
+        let (x, y) = self.quadrant.transform(self.origin, depth, col);
+        (self.blocks_sight)(x, y)
+    }
+
+    fn reveal(&mut self, depth: i32, col: i32) {
+        let (x, y) = self.quadrant.transform(self.origin, depth, col);
+        if in_radius(x - self.origin.0, y - self.origin.1, self.radius) {
+            self.visible.insert((x, y));
+        }
+    }
+
+    fn scan(&mut self, mut row: Row) {
+        if row.depth > self.radius {
+            return;
+        }
+        // None until the first tile of the row, then whether the previous tile was a wall
+        let mut previous_wall: Option<bool> = None;
+        for col in row.min_col()..=row.max_col() {
+            let wall = self.is_wall(row.depth, col);
+            if wall || row.is_symmetric(col) {
+                self.reveal(row.depth, col);
+            }
+            if previous_wall == Some(true) && !wall {
+                row.start_slope = Slope::of_tile(row.depth, col);
+            }
+            if previous_wall == Some(false) && wall {
+                let mut next_row = row.next();
+                next_row.end_slope = Slope::of_tile(row.depth, col);
+                self.scan(next_row);
+            }
+            previous_wall = Some(wall);
+        }
+        if previous_wall == Some(false) {
+            self.scan(row.next());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{map::Level, tiles::TileType};
+
+    fn blocks(level: &Level) -> impl Fn(i32, i32) -> bool + '_ {
+        |x, y| level.blocks_sight(x, y)
+    }
+
+    #[test]
+    fn open_room_is_a_circle() {
+        let rows = vec!["........................."; 25];
+        let level = Level::from_ascii(&rows);
+        let visible = field_of_view((12, 12), 5, blocks(&level));
+        for x in 0..25 {
+            for y in 0..25 {
+                assert_eq!(
+                    visible.contains(&(x, y)),
+                    in_radius(x - 12, y - 12, 5),
+                    "tile ({}, {})",
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn walls_are_seen_but_block_sight() {
+        let level = Level::from_ascii(&[
+            "#######", //
+            "#.....#", //
+            "#..#..#", //
+            "#.....#", //
+            "#######",
+        ]);
+        let visible = field_of_view((1, 2), 8, blocks(&level));
+        assert!(visible.contains(&(3, 2)));
+        assert!(!visible.contains(&(4, 2)));
+        assert!(!visible.contains(&(5, 2)));
+        assert!(visible.contains(&(0, 2)));
+        assert!(visible.contains(&(5, 1)));
+    }
+
+    #[test]
+    fn corridor_corner() {
+        let level = Level::from_ascii(&[
+            "#####", //
+            "#...#", //
+            "###.#", //
+            "###.#", //
+            "#####",
+        ]);
+        let visible = field_of_view((1, 1), 8, blocks(&level));
+        assert!(visible.contains(&(3, 1)));
+        assert!(!visible.contains(&(3, 3)));
+    }
+
+    #[test]
+    fn vision_is_symmetric() {
+        for seed in 0..2 {
+            let level = Level::generate(seed, 1);
+            let floors: Vec<(i32, i32)> = level
+                .tiles
+                .iter()
+                .filter(|tile| tile.1 .0 == TileType::FLOOR)
+                .map(|tile| (tile.0.x as i32, tile.0.y as i32))
+                .collect();
+            let views: Vec<HashSet<(i32, i32)>> = floors
+                .iter()
+                .map(|floor| field_of_view(*floor, 8, blocks(&level)))
+                .collect();
+            for (a, view) in floors.iter().zip(views.iter()) {
+                for (b, other_view) in floors.iter().zip(views.iter()) {
+                    assert_eq!(
+                        view.contains(b),
+                        other_view.contains(a),
+                        "seed {}: {:?} and {:?}",
+                        seed,
+                        a,
+                        b
+                    );
+                }
+            }
+        }
+    }
+}
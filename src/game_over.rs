@@ -0,0 +1,245 @@
+use bevy::{ecs::system::SystemState, prelude::*};
+use rand::prelude::*;
+
+use crate::{
+    components::{MainCamera, Mob},
+    enemies::{spawn_enemies, Bestiary},
+    health::LastHitBy,
+    items::{spawn_items, Item},
+    keymap::{Action, Keymap},
+    lighting::{spawn_lights, FixedLight},
+    map::{Dungeon, Level},
+    messages::{with_article, MessageLog},
+    player::{spawn_player, Player},
+    replay::{Replay, ReplayText},
+    resources::GlyphAssets,
+    save::ResumedGame,
+    terrain::Burning,
+    tiles::Tile,
+    turns::{GameState, TurnScheduler},
+    ui::{spawn_panel, spawn_text, GLYPH_SIZE},
+    vaults::Vaults,
+};
+
+/// Root of the game over screen.
+#[derive(Component)]
+pub struct GameOverScreen;
+
+// ============================
+// ========== PLUGIN ==========
+pub struct GameOverPlugin;
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(show_screen))
+            .add_system_set(SystemSet::on_update(GameState::GameOver).with_system(restart))
+            .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(hide_screen));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn show_screen(
+    mut commands: Commands,
+    atlas: Res<GlyphAssets>,
+    map: Res<Level>,
+    dungeon: Res<Dungeon>,
+    scheduler: Res<TurnScheduler>,
+    keymap: Res<Keymap>,
+    camera_query: Query<Entity, With<MainCamera>>,
+    player_query: Query<(Option<&LastHitBy>, Option<&Burning>), With<Player>>,
+) {
+    let cause = match player_query.get_single() {
+        Ok((Some(LastHitBy(Some(killer))), _)) => {
+            format!("Killed by {}", with_article(killer.name()))
+        }
+        Ok((_, Some(_))) => "Burned to death".to_string(),
+        _ => "Died of unknown causes".to_string(),
+    };
+    let lines = [
+        ("YOU DIED".to_string(), Color::RED),
+        (cause, Color::WHITE),
+        (format!("Depth {}", map.depth), Color::WHITE),
+        (format!("Survived {} turns", scheduler.turn), Color::WHITE),
+        (format!("Seed {}", dungeon.seed), Color::GRAY),
+        (String::new(), Color::WHITE),
+        (
+            format!("Press {} to restart", keymap.describe(Action::Restart)),
+            Color::GRAY,
+        ),
+    ];
+    let camera = camera_query.single();
+    commands.entity(camera).with_children(|parent| {
+        parent
+            .spawn((
+                GameOverScreen,
+                SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.0, -0.5)),
+            ))
+            .with_children(|parent| {
+                spawn_panel(
+                    parent,
+                    &atlas,
+                    Color::rgba(0.0, 0.0, 0.0, 0.85),
+                    Vec2::ZERO,
+                    Vec2::new(GLYPH_SIZE * 32.0, GLYPH_SIZE * 14.0),
+                );
+                for (i, (text, color)) in lines.into_iter().enumerate() {
+                    // Center each line horizontally
+                    let x = -(text.len() as f32 * GLYPH_SIZE) / 2.0;
+                    let y = GLYPH_SIZE * (4.0 - i as f32 * 1.5);
+                    spawn_text(parent, text, color, Vec2::new(x, y));
+                }
+            });
+    });
+}
+
+fn hide_screen(mut commands: Commands, screen_query: Query<Entity, With<GameOverScreen>>) {
+    for screen in screen_query.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+fn restart(
+    mut commands: Commands,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+) {
+    if !keymap.just_pressed(Action::Restart, &keyboard_input) {
+        return;
+    }
+    keyboard_input.clear();
+    commands.add(new_game);
+}
+
+type NewGameParams<'w, 's> = (
+    Commands<'w, 's>,
+    ResMut<'w, Level>,
+    ResMut<'w, Dungeon>,
+    Res<'w, GlyphAssets>,
+    Res<'w, Bestiary>,
+    Res<'w, Vaults>,
+    Query<
+        'w,
+        's,
+        Entity,
+        Or<(
+            With<Mob>,
+            With<Tile>,
+            With<Item>,
+            With<FixedLight>,
+            With<ReplayText>,
+        )>,
+    >,
+);
+
+/// Throws away the game in `world` and starts a new one on a fresh dungeon, seeded from the
+/// old one.
+pub fn new_game(world: &mut World) {
+    let mut params: SystemState<NewGameParams> = SystemState::new(world);
+    let (mut commands, mut map, mut dungeon, atlas, bestiary, vaults, entity_query) =
+        params.get_mut(world);
+    for entity in entity_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    // Fresh dungeon, seeded from the old one
+    let (generator, size) = (dungeon.generator, dungeon.size);
+    *dungeon = Dungeon::new(map.rng.gen());
+    dungeon.generator = generator;
+    dungeon.size = size;
+    let level = dungeon.take_level(1, &bestiary, &vaults);
+    spawn_player(&mut commands, &atlas, &level);
+    spawn_enemies(&mut commands, &atlas, &bestiary, &level);
+    spawn_items(&mut commands, &atlas, &level);
+    spawn_lights(&mut commands, &level);
+    *map = level;
+    params.apply(world);
+    world.insert_resource(TurnScheduler::default());
+    world.resource_mut::<MessageLog>().messages.clear();
+    // This is a new game from here on, played from the keyboard even after a replay
+    world.remove_resource::<ResumedGame>();
+    world.remove_resource::<Replay>();
+    world
+        .resource_mut::<State<GameState>>()
+        .set(GameState::AwaitingInput)
+        .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use map_gen_2d::Point;
+
+    use super::*;
+    use crate::{
+        components::MobType,
+        health::Health,
+        messages::{Message, MessageCategory},
+        player::PLAYER_HEALTH,
+        tiles::TileType,
+    };
+
+    fn walls(map: &Level) -> HashSet<Point> {
+        map.tiles
+            .iter()
+            .filter(|tile| tile.1 .0 == TileType::WALL)
+            .map(|tile| *tile.0)
+            .collect()
+    }
+
+    #[test]
+    fn restarting_builds_a_new_dungeon() {
+        let mut app = App::new();
+        app.add_state(GameState::GameOver);
+        let (bestiary, vaults) = (Bestiary::builtin(), Vaults::builtin());
+        let mut dungeon = Dungeon::new(3);
+        let level = dungeon.take_level(1, &bestiary, &vaults);
+        let old_walls = walls(&level);
+        app.insert_resource(level)
+            .insert_resource(dungeon)
+            .insert_resource(bestiary)
+            .insert_resource(vaults)
+            .insert_resource(GlyphAssets {
+                atlas: Handle::default(),
+            })
+            .insert_resource(TurnScheduler {
+                turn: 12,
+                ..default()
+            })
+            .insert_resource(MessageLog {
+                messages: vec![Message {
+                    text: "You die...".to_string(),
+                    category: MessageCategory::Danger,
+                    turn: 12,
+                }],
+            });
+        let dead = app
+            .world
+            .spawn((Player, Mob(MobType::PLAYER), Health(0)))
+            .id();
+        app.update();
+
+        new_game(&mut app.world);
+        app.update();
+
+        let world = &mut app.world;
+        assert_eq!(
+            world.resource::<State<GameState>>().current(),
+            &GameState::AwaitingInput
+        );
+        assert!(world.get_entity(dead).is_none());
+        assert_ne!(world.resource::<Dungeon>().seed, 3);
+        assert_ne!(walls(world.resource::<Level>()), old_walls);
+        assert_eq!(world.resource::<TurnScheduler>().turn, 0);
+        assert!(world.resource::<MessageLog>().messages.is_empty());
+        let players: Vec<i32> = world
+            .query_filtered::<&Health, With<Player>>()
+            .iter(world)
+            .map(|health| health.0)
+            .collect();
+        assert_eq!(players, vec![PLAYER_HEALTH]);
+        // Everything the new level was populated with is there
+        let mobs = world.query::<&Mob>().iter(world).count();
+        assert_eq!(mobs, world.resource::<Level>().mobs.len());
+        let items = world.query::<&Item>().iter(world).count();
+        assert_eq!(items, world.resource::<Level>().items.len());
+    }
+}
@@ -0,0 +1,205 @@
+// Running the game rules without a window, for tests and balance simulations.
+// A Simulation is an App with MinimalPlugins and GamePlugins. Actions are fed to it one at a
+// time and each one is played out, monster turns included, before the next is sent.
+use bevy::prelude::*;
+use serde::Serialize;
+
+use crate::{
+    actions::PlayerAction,
+    components::{MobType, Position},
+    enemies::Enemy,
+    health::{DeathEvent, Health, LastHitBy},
+    items::{Inventory, ItemType},
+    map::{Dungeon, Level},
+    options::Options,
+    player::Player,
+    resources::GlyphAssets,
+    save::{resume_game, spawn_loaded_mobs, SaveFile},
+    turns::{GameState, TurnScheduler},
+    GamePlugins,
+};
+
+/// Most frames one action may take before the simulation gives up on it.
+const MAX_FRAMES_PER_ACTION: usize = 1000;
+
+/// Monsters killed so far.
+#[derive(Resource, Default)]
+pub struct Kills(pub u32);
+
+/// The state of a simulated game.
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Report {
+    pub seed: u64,
+    /// Actions that were played.
+    pub actions: usize,
+    pub turn: u32,
+    pub ticks: u64,
+    pub depth: u32,
+    pub alive: bool,
+    pub health: i32,
+    pub position: Position,
+    pub inventory: Vec<ItemType>,
+    /// Monsters left alive on the current level.
+    pub monsters: usize,
+    pub kills: u32,
+    /// What killed the player, if they are dead.
+    pub killed_by: Option<MobType>,
+}
+
+pub struct Simulation {
+    pub app: App,
+    actions: usize,
+}
+
+impl Simulation {
+    /// Starts a new game on the dungeon built from `seed`.
+    pub fn new(seed: u64) -> Self {
+        Simulation::with_options(Options {
+            seed: Some(seed),
+            ..default()
+        })
+    }
+
+    /// Starts a new game with the given options, e.g. to pick the map generator.
+    pub fn with_options(options: Options) -> Self {
+        let mut app = App::new();
+        app.insert_resource(options);
+        Simulation::start(app)
+    }
+
+    /// Starts a game on a level built by hand, e.g. with Level::from_ascii.
+    /// Only the mobs and items listed in the level are spawned.
+    pub fn from_level(level: Level) -> Self {
+        let mut app = App::new();
+        app.insert_resource(Options::default())
+            .insert_resource(Dungeon::new(0))
+            .insert_resource(level);
+        Simulation::start(app)
+    }
+
+    /// Picks up a saved game, see `save::save_game`.
+    pub fn from_save(save: SaveFile) -> Self {
+        let mut app = App::new();
+        app.insert_resource(Options::default())
+            .add_startup_system(spawn_loaded_mobs);
+        resume_game(&mut app.world, save);
+        Simulation::start(app)
+    }
+
+    fn start(mut app: App) -> Self {
+        app.add_plugins(MinimalPlugins)
+            // Nothing is drawn, so the sprites can go without an atlas
+            .insert_resource(GlyphAssets {
+                atlas: Handle::default(),
+            })
+            .init_resource::<Kills>()
+            .add_plugins(GamePlugins)
+            // Deaths are sent during Update
+            .add_system_to_stage(CoreStage::PostUpdate, count_kills);
+        // Runs the startup systems
+        app.update();
+        Simulation { app, actions: 0 }
+    }
+
+    pub fn state(&self) -> GameState {
+        *self.app.world.resource::<State<GameState>>().current()
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.state() == GameState::GameOver
+    }
+
+    /// Plays one action and the monster turns that follow it. Does nothing once the game is over.
+    pub fn act(&mut self, action: PlayerAction) {
+        if self.state() != GameState::AwaitingInput {
+            return;
+        }
+        self.app
+            .world
+            .resource_mut::<Events<PlayerAction>>()
+            .send(action);
+        self.actions += 1;
+        for _ in 0..MAX_FRAMES_PER_ACTION {
+            self.app.update();
+            if self.state() != GameState::MonsterTurn {
+                return;
+            }
+        }
+        warn!(
+            "Monster turns did not end after {} frames",
+            MAX_FRAMES_PER_ACTION
+        );
+    }
+
+    /// Plays the actions in order, stopping early if the player dies.
+    pub fn run(&mut self, actions: impl IntoIterator<Item = PlayerAction>) {
+        for action in actions {
+            if self.is_over() {
+                break;
+            }
+            self.act(action);
+        }
+    }
+
+    pub fn report(&mut self) -> Report {
+        let alive = !self.is_over();
+        let world = &mut self.app.world;
+        let (position, health, inventory, last_hit) = world
+            .query_filtered::<(&Position, &Health, &Inventory, Option<&LastHitBy>), With<Player>>()
+            .single(world);
+        let (position, health, inventory) = (*position, health.0, inventory.items.clone());
+        let killed_by = last_hit.and_then(|hit| hit.0.clone()).filter(|_| !alive);
+        let monsters = world
+            .query_filtered::<&Health, With<Enemy>>()
+            .iter(world)
+            .filter(|health| health.0 > 0)
+            .count();
+        let scheduler = world.resource::<TurnScheduler>();
+        Report {
+            seed: world.resource::<Dungeon>().seed,
+            actions: self.actions,
+            turn: scheduler.turn,
+            ticks: scheduler.ticks,
+            depth: world.resource::<Level>().depth,
+            alive,
+            health,
+            position,
+            inventory,
+            monsters,
+            kills: world.resource::<Kills>().0,
+            killed_by,
+        }
+    }
+}
+
+fn count_kills(mut kills: ResMut<Kills>, mut death_events: EventReader<DeathEvent>) {
+    kills.0 += death_events
+        .iter()
+        .filter(|event| event.kind != MobType::PLAYER)
+        .count() as u32;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wander() -> Vec<PlayerAction> {
+        [(0, 1), (1, 0), (0, -1), (-1, 0)]
+            .iter()
+            .cycle()
+            .take(40)
+            .map(|&(dx, dy)| PlayerAction::Move { dx, dy })
+            .collect()
+    }
+
+    #[test]
+    fn same_seed_same_game() {
+        let mut first = Simulation::new(7);
+        first.run(wander());
+        let mut second = Simulation::new(7);
+        second.run(wander());
+        let report = first.report();
+        assert_eq!(report, second.report());
+        assert!(report.turn > 0);
+    }
+}
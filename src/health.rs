@@ -1,26 +1,76 @@
 use bevy::prelude::*;
 
 use crate::{
-    components::Mob,
+    combat::{AttackOutcome, CombatEvent},
+    components::{Mob, MobType},
+    player::Player,
+    turns::GameState,
 };
 
 #[derive(Component)]
 pub struct Health(pub i32);
 
+/// Sent when a mob runs out of health.
+pub struct DeathEvent {
+    pub kind: MobType,
+}
+
+/// The kind of mob that last damaged this one. Used for the cause of death.
+#[derive(Component)]
+pub struct LastHitBy(pub Option<MobType>);
+
 pub struct HealthPlugin;
 impl Plugin for HealthPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system(check_health_to_despawn);
+        app.add_event::<DeathEvent>()
+            .add_system(check_health_to_despawn)
+            .add_system(record_last_hit.before(check_player_death))
+            .add_system(check_player_death);
     }
 }
 
 fn check_health_to_despawn(
     mut commands: Commands,
-    health_query: Query<(Entity, &Health, &Mob)>,
+    health_query: Query<(Entity, &Health, &Mob), Without<Player>>,
+    mut death_events: EventWriter<DeathEvent>,
 ) {
     for mob in health_query.iter() {
-        if mob.1.0 <= 0 {
+        if mob.1 .0 <= 0 {
             commands.entity(mob.0).despawn();
+            death_events.send(DeathEvent {
+                kind: mob.2 .0.clone(),
+            });
+        }
+    }
+}
+
+fn record_last_hit(
+    mut combat_events: EventReader<CombatEvent>,
+    mut hit_query: Query<&mut LastHitBy>,
+) {
+    for event in combat_events.iter() {
+        if let AttackOutcome::Hit { damage, .. } = event.outcome {
+            if damage > 0 {
+                if let Ok(mut last_hit_by) = hit_query.get_mut(event.target) {
+                    last_hit_by.0 = Some(event.attacker_kind.clone());
+                }
+            }
+        }
+    }
+}
+
+// The player is not despawned, the game over screen takes over instead.
+fn check_player_death(
+    player_query: Query<&Health, (With<Player>, Changed<Health>)>,
+    mut state: ResMut<State<GameState>>,
+    mut death_events: EventWriter<DeathEvent>,
+) {
+    for health in player_query.iter() {
+        if health.0 <= 0 && *state.current() != GameState::GameOver {
+            state.overwrite_set(GameState::GameOver).unwrap();
+            death_events.send(DeathEvent {
+                kind: MobType::PLAYER,
+            });
         }
     }
 }
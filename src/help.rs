@@ -0,0 +1,115 @@
+// A screen listing every action and the keys it is on right now.
+use bevy::prelude::*;
+
+use crate::{
+    components::MainCamera,
+    keymap::{keymap_path, Action, Keymap},
+    rendering::WINDOWSIZE,
+    resources::GlyphAssets,
+    turns::GameState,
+    ui::{spawn_panel, spawn_text, GLYPH_SIZE},
+};
+
+const LINE_HEIGHT: f32 = GLYPH_SIZE + 4.0;
+/// Characters from the left edge to the keys column.
+const KEYS_COLUMN: f32 = 32.0;
+
+#[derive(Component)]
+pub struct HelpScreen;
+
+// ============================
+// ========== PLUGIN ==========
+pub struct HelpPlugin;
+impl Plugin for HelpPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_update(GameState::AwaitingInput).with_system(open_help))
+            .add_system_set(SystemSet::on_enter(GameState::Help).with_system(show_help))
+            .add_system_set(SystemSet::on_update(GameState::Help).with_system(close_help))
+            .add_system_set(SystemSet::on_exit(GameState::Help).with_system(hide_help));
+    }
+}
+
+fn open_help(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if keymap.just_pressed(Action::Help, &keyboard_input) {
+        keyboard_input.clear();
+        state.push(GameState::Help).unwrap();
+    }
+}
+
+fn show_help(
+    mut commands: Commands,
+    atlas: Res<GlyphAssets>,
+    keymap: Res<Keymap>,
+    camera_query: Query<Entity, With<MainCamera>>,
+) {
+    let camera = camera_query.single();
+    commands.entity(camera).with_children(|parent| {
+        parent
+            .spawn((
+                HelpScreen,
+                SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.0, -0.4)),
+            ))
+            .with_children(|parent| {
+                spawn_panel(
+                    parent,
+                    &atlas,
+                    Color::BLACK,
+                    Vec2::ZERO,
+                    Vec2::new(WINDOWSIZE.0, WINDOWSIZE.1),
+                );
+                let left = -WINDOWSIZE.0 / 2.0 + GLYPH_SIZE;
+                let top = WINDOWSIZE.1 / 2.0 - GLYPH_SIZE;
+                spawn_text(
+                    parent,
+                    "Keys - Esc to close",
+                    Color::YELLOW,
+                    Vec2::new(left, top),
+                );
+                for (i, action) in Action::ALL.into_iter().enumerate() {
+                    let y = top - (i + 2) as f32 * LINE_HEIGHT;
+                    spawn_text(
+                        parent,
+                        action.description(),
+                        Color::WHITE,
+                        Vec2::new(left, y),
+                    );
+                    spawn_text(
+                        parent,
+                        keymap.describe(action),
+                        Color::GRAY,
+                        Vec2::new(left + KEYS_COLUMN * GLYPH_SIZE, y),
+                    );
+                }
+                let y = top - (Action::ALL.len() + 3) as f32 * LINE_HEIGHT;
+                spawn_text(
+                    parent,
+                    format!("Keys can be changed in {}", keymap_path().display()),
+                    Color::GRAY,
+                    Vec2::new(left, y),
+                );
+            });
+    });
+}
+
+fn close_help(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape)
+        || keymap.just_pressed(Action::Help, &keyboard_input)
+    {
+        keyboard_input.clear();
+        state.pop().unwrap();
+    }
+}
+
+fn hide_help(mut commands: Commands, screen_query: Query<Entity, With<HelpScreen>>) {
+    for screen in screen_query.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
@@ -0,0 +1,486 @@
+// Items lying on the map and the players inventory.
+// Items on the floor are entities with a Position. Once picked up they are just an
+// ItemType in the Inventory, and get a new entity if they are dropped again.
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{ActionInput, PlayerAction},
+    components::{MainCamera, Mob, Position, POV},
+    enemies::SpawnEnemies,
+    keymap::{Action, Keymap},
+    map::Level,
+    messages::{GameMessage, MessageCategory},
+    player::Player,
+    replay::Replay,
+    resources::GlyphAssets,
+    tiles::TILESIZE,
+    turns::{end_player_turn, Energy, GameState},
+    ui::{spawn_panel, spawn_text, GLYPH_SIZE},
+};
+
+/// Keys used to pick an item in the inventory screen, one per inventory slot.
+const SLOT_KEYS: [KeyCode; 10] = [
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+];
+
+/// Most items the player can carry.
+pub const INVENTORY_CAPACITY: usize = SLOT_KEYS.len();
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ItemType {
+    POTION,
+    SCROLL,
+    DAGGER,
+    /// Opens one locked door.
+    KEY,
+}
+
+impl ItemType {
+    /// Lowercase name used in text shown to the player.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ItemType::POTION => "potion",
+            ItemType::SCROLL => "scroll",
+            ItemType::DAGGER => "dagger",
+            ItemType::KEY => "key",
+        }
+    }
+
+    /// The item with the given name, as in `name`.
+    pub fn from_name(name: &str) -> Option<ItemType> {
+        [
+            ItemType::POTION,
+            ItemType::SCROLL,
+            ItemType::DAGGER,
+            ItemType::KEY,
+        ]
+        .into_iter()
+        .find(|item| item.name() == name)
+    }
+
+    /// CP437 glyph drawn on the map.
+    pub fn glyph(&self) -> usize {
+        match self {
+            ItemType::POTION => '!' as usize,
+            ItemType::SCROLL => '?' as usize,
+            ItemType::DAGGER => ')' as usize,
+            // A key lying on its side
+            ItemType::KEY => '-' as usize,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            ItemType::POTION => Color::FUCHSIA,
+            ItemType::SCROLL => Color::ANTIQUE_WHITE,
+            ItemType::DAGGER => Color::CYAN,
+            ItemType::KEY => Color::GOLD,
+        }
+    }
+}
+
+/// An item lying on the map.
+#[derive(Component)]
+pub struct Item(pub ItemType);
+
+#[derive(Bundle)]
+pub struct ItemBundle {
+    #[bundle]
+    sprite_sheet_bundle: SpriteSheetBundle,
+    item: Item,
+    position: Position,
+}
+
+impl ItemBundle {
+    pub fn new(
+        location: (i32, i32),
+        kind: ItemType,
+        texture_atlas_handle: Handle<TextureAtlas>,
+    ) -> ItemBundle {
+        let mut color = kind.color();
+        // Hidden until the player can see it
+        color.set_a(0.0);
+        ItemBundle {
+            sprite_sheet_bundle: SpriteSheetBundle {
+                texture_atlas: texture_atlas_handle,
+                transform: Transform {
+                    translation: Vec3 {
+                        x: location.0 as f32 * TILESIZE as f32,
+                        y: location.1 as f32 * TILESIZE as f32,
+                        // Above the floor
+                        z: 0.5,
+                    },
+                    scale: Vec3 {
+                        x: TILESIZE as f32 / 8.0,
+                        y: TILESIZE as f32 / 8.0,
+                        z: TILESIZE as f32 / 8.0,
+                    },
+                    ..default()
+                },
+                sprite: TextureAtlasSprite {
+                    index: kind.glyph(),
+                    color,
+                    ..default()
+                },
+                ..default()
+            },
+            item: Item(kind),
+            position: Position {
+                x: location.0,
+                y: location.1,
+            },
+        }
+    }
+}
+
+/// Items carried by a mob.
+#[derive(Component)]
+pub struct Inventory {
+    pub items: Vec<ItemType>,
+    pub capacity: usize,
+}
+
+impl Inventory {
+    pub fn new(capacity: usize) -> Self {
+        Inventory {
+            items: Vec::new(),
+            capacity,
+        }
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.items.len() >= self.capacity
+    }
+}
+
+/// What choosing an item in the inventory screen does.
+#[derive(Resource, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum InventoryMode {
+    #[default]
+    Browse,
+    Drop,
+}
+
+/// Root of the inventory screen.
+#[derive(Component)]
+pub struct InventoryScreen;
+
+// ============================
+// ========== PLUGIN ==========
+pub struct ItemPlugin;
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        // Keep entity ids the same from game to game
+        app.add_startup_system(setup.after(SpawnEnemies))
+            .add_system_set(
+                SystemSet::on_update(GameState::AwaitingInput)
+                    .with_system(pick_up.after(ActionInput))
+                    .with_system(drop_item.after(ActionInput)),
+            );
+    }
+}
+
+/// The inventory screen, and showing items the player can see.
+pub struct InventoryScreenPlugin;
+impl Plugin for InventoryScreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InventoryMode>()
+            .add_system_set(
+                SystemSet::on_update(GameState::AwaitingInput).with_system(open_inventory),
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Inventory).with_system(show_inventory))
+            .add_system_set(SystemSet::on_update(GameState::Inventory).with_system(inventory_input))
+            .add_system_set(SystemSet::on_exit(GameState::Inventory).with_system(hide_inventory))
+            .add_system_to_stage(CoreStage::PostUpdate, update_item_vis);
+    }
+}
+
+fn setup(mut commands: Commands, atlas: Res<GlyphAssets>, map: Res<Level>) {
+    spawn_items(&mut commands, &atlas, &map);
+}
+
+pub fn spawn_items(commands: &mut Commands, atlas: &GlyphAssets, map: &Level) {
+    for item in map.items.iter() {
+        commands.spawn(ItemBundle::new(
+            (item.0.x.try_into().unwrap(), item.0.y.try_into().unwrap()),
+            item.1,
+            atlas.atlas.clone(),
+        ));
+    }
+}
+
+fn pick_up(
+    mut commands: Commands,
+    mut actions: EventReader<PlayerAction>,
+    mut state: ResMut<State<GameState>>,
+    mut player_query: Query<(&Position, &mut Inventory, &mut Energy), With<Player>>,
+    item_query: Query<(Entity, &Position, &Item)>,
+    mut messages: EventWriter<GameMessage>,
+) {
+    if !actions.iter().any(|action| *action == PlayerAction::PickUp) {
+        return;
+    }
+    for (position, mut inventory, mut energy) in player_query.iter_mut() {
+        let Some(item) = item_query.iter().find(|item| item.1 == position) else {
+            messages.send(GameMessage::new(
+                "There is nothing here to pick up.",
+                MessageCategory::Info,
+            ));
+            continue;
+        };
+        if inventory.is_full() {
+            messages.send(GameMessage::new(
+                "Your pack is full.",
+                MessageCategory::Info,
+            ));
+            continue;
+        }
+        inventory.items.push(item.2 .0);
+        commands.entity(item.0).despawn();
+        messages.send(GameMessage::new(
+            format!("You pick up the {}.", item.2 .0.name()),
+            MessageCategory::Discovery,
+        ));
+        end_player_turn(&mut energy, &mut state);
+    }
+}
+
+fn open_inventory(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut state: ResMut<State<GameState>>,
+    mut mode: ResMut<InventoryMode>,
+    replay: Option<Res<Replay>>,
+) {
+    // Dropping an item is up to the recording
+    if replay.is_some() {
+        return;
+    }
+    let new_mode = if keymap.just_pressed(Action::Inventory, &keyboard_input) {
+        InventoryMode::Browse
+    } else if keymap.just_pressed(Action::Drop, &keyboard_input) {
+        InventoryMode::Drop
+    } else {
+        return;
+    };
+    keyboard_input.clear();
+    *mode = new_mode;
+    state.set(GameState::Inventory).unwrap();
+}
+
+fn show_inventory(
+    mut commands: Commands,
+    atlas: Res<GlyphAssets>,
+    mode: Res<InventoryMode>,
+    camera_query: Query<Entity, With<MainCamera>>,
+    player_query: Query<&Inventory, With<Player>>,
+) {
+    let Ok(inventory) = player_query.get_single() else {
+        return;
+    };
+    let title = match *mode {
+        InventoryMode::Browse => format!(
+            "Inventory ({}/{})",
+            inventory.items.len(),
+            inventory.capacity
+        ),
+        InventoryMode::Drop => "Drop which item?".to_string(),
+    };
+    let mut lines = vec![(title, Color::YELLOW), (String::new(), Color::WHITE)];
+    if inventory.items.is_empty() {
+        lines.push(("You are not carrying anything.".to_string(), Color::GRAY));
+    }
+    for (i, item) in inventory.items.iter().enumerate() {
+        let letter = (b'a' + i as u8) as char;
+        lines.push((format!("{}) {}", letter, item.name()), item.color()));
+    }
+    lines.push((String::new(), Color::WHITE));
+    lines.push(("Esc to close".to_string(), Color::GRAY));
+
+    let width = GLYPH_SIZE * 34.0;
+    let height = (lines.len() + 2) as f32 * GLYPH_SIZE * 1.5;
+    let camera = camera_query.single();
+    commands.entity(camera).with_children(|parent| {
+        parent
+            .spawn((
+                InventoryScreen,
+                SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.0, -0.5)),
+            ))
+            .with_children(|parent| {
+                spawn_panel(
+                    parent,
+                    &atlas,
+                    Color::rgba(0.0, 0.0, 0.0, 0.9),
+                    Vec2::ZERO,
+                    Vec2::new(width, height),
+                );
+                let left = -width / 2.0 + GLYPH_SIZE * 2.0;
+                let top = height / 2.0 - GLYPH_SIZE * 1.5;
+                for (i, (text, color)) in lines.into_iter().enumerate() {
+                    let y = top - i as f32 * GLYPH_SIZE * 1.5;
+                    spawn_text(parent, text, color, Vec2::new(left, y));
+                }
+            });
+    });
+}
+
+fn inventory_input(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut state: ResMut<State<GameState>>,
+    mode: Res<InventoryMode>,
+    player_query: Query<&Inventory, With<Player>>,
+    mut actions: EventWriter<PlayerAction>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape)
+        || keymap.just_pressed(Action::Inventory, &keyboard_input)
+    {
+        keyboard_input.clear();
+        state.set(GameState::AwaitingInput).unwrap();
+        return;
+    }
+    if *mode != InventoryMode::Drop {
+        return;
+    }
+    let Some(slot) = SLOT_KEYS
+        .iter()
+        .position(|&key| keyboard_input.just_pressed(key))
+    else {
+        return;
+    };
+    for inventory in player_query.iter() {
+        if slot >= inventory.items.len() {
+            continue;
+        }
+        keyboard_input.clear();
+        // The drop itself happens back on the players turn
+        actions.send(PlayerAction::Drop(slot));
+        state.set(GameState::AwaitingInput).unwrap();
+    }
+}
+
+fn drop_item(
+    mut commands: Commands,
+    mut actions: EventReader<PlayerAction>,
+    mut state: ResMut<State<GameState>>,
+    atlas: Res<GlyphAssets>,
+    mut player_query: Query<(&Position, &mut Inventory, &mut Energy), With<Player>>,
+    mut messages: EventWriter<GameMessage>,
+) {
+    for action in actions.iter() {
+        let PlayerAction::Drop(slot) = *action else {
+            continue;
+        };
+        for (position, mut inventory, mut energy) in player_query.iter_mut() {
+            if slot >= inventory.items.len() {
+                continue;
+            }
+            let item = inventory.items.remove(slot);
+            commands.spawn(ItemBundle::new(
+                (position.x, position.y),
+                item,
+                atlas.atlas.clone(),
+            ));
+            messages.send(GameMessage::new(
+                format!("You drop the {}.", item.name()),
+                MessageCategory::Info,
+            ));
+            end_player_turn(&mut energy, &mut state);
+        }
+    }
+}
+
+fn hide_inventory(mut commands: Commands, screen_query: Query<Entity, With<InventoryScreen>>) {
+    for screen in screen_query.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+// Items can be seen while they are in view and nothing is standing on them.
+#[allow(clippy::type_complexity)]
+fn update_item_vis(
+    player_query: Query<&POV, With<Player>>,
+    changed_query: Query<(), Or<(Changed<Position>, Added<Item>)>>,
+    mob_query: Query<&Position, With<Mob>>,
+    mut item_query: Query<(&Position, &mut TextureAtlasSprite), With<Item>>,
+) {
+    if changed_query.is_empty() {
+        return;
+    }
+    for pov in player_query.iter() {
+        for (position, mut sprite) in item_query.iter_mut() {
+            let visible = pov.visible_tiles.iter().any(|tile| tile.0 == *position)
+                && !mob_query.iter().any(|mob| mob == position);
+            sprite.color.set_a(if visible { 1.0 } else { 0.0 });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::turns::ACTION_COST;
+
+    // Tries to pick up whatever is under the player.
+    fn pick_up_here(app: &mut App) -> Vec<String> {
+        app.world
+            .resource_mut::<Events<PlayerAction>>()
+            .send(PlayerAction::PickUp);
+        app.update();
+        let events = app.world.resource::<Events<GameMessage>>();
+        events
+            .get_reader()
+            .iter(events)
+            .map(|message| message.text.clone())
+            .collect()
+    }
+
+    #[test]
+    fn a_full_pack_refuses_more_items() {
+        let mut app = App::new();
+        app.add_state(GameState::AwaitingInput)
+            .add_event::<GameMessage>()
+            .add_event::<PlayerAction>()
+            .add_system_set(SystemSet::on_update(GameState::AwaitingInput).with_system(pick_up));
+        let mut inventory = Inventory::new(INVENTORY_CAPACITY);
+        inventory.items = vec![ItemType::POTION; INVENTORY_CAPACITY];
+        let here = Position { x: 1, y: 1 };
+        let player = app
+            .world
+            .spawn((Player, here, inventory, Energy(ACTION_COST)))
+            .id();
+        let dagger = app.world.spawn((Item(ItemType::DAGGER), here)).id();
+        app.update();
+
+        // Nothing is picked up and the turn is not used
+        let messages = pick_up_here(&mut app);
+        assert_eq!(messages, ["Your pack is full."]);
+        assert!(app.world.get_entity(dagger).is_some());
+        let carried = &app.world.get::<Inventory>(player).unwrap().items;
+        assert_eq!(carried.len(), INVENTORY_CAPACITY);
+        assert_eq!(app.world.get::<Energy>(player).unwrap().0, ACTION_COST);
+        let state = app.world.resource::<State<GameState>>();
+        assert_eq!(state.current(), &GameState::AwaitingInput);
+
+        // Making room lets it be picked up
+        app.world.get_mut::<Inventory>(player).unwrap().items.pop();
+        let messages = pick_up_here(&mut app);
+        assert_eq!(messages.last().unwrap(), "You pick up the dagger.");
+        assert!(app.world.get_entity(dagger).is_none());
+        let carried = &app.world.get::<Inventory>(player).unwrap().items;
+        assert_eq!(carried.last(), Some(&ItemType::DAGGER));
+        let state = app.world.resource::<State<GameState>>();
+        assert_eq!(state.current(), &GameState::MonsterTurn);
+    }
+}
@@ -0,0 +1,452 @@
+// Which keys do what.
+// Every key press the game reacts to is looked up in the Keymap, so the keys can be changed in
+// keymap.ron in the users config directory (~/.config/p01-rogue on Linux). The file only needs
+// the actions that should get other keys, the rest keep their default keys. Keys that belong to
+// one screen, like the inventory letters or scrolling the message history, are not in it.
+use std::{collections::HashMap, fmt, fs, path::PathBuf};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{actions::PlayerAction, direction::Direction};
+
+/// Keys by the name used for them in keymap.ron.
+const KEY_NAMES: [(&str, KeyCode); 78] = [
+    ("a", KeyCode::A),
+    ("b", KeyCode::B),
+    ("c", KeyCode::C),
+    ("d", KeyCode::D),
+    ("e", KeyCode::E),
+    ("f", KeyCode::F),
+    ("g", KeyCode::G),
+    ("h", KeyCode::H),
+    ("i", KeyCode::I),
+    ("j", KeyCode::J),
+    ("k", KeyCode::K),
+    ("l", KeyCode::L),
+    ("m", KeyCode::M),
+    ("n", KeyCode::N),
+    ("o", KeyCode::O),
+    ("p", KeyCode::P),
+    ("q", KeyCode::Q),
+    ("r", KeyCode::R),
+    ("s", KeyCode::S),
+    ("t", KeyCode::T),
+    ("u", KeyCode::U),
+    ("v", KeyCode::V),
+    ("w", KeyCode::W),
+    ("x", KeyCode::X),
+    ("y", KeyCode::Y),
+    ("z", KeyCode::Z),
+    ("0", KeyCode::Key0),
+    ("1", KeyCode::Key1),
+    ("2", KeyCode::Key2),
+    ("3", KeyCode::Key3),
+    ("4", KeyCode::Key4),
+    ("5", KeyCode::Key5),
+    ("6", KeyCode::Key6),
+    ("7", KeyCode::Key7),
+    ("8", KeyCode::Key8),
+    ("9", KeyCode::Key9),
+    ("Numpad0", KeyCode::Numpad0),
+    ("Numpad1", KeyCode::Numpad1),
+    ("Numpad2", KeyCode::Numpad2),
+    ("Numpad3", KeyCode::Numpad3),
+    ("Numpad4", KeyCode::Numpad4),
+    ("Numpad5", KeyCode::Numpad5),
+    ("Numpad6", KeyCode::Numpad6),
+    ("Numpad7", KeyCode::Numpad7),
+    ("Numpad8", KeyCode::Numpad8),
+    ("Numpad9", KeyCode::Numpad9),
+    ("F1", KeyCode::F1),
+    ("F2", KeyCode::F2),
+    ("F3", KeyCode::F3),
+    ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5),
+    ("F6", KeyCode::F6),
+    ("F7", KeyCode::F7),
+    ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9),
+    ("F10", KeyCode::F10),
+    ("F11", KeyCode::F11),
+    ("F12", KeyCode::F12),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Space", KeyCode::Space),
+    ("Enter", KeyCode::Return),
+    ("Tab", KeyCode::Tab),
+    ("Escape", KeyCode::Escape),
+    (".", KeyCode::Period),
+    (",", KeyCode::Comma),
+    ("/", KeyCode::Slash),
+    (";", KeyCode::Semicolon),
+    ("'", KeyCode::Apostrophe),
+    ("-", KeyCode::Minus),
+    ("=", KeyCode::Equals),
+    ("\\", KeyCode::Backslash),
+];
+
+/// What a key press asks for. Actions that take a turn are sent on as PlayerActions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveNorth,
+    MoveNorthEast,
+    MoveEast,
+    MoveSouthEast,
+    MoveSouth,
+    MoveSouthWest,
+    MoveWest,
+    MoveNorthWest,
+    Wait,
+    PickUp,
+    Drop,
+    CloseDoor,
+    Descend,
+    Ascend,
+    Inventory,
+    MessageHistory,
+    Help,
+    SaveAndQuit,
+    Restart,
+}
+
+impl Action {
+    /// In the order they are listed on the help screen.
+    pub const ALL: [Action; 19] = [
+        Action::MoveNorth,
+        Action::MoveNorthEast,
+        Action::MoveEast,
+        Action::MoveSouthEast,
+        Action::MoveSouth,
+        Action::MoveSouthWest,
+        Action::MoveWest,
+        Action::MoveNorthWest,
+        Action::Wait,
+        Action::PickUp,
+        Action::Drop,
+        Action::CloseDoor,
+        Action::Descend,
+        Action::Ascend,
+        Action::Inventory,
+        Action::MessageHistory,
+        Action::Help,
+        Action::SaveAndQuit,
+        Action::Restart,
+    ];
+
+    /// Shown on the help screen.
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::MoveNorth => "Move or attack north",
+            Action::MoveNorthEast => "Move or attack north east",
+            Action::MoveEast => "Move or attack east",
+            Action::MoveSouthEast => "Move or attack south east",
+            Action::MoveSouth => "Move or attack south",
+            Action::MoveSouthWest => "Move or attack south west",
+            Action::MoveWest => "Move or attack west",
+            Action::MoveNorthWest => "Move or attack north west",
+            Action::Wait => "Wait a turn",
+            Action::PickUp => "Pick up an item",
+            Action::Drop => "Drop an item",
+            Action::CloseDoor => "Close a door",
+            Action::Descend => "Go down the stairs",
+            Action::Ascend => "Go up the stairs",
+            Action::Inventory => "Inventory",
+            Action::MessageHistory => "Message history",
+            Action::Help => "This screen",
+            Action::SaveAndQuit => "Save and quit",
+            Action::Restart => "Start over, once dead",
+        }
+    }
+
+    pub fn direction(&self) -> Option<Direction> {
+        match self {
+            Action::MoveNorth => Some(Direction::North),
+            Action::MoveNorthEast => Some(Direction::NorthEast),
+            Action::MoveEast => Some(Direction::East),
+            Action::MoveSouthEast => Some(Direction::SouthEast),
+            Action::MoveSouth => Some(Direction::South),
+            Action::MoveSouthWest => Some(Direction::SouthWest),
+            Action::MoveWest => Some(Direction::West),
+            Action::MoveNorthWest => Some(Direction::NorthWest),
+            _ => None,
+        }
+    }
+
+    /// The PlayerAction sent for this, if it is played out on the players turn.
+    pub fn player_action(&self) -> Option<PlayerAction> {
+        if let Some(direction) = self.direction() {
+            return Some(PlayerAction::step(direction));
+        }
+        match self {
+            Action::Wait => Some(PlayerAction::Wait),
+            Action::PickUp => Some(PlayerAction::PickUp),
+            Action::CloseDoor => Some(PlayerAction::CloseDoor),
+            Action::Descend => Some(PlayerAction::Descend),
+            Action::Ascend => Some(PlayerAction::Ascend),
+            _ => None,
+        }
+    }
+
+    // Keys used when the keymap file does not say otherwise.
+    fn default_keys(&self) -> &'static [&'static str] {
+        match self {
+            Action::MoveNorth => &["k", "w", "Up", "Numpad8"],
+            Action::MoveNorthEast => &["u", "Numpad9"],
+            Action::MoveEast => &["l", "d", "Right", "Numpad6"],
+            Action::MoveSouthEast => &["n", "Numpad3"],
+            Action::MoveSouth => &["j", "s", "Down", "Numpad2"],
+            Action::MoveSouthWest => &["b", "Numpad1"],
+            Action::MoveWest => &["h", "a", "Left", "Numpad4"],
+            Action::MoveNorthWest => &["y", "Numpad7"],
+            Action::Wait => &[".", "Numpad5"],
+            Action::PickUp => &["g"],
+            Action::Drop => &["x"],
+            Action::CloseDoor => &["c"],
+            // '>' and '<'
+            Action::Descend => &["Shift+."],
+            Action::Ascend => &["Shift+,"],
+            Action::Inventory => &["i"],
+            Action::MessageHistory => &["m"],
+            // '?'
+            Action::Help => &["Shift+/", "F1"],
+            Action::SaveAndQuit => &["Ctrl+q"],
+            Action::Restart => &["r"],
+        }
+    }
+}
+
+/// A key, pressed with or without Shift and Ctrl.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Binding {
+    pub key: KeyCode,
+    pub shift: bool,
+    pub control: bool,
+}
+
+impl Binding {
+    /// Reads a key written as in keymap.ron, e.g. "k", "Numpad8" or "Shift+.".
+    pub fn parse(text: &str) -> Result<Binding, String> {
+        let mut rest = text.trim();
+        let (mut shift, mut control) = (false, false);
+        loop {
+            if let Some(after) = strip_prefix_ignore_case(rest, "Shift+") {
+                shift = true;
+                rest = after;
+            } else if let Some(after) = strip_prefix_ignore_case(rest, "Ctrl+") {
+                control = true;
+                rest = after;
+            } else {
+                break;
+            }
+        }
+        let key = KEY_NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(rest))
+            .map(|(_, key)| *key)
+            .ok_or_else(|| format!("unknown key '{}'", text))?;
+        Ok(Binding {
+            key,
+            shift,
+            control,
+        })
+    }
+
+    pub fn just_pressed(&self, input: &Input<KeyCode>) -> bool {
+        let shift = input.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+        let control = input.any_pressed([KeyCode::LControl, KeyCode::RControl]);
+        input.just_pressed(self.key) && shift == self.shift && control == self.control
+    }
+}
+
+fn strip_prefix_ignore_case<'a>(text: &'a str, prefix: &str) -> Option<&'a str> {
+    let head = text.get(..prefix.len())?;
+    head.eq_ignore_ascii_case(prefix)
+        .then(|| &text[prefix.len()..])
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.control {
+            write!(f, "Ctrl+")?;
+        }
+        if self.shift {
+            write!(f, "Shift+")?;
+        }
+        let name = KEY_NAMES
+            .iter()
+            .find(|(_, key)| *key == self.key)
+            .map_or("?", |(name, _)| name);
+        write!(f, "{}", name)
+    }
+}
+
+/// The keys for every action.
+#[derive(Resource, Clone, Debug, PartialEq)]
+pub struct Keymap {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .map(|action| {
+                let keys = action
+                    .default_keys()
+                    .iter()
+                    .map(|key| Binding::parse(key).unwrap())
+                    .collect();
+                (*action, keys)
+            })
+            .collect();
+        Keymap { bindings }
+    }
+}
+
+impl Keymap {
+    /// Reads a keymap file: a map from actions to lists of keys. Actions that are left out
+    /// keep their default keys.
+    pub fn parse(text: &str) -> Result<Keymap, String> {
+        let file: HashMap<Action, Vec<String>> =
+            ron::from_str(text).map_err(|err| err.to_string())?;
+        let mut keymap = Keymap::default();
+        for (action, keys) in file {
+            let keys = keys
+                .iter()
+                .map(|key| Binding::parse(key))
+                .collect::<Result<_, _>>()
+                .map_err(|err| format!("{:?}: {}", action, err))?;
+            keymap.bindings.insert(action, keys);
+        }
+        keymap.check()?;
+        Ok(keymap)
+    }
+
+    // No key may do two things.
+    fn check(&self) -> Result<(), String> {
+        let mut seen: HashMap<Binding, Action> = HashMap::new();
+        for action in Action::ALL {
+            for binding in self.keys(action) {
+                if let Some(other) = seen.insert(*binding, action) {
+                    return Err(format!(
+                        "'{}' is the key for both {:?} and {:?}",
+                        binding, other, action
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the keymap file, or uses the default keys if there is none or it is broken.
+    pub fn load() -> Keymap {
+        let path = keymap_path();
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Keymap::default();
+        };
+        match Keymap::parse(&text) {
+            Ok(keymap) => {
+                info!("Loaded keys from {}", path.display());
+                keymap
+            }
+            Err(err) => {
+                warn!(
+                    "Could not read {}, using the default keys: {}",
+                    path.display(),
+                    err
+                );
+                Keymap::default()
+            }
+        }
+    }
+
+    pub fn keys(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], Vec::as_slice)
+    }
+
+    /// The keys for `action` as text, e.g. "k, Up".
+    pub fn describe(&self, action: Action) -> String {
+        self.keys(action)
+            .iter()
+            .map(|binding| binding.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Returns true if one of the keys for `action` was just pressed.
+    pub fn just_pressed(&self, action: Action, input: &Input<KeyCode>) -> bool {
+        self.keys(action)
+            .iter()
+            .any(|binding| binding.just_pressed(input))
+    }
+}
+
+/// Where the keymap file is looked for, in the users config directory if there is one.
+pub fn keymap_path() -> PathBuf {
+    dirs::config_dir()
+        .map(|dir| dir.join("p01-rogue"))
+        .unwrap_or_default()
+        .join("keymap.ron")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_keys_are_all_different() {
+        assert_eq!(Keymap::default().check(), Ok(()));
+    }
+
+    #[test]
+    fn keys_are_named_as_they_are_written() {
+        for text in ["k", "Numpad8", "Shift+.", "Ctrl+q", "F1", "Up"] {
+            assert_eq!(Binding::parse(text).unwrap().to_string(), text);
+        }
+        assert_eq!(Binding::parse("shift+K"), Binding::parse("Shift+k"));
+        assert!(Binding::parse("Hyper+k").is_err());
+        assert!(Binding::parse("").is_err());
+    }
+
+    #[test]
+    fn keymap_file_replaces_only_the_actions_it_lists() {
+        // A Dvorak player moving with the keys where WASD are on QWERTY
+        let keymap = Keymap::parse(
+            r#"{
+                MoveNorth: [","],
+                MoveWest: ["a"],
+                MoveSouth: ["o"],
+                MoveEast: ["e"],
+                Ascend: ["Shift+w"],
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(keymap.describe(Action::MoveNorth), ",");
+        assert_eq!(keymap.describe(Action::MoveEast), "e");
+        assert_eq!(keymap.describe(Action::PickUp), "g");
+        assert_eq!(
+            keymap.describe(Action::Help),
+            Keymap::default().describe(Action::Help)
+        );
+    }
+
+    #[test]
+    fn keymap_file_mistakes_are_reported() {
+        let err = Keymap::parse(r#"{ PickUp: ["Numpad99"] }"#).unwrap_err();
+        assert!(err.contains("Numpad99"), "{}", err);
+        let err = Keymap::parse(r#"{ PickUp: ["k"] }"#).unwrap_err();
+        assert!(
+            err.contains("MoveNorth") && err.contains("PickUp"),
+            "{}",
+            err
+        );
+        assert!(Keymap::parse(r#"{ Fly: ["f"] }"#).is_err());
+    }
+}
@@ -0,0 +1,100 @@
+// The game rules are kept apart from everything that needs a window, so the same game
+// can be played on screen (main.rs) or run headless on a server (headless.rs).
+use actions::{ActionPlugin, KeyboardPlugin};
+use ai::AiPlugin;
+use bevy::{app::PluginGroupBuilder, prelude::*};
+use combat::CombatPlugin;
+use doors::DoorPlugin;
+use enemies::EnemyPlugin;
+use game_over::GameOverPlugin;
+use health::HealthPlugin;
+use help::HelpPlugin;
+use items::{InventoryScreenPlugin, ItemPlugin};
+use lighting::LightingPlugin;
+use map::MapPlugin;
+use messages::{MessagePanelPlugin, MessagePlugin};
+use player::PlayerPlugin;
+use rendering::RenderingPlugin;
+use replay::ReplayPlugin;
+use save::SavePlugin;
+use stairs::StairsPlugin;
+use terrain::TerrainPlugin;
+use turns::TurnPlugin;
+use ui::UiPlugin;
+use vaults::VaultPlugin;
+use vision::VisionPlugin;
+pub mod actions;
+pub mod ai;
+pub mod combat;
+pub mod components;
+pub mod direction;
+pub mod doors;
+pub mod enemies;
+pub mod fov;
+pub mod game_over;
+pub mod headless;
+pub mod health;
+pub mod help;
+pub mod items;
+pub mod keymap;
+pub mod lighting;
+pub mod map;
+pub mod mapgen;
+pub mod messages;
+pub mod options;
+pub mod pathfinding;
+pub mod player;
+pub mod rendering;
+pub mod replay;
+pub mod resources;
+pub mod save;
+pub mod stairs;
+pub mod terrain;
+pub mod tiles;
+pub mod turns;
+pub mod ui;
+pub mod vaults;
+pub mod vision;
+
+/// The rules of the game: the dungeon, turns, the player, monsters, items and combat.
+/// Needs only MinimalPlugins plus the Options and GlyphAssets resources.
+pub struct GamePlugins;
+impl PluginGroup for GamePlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(MapPlugin)
+            .add(TurnPlugin)
+            .add(ActionPlugin)
+            .add(PlayerPlugin)
+            .add(EnemyPlugin)
+            .add(VaultPlugin)
+            .add(ItemPlugin)
+            .add(StairsPlugin)
+            .add(DoorPlugin)
+            .add(TerrainPlugin)
+            .add(AiPlugin)
+            .add(LightingPlugin)
+            .add(VisionPlugin)
+            .add(HealthPlugin)
+            .add(CombatPlugin)
+            .add(MessagePlugin)
+    }
+}
+
+/// Everything for a person playing in a window: drawing, screens, the keyboard,
+/// saving and replays. Needs DefaultPlugins and a MainCamera.
+pub struct ClientPlugins;
+impl PluginGroup for ClientPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        PluginGroupBuilder::start::<Self>()
+            .add(KeyboardPlugin)
+            .add(RenderingPlugin)
+            .add(UiPlugin)
+            .add(MessagePanelPlugin)
+            .add(InventoryScreenPlugin)
+            .add(HelpPlugin)
+            .add(GameOverPlugin)
+            .add(SavePlugin)
+            .add(ReplayPlugin)
+    }
+}
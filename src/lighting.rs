@@ -0,0 +1,183 @@
+// Light sources and how much light reaches every tile.
+// Anything with a Position and a LightSource gives off light: torches and fungi that belong
+// to the level, the player's lantern, monsters carrying torches. The light of every source
+// is added up per tile, and the player can only see tiles that are lit.
+use std::collections::HashMap;
+
+use bevy::prelude::*;
+use map_gen_2d::Point;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::Position,
+    fov::field_of_view,
+    map::{Level, TileChanged},
+    vision::VisionUpdate,
+};
+
+/// Tiles with less light than this are dark.
+pub const MIN_LIGHT: f32 = 0.05;
+
+#[derive(Component, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LightSource {
+    /// Red, green and blue from 0 to 1, at full strength.
+    pub color: (f32, f32, f32),
+    /// How many tiles away it reaches.
+    pub radius: i32,
+    /// How quickly it fades with distance. 1 fades evenly out to the radius, more fades sooner.
+    pub falloff: f32,
+}
+
+impl LightSource {
+    /// Strength of the light `distance` tiles away, from 0 to 1.
+    pub fn strength(&self, distance: f32) -> f32 {
+        (1.0 - distance / (self.radius + 1) as f32)
+            .max(0.0)
+            .powf(self.falloff)
+    }
+}
+
+/// Lights that are part of a level.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LightKind {
+    /// Burns on a wall. Kept on the floor in front of the wall, so it lights only the side
+    /// of the wall it hangs on.
+    TORCH,
+    /// Glows faintly on cave floors.
+    FUNGUS,
+}
+
+impl LightKind {
+    pub fn source(&self) -> LightSource {
+        match self {
+            LightKind::TORCH => LightSource {
+                color: (1.0, 0.6, 0.25),
+                radius: 6,
+                falloff: 1.5,
+            },
+            LightKind::FUNGUS => LightSource {
+                color: (0.2, 0.8, 0.6),
+                radius: 3,
+                falloff: 2.0,
+            },
+        }
+    }
+}
+
+/// A light that belongs to the level, despawned with it.
+#[derive(Component)]
+pub struct FixedLight;
+
+#[derive(Bundle)]
+pub struct LightBundle {
+    position: Position,
+    light: LightSource,
+    fixed: FixedLight,
+}
+
+/// How much light reaches every tile of the level, added up over every light source.
+/// Tiles that are not in the map are dark.
+#[derive(Resource, Default)]
+pub struct LightMap {
+    pub light: HashMap<Point, Vec3>,
+}
+
+impl LightMap {
+    /// Red, green and blue light on the tile at (x, y). Can be more than 1 where lights
+    /// overlap.
+    pub fn light_at(&self, x: i32, y: i32) -> Vec3 {
+        if x < 0 || y < 0 {
+            return Vec3::ZERO;
+        }
+        self.light
+            .get(&Point::new(x as usize, y as usize))
+            .copied()
+            .unwrap_or(Vec3::ZERO)
+    }
+
+    pub fn is_lit(&self, x: i32, y: i32) -> bool {
+        self.light_at(x, y).max_element() >= MIN_LIGHT
+    }
+
+    /// `base` as it looks under the light on the tile at (x, y).
+    pub fn color_at(&self, x: i32, y: i32, base: Color) -> Color {
+        let light = self.light_at(x, y).min(Vec3::ONE);
+        Color::rgb(base.r() * light.x, base.g() * light.y, base.b() * light.z)
+    }
+}
+
+/// Updates the LightMap. Runs before VisionUpdate, which needs it.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct LightUpdate;
+
+// ============================
+// ========== PLUGIN ==========
+pub struct LightingPlugin;
+impl Plugin for LightingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LightMap>()
+            .add_startup_system(setup)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                update_light_map.label(LightUpdate).before(VisionUpdate),
+            );
+    }
+}
+
+fn setup(mut commands: Commands, map: Res<Level>) {
+    spawn_lights(&mut commands, &map);
+}
+
+/// Spawns the torches and fungi of a level.
+pub fn spawn_lights(commands: &mut Commands, map: &Level) {
+    for (point, kind) in map.lights.iter() {
+        commands.spawn(LightBundle {
+            position: Position {
+                x: point.x as i32,
+                y: point.y as i32,
+            },
+            light: kind.source(),
+            fixed: FixedLight,
+        });
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_light_map(
+    mut light_map: ResMut<LightMap>,
+    map: Res<Level>,
+    light_query: Query<(&Position, &LightSource)>,
+    changed_query: Query<
+        (),
+        (
+            With<LightSource>,
+            Or<(Changed<Position>, Changed<LightSource>)>,
+        ),
+    >,
+    removed: RemovedComponents<LightSource>,
+    mut tile_changes: EventReader<TileChanged>,
+) {
+    // A door opening or closing lets light through, or stops it
+    let tiles_changed = tile_changes.iter().count() > 0;
+    if !tiles_changed && changed_query.is_empty() && removed.iter().next().is_none() {
+        return;
+    }
+    let mut light: HashMap<Point, Vec3> = HashMap::new();
+    for (position, source) in light_query.iter() {
+        let color = Vec3::new(source.color.0, source.color.1, source.color.2);
+        let lit = field_of_view((position.x, position.y), source.radius, |x, y| {
+            map.blocks_sight(x, y)
+        });
+        for (x, y) in lit {
+            let (dx, dy) = (x - position.x, y - position.y);
+            if x < 0 || y < 0 {
+                continue;
+            }
+            let distance = ((dx * dx + dy * dy) as f32).sqrt();
+            *light.entry(Point::new(x as usize, y as usize)).or_default() +=
+                color * source.strength(distance);
+        }
+    }
+    light_map.light = light;
+}
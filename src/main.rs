@@ -1,49 +1,83 @@
 use bevy::{prelude::*, render::camera::ScalingMode};
 use bevy_embedded_assets::EmbeddedAssetPlugin;
-use components::MainCamera;
-use enemies::EnemyPlugin;
-use health::HealthPlugin;
-use map::MapPlugin;
-use player::PlayerPlugin;
-use rendering::{RenderingPlugin, WINDOWSIZE};
-use resources::GlyphAssets;
-mod components;
-mod enemies;
-mod health;
-mod map;
-mod player;
-mod rendering;
-mod resources;
-mod tiles;
+use p01_rogue::{
+    components::MainCamera,
+    headless::Simulation,
+    options::{Options, USAGE},
+    rendering::{CAMERA_Z, WINDOWSIZE},
+    replay::{Recording, Replay},
+    resources::GlyphAssets,
+    ClientPlugins, GamePlugins,
+};
 
 fn main() {
     // When building for WASM, print panics to the browser console
     #[cfg(target_arch = "wasm32")]
     console_error_panic_hook::set_once();
-    App::new()
-        .add_plugins(
-            DefaultPlugins
-                .set(WindowPlugin {
-                    window: WindowDescriptor {
-                        title: "p01_3dr".to_string(),
-                        width: WINDOWSIZE.0,
-                        height: WINDOWSIZE.1,
-                        ..default()
-                    },
+    let mut options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("{}", message);
+            std::process::exit(2);
+        }
+    };
+    if options.help {
+        println!("{}", USAGE);
+        return;
+    }
+    let mut app = App::new();
+    if let Some(path) = &options.replay {
+        let recording = match Recording::load(path) {
+            Ok(recording) => recording,
+            Err(message) => {
+                eprintln!("Could not read {}: {}", path.display(), message);
+                std::process::exit(2);
+            }
+        };
+        if options.headless {
+            let mut simulation = Simulation::with_options(Options {
+                seed: Some(recording.seed),
+                generator: recording.generator,
+                size: Some(recording.size),
+                ..default()
+            });
+            simulation.run(recording.actions);
+            let report = simulation.report();
+            println!(
+                "{}",
+                ron::ser::to_string_pretty(&report, Default::default()).unwrap()
+            );
+            return;
+        }
+        // Replays always start a new game from the recorded seed
+        options.seed = Some(recording.seed);
+        options.generator = recording.generator;
+        options.size = Some(recording.size);
+        app.insert_resource(Replay::new(recording.actions));
+    }
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                window: WindowDescriptor {
+                    title: "p01_3dr".to_string(),
+                    width: WINDOWSIZE.0,
+                    height: WINDOWSIZE.1,
                     ..default()
-                })
-                .build()
-                .add_before::<bevy::asset::AssetPlugin, _>(EmbeddedAssetPlugin)
-                .set(ImagePlugin::default_nearest()),
-        )
-        .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
-        .add_startup_system(setup.at_start())
-        .add_plugin(MapPlugin)
-        .add_plugin(PlayerPlugin)
-        .add_plugin(EnemyPlugin)
-        .add_plugin(RenderingPlugin)
-        .add_plugin(HealthPlugin)
-        .run();
+                },
+                // The save plugin closes the game once the monsters are done
+                close_when_requested: false,
+                ..default()
+            })
+            .build()
+            .add_before::<bevy::asset::AssetPlugin, _>(EmbeddedAssetPlugin)
+            .set(ImagePlugin::default_nearest()),
+    )
+    .insert_resource(ClearColor(Color::rgb(0.0, 0.0, 0.0)))
+    .insert_resource(options)
+    .add_startup_system(setup.at_start())
+    .add_plugins(GamePlugins)
+    .add_plugins(ClientPlugins)
+    .run();
 }
 
 fn setup(
@@ -69,14 +103,8 @@ fn setup(
                 scaling_mode: ScalingMode::WindowSize,
                 ..default()
             },
-            transform: Transform {
-                translation: Vec3 {
-                    x: 200.0,
-                    y: 000.0,
-                    z: 0.0,
-                },
-                ..default()
-            },
+            // Moved onto the player on the first frame
+            transform: Transform::from_xyz(0.0, 0.0, CAMERA_Z),
             ..default()
         })
         .insert(MainCamera);
@@ -1,96 +1,697 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 
 use crate::{
     components::MobType,
+    enemies::Bestiary,
+    items::ItemType,
+    lighting::LightKind,
+    mapgen::{GeneratorKind, MapStats, Rect},
+    options::Options,
     tiles::{TileType, TileTypeMap},
+    turns::ACTION_COST,
+    vaults::Vaults,
 };
 
 use bevy::prelude::*;
-use map_gen_2d::{bsp::*, Point};
+use map_gen_2d::Point;
 use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+
+/// Width and height of every level, in tiles, unless another size is picked.
+pub const DEFAULT_LEVEL_SIZE: (usize, usize) = (50, 50);
+/// Smallest and largest width or height a level can be given.
+pub const MIN_LEVEL_SIDE: usize = 30;
+pub const MAX_LEVEL_SIDE: usize = 400;
+
+/// Levels built before giving up on one that is all in one piece.
+const GENERATION_ATTEMPTS: u32 = 10;
+
+/// Chance of a door being locked.
+const LOCKED_DOOR_CHANCE: f64 = 0.15;
+
+/// Chance of a room having a torch on its wall.
+const TORCH_CHANCE: f64 = 0.6;
+/// Glowing fungi on a level without rooms, at the default size.
+const FUNGI: usize = 12;
+
+/// Patches of terrain scattered over the rooms of a level at the default size:
+/// (tile, shallowest depth, most patches, most tiles in a patch).
+const TERRAIN_PATCHES: [(TileType, u32, u32, usize); 4] = [
+    (TileType::WATER, 1, 3, 12),
+    (TileType::RUBBLE, 1, 4, 6),
+    (TileType::CHASM, 2, 1, 5),
+    (TileType::LAVA, 3, 2, 8),
+];
 
 #[derive(Resource)]
 pub struct Level {
     pub tiles: HashMap<Point, TileTypeMap>,
     pub size: (usize, usize),
     pub revealed_tiles: HashSet<Point>,
-    pub rng: StdRng,
-    pub mobs: Vec<(Point,MobType)>,
+    pub rng: ChaCha12Rng,
+    pub mobs: Vec<(Point, MobType)>,
+    pub items: Vec<(Point, ItemType)>,
+    /// Torches, fungi and other lights that are part of the level.
+    pub lights: Vec<(Point, LightKind)>,
+    /// How far down the dungeon this level is, starting at 1.
+    pub depth: u32,
 }
 
 impl Level {
-    pub fn new() -> Self {
-        let rng = SeedableRng::seed_from_u64(5);
-        let map = BSPMap::new(
-            Point::new(50, 50),
-            SeedableRng::seed_from_u64(5),
-            Point::new(3, 5),
-            Point::new(10, 15),
+    /// Builds the level at `depth` at the default size, with the generator picked for that
+    /// depth and without vaults.
+    pub fn generate(seed: u64, depth: u32) -> Self {
+        Self::generate_with(
+            seed,
+            depth,
+            DEFAULT_LEVEL_SIZE,
+            GeneratorKind::for_depth(depth),
+            &Vaults::default(),
         )
-        .unwrap();
-        let mut tiles: HashMap<Point, TileTypeMap> = HashMap::new();
-        // Convert map_gen_2d tiles to p01_3dr tiles
-        for tile in map.get_tiles() {
-            match tile.1 {
-                map_gen_2d::Tile::Floor => {
-                    tiles.insert(tile.0.clone(), TileTypeMap(TileType::FLOOR));
-                }
-                map_gen_2d::Tile::Wall => {
-                    tiles.insert(tile.0.clone(), TileTypeMap(TileType::WALL));
-                }
+    }
+
+    /// Builds the level at `depth` with the given size and generator, and stamps in
+    /// whichever of `vaults` turn up. Every level has stairs down, and all but the first
+    /// have stairs back up.
+    pub fn generate_with(
+        seed: u64,
+        depth: u32,
+        size: (usize, usize),
+        generator: GeneratorKind,
+        vaults: &Vaults,
+    ) -> Self {
+        // Everything is joined up while building, but better a different level than one
+        // that can not be finished
+        let mut seed = seed;
+        let mut best: Option<(Level, Vec<Rect>, usize)> = None;
+        for _ in 0..GENERATION_ATTEMPTS {
+            let (mut level, rooms) = Self::build(seed, depth, size, generator, vaults);
+            let stats = level.stats();
+            if stats.regions <= 1 {
+                place_lights(&mut level, &rooms);
+                return level;
+            }
+            warn!(
+                "Level {} seed {} is in pieces ({:?}), building another",
+                depth, seed, stats
+            );
+            seed = level.rng.gen();
+            if best.as_ref().is_none_or(|best| stats.regions < best.2) {
+                best = Some((level, rooms, stats.regions));
             }
         }
+        let (mut level, rooms, regions) = best.unwrap();
+        error!(
+            "Level {} is still in {} pieces after {} tries, keeping it anyway",
+            depth, regions, GENERATION_ATTEMPTS
+        );
+        place_lights(&mut level, &rooms);
+        level
+    }
 
-        Level {
-            tiles: tiles,
-            size: (50, 50),
+    // One try at building a level, along with the rooms its lights go in.
+    fn build(
+        seed: u64,
+        depth: u32,
+        size: (usize, usize),
+        generator: GeneratorKind,
+        vaults: &Vaults,
+    ) -> (Self, Vec<Rect>) {
+        let mut rng = SeedableRng::seed_from_u64(seed);
+        let mut map = generator.generator().generate(size, seed);
+        let (mobs, items) = vaults.stamp(&mut map, size, depth, &mut rng);
+        map.connect_regions(size);
+
+        let mut level = Level {
+            tiles: map.tiles,
+            size,
             revealed_tiles: HashSet::new(),
             rng,
+            mobs,
+            items,
+            lights: Vec::new(),
+            depth,
+        };
+        // Caves have no rooms to close off
+        if !map.rooms.is_empty() {
+            place_doors(&mut level, &map.vaults);
+        }
+        place_terrain(&mut level, &map.vaults);
+        let down = random_floor_point(&mut level);
+        level.tiles.insert(down, TileTypeMap(TileType::DOWNSTAIRS));
+        if depth > 1 {
+            let up = random_floor_point(&mut level);
+            level.tiles.insert(up, TileTypeMap(TileType::UPSTAIRS));
+        }
+        (level, map.rooms)
+    }
+
+    /// Counts the areas of the level that can not be reached from each other, going
+    /// through doors whether they are locked or not, and how much of it is open.
+    pub fn stats(&self) -> MapStats {
+        MapStats::of(self.size, |point| {
+            let (x, y) = (point.x as i32, point.y as i32);
+            self.is_passable(x, y) || self.tile_at(x, y) == Some(TileType::LOCKEDDOOR)
+        })
+    }
+
+    /// Returns where the first tile of the given type is, if there is one.
+    pub fn find_tile(&self, tile_type: TileType) -> Option<Point> {
+        self.tiles
+            .iter()
+            .find(|tile| tile.1 .0 == tile_type)
+            .map(|tile| *tile.0)
+    }
+
+    /// Returns true if a mob could stand on the tile at (x, y).
+    pub fn is_walkable(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 {
+            return false;
+        }
+        matches!(
+            self.tiles.get(&Point::new(x as usize, y as usize)),
+            Some(TileTypeMap(
+                TileType::FLOOR
+                    | TileType::DOWNSTAIRS
+                    | TileType::UPSTAIRS
+                    | TileType::OPENDOOR
+                    | TileType::WATER
+                    | TileType::RUBBLE
+            ))
+        )
+    }
+
+    /// Energy it takes to step onto the tile at (x, y).
+    pub fn move_cost(&self, x: i32, y: i32) -> i32 {
+        match self.tile_at(x, y) {
+            Some(TileType::WATER) => ACTION_COST * 3 / 2,
+            Some(TileType::RUBBLE) => ACTION_COST * 2,
+            _ => ACTION_COST,
+        }
+    }
+
+    /// Returns true if a mob could get onto the tile at (x, y), opening a door if it has to.
+    /// Lava and chasms are left out, nothing walks into those on purpose.
+    pub fn is_passable(&self, x: i32, y: i32) -> bool {
+        self.is_walkable(x, y) || self.tile_at(x, y) == Some(TileType::CLOSEDDOOR)
+    }
+
+    /// Like `is_passable`, but the player may also step into lava or a chasm. Anything outside
+    /// of the map blocks.
+    pub fn is_passable_for_player(&self, x: i32, y: i32) -> bool {
+        self.is_passable(x, y)
+            || matches!(self.tile_at(x, y), Some(TileType::LAVA | TileType::CHASM))
+    }
+
+    pub fn tile_at(&self, x: i32, y: i32) -> Option<TileType> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        self.tiles
+            .get(&Point::new(x as usize, y as usize))
+            .map(|tile| tile.0)
+    }
+
+    /// Returns true if the tile at (x, y) can not be seen through.
+    /// Anything outside of the map blocks sight.
+    pub fn blocks_sight(&self, x: i32, y: i32) -> bool {
+        !self.is_walkable(x, y)
+            && !matches!(self.tile_at(x, y), Some(TileType::LAVA | TileType::CHASM))
+    }
+
+    /// Builds a level by hand. '#' is a wall, '+' a closed door, '=' a locked door,
+    /// '\'' an open door, '~' water, '^' lava, '_' a chasm, ';' rubble and anything else is
+    /// floor. The first row is y = 0.
+    pub fn from_ascii(rows: &[&str]) -> Self {
+        let mut tiles = HashMap::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, glyph) in row.chars().enumerate() {
+                let tile = match glyph {
+                    '#' => TileType::WALL,
+                    '+' => TileType::CLOSEDDOOR,
+                    '=' => TileType::LOCKEDDOOR,
+                    '\'' => TileType::OPENDOOR,
+                    '~' => TileType::WATER,
+                    '^' => TileType::LAVA,
+                    '_' => TileType::CHASM,
+                    ';' => TileType::RUBBLE,
+                    _ => TileType::FLOOR,
+                };
+                tiles.insert(Point::new(x, y), TileTypeMap(tile));
+            }
+        }
+        Level {
+            tiles,
+            size: (rows[0].len(), rows.len()),
+            revealed_tiles: HashSet::new(),
+            rng: SeedableRng::seed_from_u64(0),
             mobs: Vec::new(),
+            items: Vec::new(),
+            lights: Vec::new(),
+            depth: 1,
         }
     }
 }
 
+/// Sent when a tile of the current level changes, e.g. a door opening.
+pub struct TileChanged(pub Point);
+
 // ============================
 // ========== PLUGIN ==========
 pub struct MapPlugin;
 impl Plugin for MapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup.at_start());
+        app.add_event::<TileChanged>()
+            .add_startup_system(setup.at_start());
     }
 }
 
-fn setup(mut commands: Commands) {
-    let mut level = Level::new();
-    // Randomly select one point for player
-    place_at_random_valid_point(&mut level, MobType::PLAYER);
-    // Randomly select 4 spots for goblins
-    for _ in 0..4 {
-        place_at_random_valid_point(&mut level, MobType::GOBLIN);
+fn setup(
+    mut commands: Commands,
+    options: Res<Options>,
+    bestiary: Res<Bestiary>,
+    vaults: Res<Vaults>,
+    loaded: Option<Res<Level>>,
+) {
+    // A saved game has been loaded already
+    if loaded.is_some() {
+        return;
+    }
+    let seed = options.seed.unwrap_or_else(random);
+    info!("Dungeon seed {}", seed);
+    let mut dungeon = Dungeon::new(seed);
+    dungeon.generator = options.generator;
+    if let Some(size) = options.size {
+        dungeon.size = size;
     }
+    commands.insert_resource(dungeon.take_level(1, &bestiary, &vaults));
+    commands.insert_resource(dungeon);
+}
 
-    // Randomly select 2 spots for orcs
-    for _ in 0..2 {
-        place_at_random_valid_point(&mut level, MobType::ORC);
+/// Levels the player is not on right now, kept so they are the same when the player comes back.
+#[derive(Resource)]
+pub struct Dungeon {
+    /// Seed the dungeon was started from. Every level gets a seed of its own from it.
+    pub seed: u64,
+    /// Builds every level when set, instead of picking one by depth.
+    pub generator: Option<GeneratorKind>,
+    /// Width and height of every level, in tiles.
+    pub size: (usize, usize),
+    pub levels: HashMap<u32, Level>,
+}
+
+impl Dungeon {
+    pub fn new(seed: u64) -> Self {
+        Dungeon {
+            seed,
+            generator: None,
+            size: DEFAULT_LEVEL_SIZE,
+            levels: HashMap::new(),
+        }
+    }
+
+    /// Takes the stored level at `depth` out of the dungeon, or builds a new one.
+    pub fn take_level(&mut self, depth: u32, bestiary: &Bestiary, vaults: &Vaults) -> Level {
+        self.levels.remove(&depth).unwrap_or_else(|| {
+            let seed = self.level_seed(depth);
+            let generator = self
+                .generator
+                .unwrap_or_else(|| GeneratorKind::for_depth(depth));
+            let mut level = Level::generate_with(seed, depth, self.size, generator, vaults);
+            populate(&mut level, bestiary);
+            level
+        })
+    }
+
+    /// Seed of the level at `depth`. Drawn from a stream of its own, so neighbouring dungeon
+    /// seeds do not share levels one depth apart.
+    fn level_seed(&self, depth: u32) -> u64 {
+        let mut rng = ChaCha12Rng::seed_from_u64(self.seed);
+        rng.set_stream(depth as u64);
+        rng.next_u64()
+    }
+
+    pub fn store_level(&mut self, level: Level) {
+        self.levels.insert(level.depth, level);
     }
-    commands.insert_resource(level);
+}
+
+/// Number of monsters on a level at the default size.
+pub fn monster_count(depth: u32) -> u32 {
+    4 + (depth - 1) / 2 + 2 * depth
+}
+
+/// `count` for a level of `size`, so bigger levels get as much per tile as the default size.
+/// Never less than 1.
+pub fn scaled(count: usize, size: (usize, usize)) -> usize {
+    let area = (size.0 * size.1) as f64 / (DEFAULT_LEVEL_SIZE.0 * DEFAULT_LEVEL_SIZE.1) as f64;
+    ((count as f64 * area).round() as usize).max(1)
+}
+
+/// Picks starting spots for the player and monsters.
+pub fn populate(level: &mut Level, bestiary: &Bestiary) {
+    // The player arrives by the stairs, or anywhere on the first level
+    match level.find_tile(TileType::UPSTAIRS) {
+        Some(stairs) => level.mobs.push((stairs, MobType::PLAYER)),
+        None => place_at_random_valid_point(level, MobType::PLAYER),
+    }
+    for _ in 0..scaled(monster_count(level.depth) as usize, level.size) {
+        let Some(monster) = bestiary.choose(level.depth, &mut level.rng) else {
+            break;
+        };
+        let kind = MobType::MONSTER(monster.name.clone());
+        place_at_random_valid_point(level, kind);
+    }
+
+    // A key for every locked door, somewhere the player can get to without one
+    let locked = level
+        .tiles
+        .values()
+        .filter(|tile| tile.0 == TileType::LOCKEDDOOR)
+        .count();
+    let start = level
+        .mobs
+        .iter()
+        .find(|mob| mob.1 == MobType::PLAYER)
+        .map(|mob| mob.0)
+        .unwrap();
+    let reachable = reachable_floor(level, start);
+    for _ in 0..locked {
+        let point = *reachable.choose(&mut level.rng).unwrap();
+        level.items.push((point, ItemType::KEY));
+    }
+
+    // Scatter a few items around
+    for _ in 0..scaled(5, level.size) {
+        let item = *[ItemType::POTION, ItemType::SCROLL, ItemType::DAGGER]
+            .choose(&mut level.rng)
+            .unwrap();
+        let point = random_floor_point(level);
+        level.items.push((point, item));
+    }
+}
+
+// A floor tile between two walls, with a room on at least one side, is a doorway.
+fn is_doorway(level: &Level, x: i32, y: i32) -> bool {
+    let wall = |dx: i32, dy: i32| level.tile_at(x + dx, y + dy) == Some(TileType::WALL);
+    let open = |dx: i32, dy: i32| level.is_walkable(x + dx, y + dy);
+    if level.tile_at(x, y) != Some(TileType::FLOOR) {
+        return false;
+    }
+    // (along, across) for passages running up/down and left/right
+    [((0, 1), (1, 0)), ((1, 0), (0, 1))]
+        .iter()
+        .any(|&((ax, ay), (cx, cy))| {
+            wall(cx, cy)
+                && wall(-cx, -cy)
+                && open(ax, ay)
+                && open(-ax, -ay)
+                && [1, -1].iter().any(|&side| {
+                    // The tile past the end is open to both sides
+                    let (ex, ey) = (ax * side, ay * side);
+                    open(ex + cx, ey + cy) && open(ex - cx, ey - cy)
+                })
+        })
+}
+
+// Puts a door in every doorway outside of the vaults. Some of them are locked.
+fn place_doors(level: &mut Level, vaults: &[Rect]) {
+    let width = level
+        .tiles
+        .keys()
+        .map(|point| point.x + 1)
+        .max()
+        .unwrap_or(0);
+    let height = level
+        .tiles
+        .keys()
+        .map(|point| point.y + 1)
+        .max()
+        .unwrap_or(0);
+    // Row by row so the same seed always gives the same doors
+    for y in 0..height as i32 {
+        for x in 0..width as i32 {
+            let next_to_door = (-1..=1).any(|dy| {
+                (-1..=1).any(|dx| {
+                    matches!(
+                        level.tile_at(x + dx, y + dy),
+                        Some(TileType::CLOSEDDOOR | TileType::LOCKEDDOOR)
+                    )
+                })
+            });
+            let point = Point::new(x as usize, y as usize);
+            if next_to_door
+                || !is_doorway(level, x, y)
+                || vaults.iter().any(|vault| vault.contains(point))
+            {
+                continue;
+            }
+            let door = if level.rng.gen_bool(LOCKED_DOOR_CHANCE) {
+                TileType::LOCKEDDOOR
+            } else {
+                TileType::CLOSEDDOOR
+            };
+            level.tiles.insert(point, TileTypeMap(door));
+        }
+    }
+}
+
+// Scatters water, rubble, chasms and lava over the rooms, leaving the vaults as they were
+// made. The deeper, the more dangerous.
+fn place_terrain(level: &mut Level, vaults: &[Rect]) {
+    // Where patches can start. Terrain never makes other tiles open or closed, so only the
+    // patches that are kept need to come off
+    let mut open: Vec<Point> = level
+        .tiles
+        .keys()
+        .filter(|point| is_open_floor(level, point.x as i32, point.y as i32))
+        .filter(|point| !vaults.iter().any(|vault| vault.contains(**point)))
+        .copied()
+        .collect();
+    open.sort_by_key(|point| (point.y, point.x));
+    for (tile, min_depth, most_patches, most_tiles) in TERRAIN_PATCHES {
+        if level.depth < min_depth {
+            continue;
+        }
+        let most_patches = scaled(most_patches as usize, level.size) as u32;
+        for _ in 0..level.rng.gen_range(0..=most_patches) {
+            let patch = grow_patch(level, &open, most_tiles, vaults);
+            if patch.is_empty() {
+                break;
+            }
+            for point in patch.iter() {
+                level.tiles.insert(*point, TileTypeMap(tile));
+            }
+            // Nothing may be cut off by a patch that can not be walked through
+            if !level.is_passable(patch[0].x as i32, patch[0].y as i32) && cuts_level(level, &patch)
+            {
+                for point in patch {
+                    level.tiles.insert(point, TileTypeMap(TileType::FLOOR));
+                }
+            } else {
+                open.retain(|point| !patch.contains(point));
+            }
+        }
+    }
+}
+
+// Hangs torches on the walls of some of the rooms. Levels without rooms get glowing fungi
+// on the floor instead.
+fn place_lights(level: &mut Level, rooms: &[Rect]) {
+    if rooms.is_empty() {
+        for _ in 0..scaled(FUNGI, level.size) {
+            let point = random_floor_point(level);
+            level.lights.push((point, LightKind::FUNGUS));
+        }
+        return;
+    }
+    for room in rooms {
+        if !level.rng.gen_bool(TORCH_CHANCE) {
+            continue;
+        }
+        // The torch hangs on a wall of the room, but it lights the room from the floor in
+        // front of it, so none of its light gets through to the other side of the wall
+        let fronts: Vec<Point> = (room.y..room.y + room.height)
+            .flat_map(|y| (room.x..room.x + room.width).map(move |x| Point::new(x, y)))
+            .filter(|point| level.is_walkable(point.x as i32, point.y as i32))
+            .filter(|point| {
+                [(0, 1), (-1, 0), (0, -1), (1, 0)].iter().any(|(dx, dy)| {
+                    level.tile_at(point.x as i32 + dx, point.y as i32 + dy) == Some(TileType::WALL)
+                })
+            })
+            .collect();
+        if let Some(&torch) = fronts.choose(&mut level.rng) {
+            level.lights.push((torch, LightKind::TORCH));
+        }
+    }
+}
+
+// Floor inside a room, away from walls and doors.
+fn is_open_floor(level: &Level, x: i32, y: i32) -> bool {
+    level.tile_at(x, y) == Some(TileType::FLOOR)
+        && (-1..=1).all(|dy| {
+            (-1..=1).all(|dx| {
+                !matches!(
+                    level.tile_at(x + dx, y + dy),
+                    None | Some(
+                        TileType::WALL
+                            | TileType::OPENDOOR
+                            | TileType::CLOSEDDOOR
+                            | TileType::LOCKEDDOOR
+                    )
+                )
+            })
+        })
+}
+
+// A random blob of up to `most_tiles` open floor tiles, starting from one of `open`. Empty if
+// there is no room for one.
+fn grow_patch(level: &mut Level, open: &[Point], most_tiles: usize, vaults: &[Rect]) -> Vec<Point> {
+    let outside_vaults = |point: &Point| !vaults.iter().any(|vault| vault.contains(*point));
+    let Some(&start) = open.choose(&mut level.rng) else {
+        return Vec::new();
+    };
+    let mut patch = vec![start];
+    let size = level.rng.gen_range(1..=most_tiles);
+    // Spread out from random tiles already in the patch, giving up after a while
+    for _ in 0..size * 4 {
+        if patch.len() == size {
+            break;
+        }
+        let from = patch[level.rng.gen_range(0..patch.len())];
+        let (dx, dy) = [(0, 1), (-1, 0), (0, -1), (1, 0)][level.rng.gen_range(0..4)];
+        let (x, y) = (from.x as i32 + dx, from.y as i32 + dy);
+        let next = Point::new(x as usize, y as usize);
+        if is_open_floor(level, x, y) && outside_vaults(&next) && !patch.contains(&next) {
+            patch.push(next);
+        }
+    }
+    patch
+}
+
+// Returns true if the tiles of `patch` cut the level in pieces. The level was in one piece
+// before, so it is enough that the tiles around the patch can still reach each other, which
+// is usually found out long before the whole level has been looked at.
+fn cuts_level(level: &Level, patch: &[Point]) -> bool {
+    let is_open = |x: i32, y: i32| {
+        level.is_passable(x, y) || level.tile_at(x, y) == Some(TileType::LOCKEDDOOR)
+    };
+    let neighbours = |point: Point| {
+        [(0, 1), (-1, 0), (0, -1), (1, 0)]
+            .map(|(dx, dy)| (point.x as i32 + dx, point.y as i32 + dy))
+    };
+    let mut around: HashSet<Point> = patch
+        .iter()
+        .flat_map(|&point| neighbours(point))
+        .filter(|&(x, y)| is_open(x, y))
+        .map(|(x, y)| Point::new(x as usize, y as usize))
+        .collect();
+    let Some(&start) = around.iter().next() else {
+        return false;
+    };
+    // Breadth first, so the tiles close by are found first
+    let mut seen = HashSet::from([start]);
+    let mut next = VecDeque::from([start]);
+    while let Some(point) = next.pop_front() {
+        around.remove(&point);
+        if around.is_empty() {
+            return false;
+        }
+        for (x, y) in neighbours(point) {
+            if is_open(x, y) && seen.insert(Point::new(x as usize, y as usize)) {
+                next.push_back(Point::new(x as usize, y as usize));
+            }
+        }
+    }
+    true
+}
+
+// Floor tiles that can be reached from `start` without going through a locked door, in order.
+fn reachable_floor(level: &Level, start: Point) -> Vec<Point> {
+    let mut seen = HashSet::from([start]);
+    let mut open = vec![start];
+    while let Some(point) = open.pop() {
+        for (dx, dy) in [(0, 1), (-1, 0), (0, -1), (1, 0)] {
+            let (x, y) = (point.x as i32 + dx, point.y as i32 + dy);
+            if level.is_passable(x, y) && seen.insert(Point::new(x as usize, y as usize)) {
+                open.push(Point::new(x as usize, y as usize));
+            }
+        }
+    }
+    let mut floor: Vec<Point> = seen
+        .into_iter()
+        .filter(|point| level.tiles.get(point).map(|tile| tile.0) == Some(TileType::FLOOR))
+        .collect();
+    floor.sort_by_key(|point| (point.y, point.x));
+    floor
 }
 
 fn place_at_random_valid_point(level: &mut Level, mob: MobType) {
+    let point = random_floor_point(level);
+    level.mobs.push((point, mob));
+}
+
+pub fn random_floor_point(level: &mut Level) -> Point {
     let mut try_valid = false;
     let mut x: usize = 0;
     let mut y: usize = 0;
     while !try_valid {
         x = level.rng.gen_range(1..level.size.0);
         y = level.rng.gen_range(1..level.size.1);
-        match level.tiles.get(&Point { x, y }) {
-            Some(tile) => {
-                if tile.0 == TileType::FLOOR {
-                    try_valid = true;
-                }
+        if let Some(TileTypeMap(TileType::FLOOR)) = level.tiles.get(&Point { x, y }) {
+            try_valid = true;
+        }
+    }
+    Point { x, y }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn neighbouring_seeds_do_not_share_levels() {
+        let seeds: HashSet<u64> = (0..20)
+            .flat_map(|seed| (1..=5).map(move |depth| Dungeon::new(seed).level_seed(depth)))
+            .collect();
+        assert_eq!(seeds.len(), 20 * 5);
+        assert_eq!(Dungeon::new(7).level_seed(3), Dungeon::new(7).level_seed(3));
+    }
+
+    #[test]
+    fn every_generator_builds_levels_in_one_piece() {
+        for generator in GeneratorKind::ALL {
+            for seed in 0..5 {
+                let level = Level::generate_with(
+                    seed,
+                    2,
+                    DEFAULT_LEVEL_SIZE,
+                    generator,
+                    &Vaults::default(),
+                );
+                assert_eq!(level.stats().regions, 1, "{:?} seed {}", generator, seed);
+            }
+        }
+    }
+
+    #[test]
+    fn torches_light_the_room_from_in_front_of_their_wall() {
+        let mut torches = 0;
+        for seed in 0..5 {
+            let level = Level::generate(seed, 1);
+            for (point, _) in level
+                .lights
+                .iter()
+                .filter(|light| light.1 == LightKind::TORCH)
+            {
+                let (x, y) = (point.x as i32, point.y as i32);
+                assert!(level.is_walkable(x, y));
+                assert!([(0, 1), (-1, 0), (0, -1), (1, 0)]
+                    .iter()
+                    .any(|(dx, dy)| level.tile_at(x + dx, y + dy) == Some(TileType::WALL)));
+                torches += 1;
             }
-            _ => {}
         }
+        assert!(torches > 0);
     }
-    level.mobs.push((Point{x,y}, mob.clone()));
 }
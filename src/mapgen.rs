@@ -0,0 +1,880 @@
+// Map generators. Each one lays out the walls and floor of a level from a seed, and says
+// where its rooms and corridors are. Level::generate puts doors, terrain and stairs on top.
+// Which generator builds a level depends on its depth, unless one is picked with --generator.
+// https://www.roguebasin.com/index.php/Cellular_Automata_Method_for_Generating_Random_Cave-Like_Levels
+// https://journal.stuffwithstuff.com/2014/12/21/rooms-and-mazes/
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt,
+    str::FromStr,
+};
+
+use map_gen_2d::{bsp::BSPMap, Point};
+use rand::prelude::*;
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::tiles::{TileType, TileTypeMap};
+
+const DIRECTIONS: [(i32, i32); 4] = [(0, 1), (-1, 0), (0, -1), (1, 0)];
+
+/// A rectangle of tiles, e.g. a room.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub fn contains(&self, point: Point) -> bool {
+        (self.x..self.x + self.width).contains(&point.x)
+            && (self.y..self.y + self.height).contains(&point.y)
+    }
+
+    /// Returns true if the rectangles overlap or are less than `gap` tiles apart.
+    pub fn is_near(&self, other: &Rect, gap: usize) -> bool {
+        self.x < other.x + other.width + gap
+            && other.x < self.x + self.width + gap
+            && self.y < other.y + other.height + gap
+            && other.y < self.y + self.height + gap
+    }
+}
+
+/// The layout a generator came up with.
+pub struct GeneratedMap {
+    pub tiles: HashMap<Point, TileTypeMap>,
+    /// The rooms, or the rectangle around them for rooms that are not square.
+    /// Empty for maps without rooms, like caves.
+    pub rooms: Vec<Rect>,
+    /// Floor tiles of the passages between rooms.
+    pub corridors: Vec<Point>,
+    /// Where vaults were stamped in. Nothing else is put there after.
+    pub vaults: Vec<Rect>,
+}
+
+impl GeneratedMap {
+    pub fn stats(&self, size: (usize, usize)) -> MapStats {
+        MapStats::of(size, |point| self.is_open(point))
+    }
+
+    /// Digs tunnels between areas that can not be reached from each other, joining each to
+    /// the closest part of the biggest area until there is only one left. Tunnels go around
+    /// vaults, which can only be got into where they are open at the edge.
+    pub fn connect_regions(&mut self, size: (usize, usize)) {
+        loop {
+            let regions = find_regions(size, |point| self.is_open(point));
+            if regions.len() < 2 {
+                return;
+            }
+            let main: HashSet<Point> = regions[0].iter().copied().collect();
+            // A vault with no way in has to be dug into after all
+            let Some(tunnel) = self
+                .find_tunnel(size, &regions[1], &main, false)
+                .or_else(|| self.find_tunnel(size, &regions[1], &main, true))
+            else {
+                return;
+            };
+            for point in tunnel {
+                self.tiles.insert(point, TileTypeMap(TileType::FLOOR));
+                self.corridors.push(point);
+                self.wall_in(point);
+            }
+        }
+    }
+
+    // The shortest way through rock from `region` to `main`, leaving out the ends.
+    fn find_tunnel(
+        &self,
+        size: (usize, usize),
+        region: &[Point],
+        main: &HashSet<Point>,
+        through_vaults: bool,
+    ) -> Option<Vec<Point>> {
+        let mut came_from: HashMap<Point, Option<Point>> =
+            region.iter().map(|point| (*point, None)).collect();
+        let mut open: VecDeque<Point> = region.iter().copied().collect();
+        while let Some(point) = open.pop_front() {
+            for (dx, dy) in DIRECTIONS {
+                let (x, y) = (point.x as i32 + dx, point.y as i32 + dy);
+                // The edge of the map stays wall
+                if x < 1 || y < 1 || x as usize >= size.0 - 1 || y as usize >= size.1 - 1 {
+                    continue;
+                }
+                let next = Point::new(x as usize, y as usize);
+                if main.contains(&next) {
+                    let mut tunnel = Vec::new();
+                    let mut at = came_from[&point];
+                    if !region.contains(&point) {
+                        tunnel.push(point);
+                    }
+                    while let Some(from) = at {
+                        if !region.contains(&from) {
+                            tunnel.push(from);
+                        }
+                        at = came_from[&from];
+                    }
+                    return Some(tunnel);
+                }
+                let in_vault = self.vaults.iter().any(|vault| vault.contains(next));
+                if came_from.contains_key(&next) || (in_vault && !through_vaults) {
+                    continue;
+                }
+                came_from.insert(next, Some(point));
+                open.push_back(next);
+            }
+        }
+        None
+    }
+
+    // Puts walls around a tile where there is nothing yet.
+    fn wall_in(&mut self, point: Point) {
+        for (dx, dy) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy))) {
+            let (x, y) = (point.x as i32 + dx, point.y as i32 + dy);
+            if x >= 0 && y >= 0 {
+                self.tiles
+                    .entry(Point::new(x as usize, y as usize))
+                    .or_insert(TileTypeMap(TileType::WALL));
+            }
+        }
+    }
+
+    /// Anything a mob can get through, with or without a key.
+    pub fn is_open(&self, point: Point) -> bool {
+        matches!(
+            self.tiles.get(&point).map(|tile| tile.0),
+            Some(
+                TileType::FLOOR
+                    | TileType::DOWNSTAIRS
+                    | TileType::UPSTAIRS
+                    | TileType::OPENDOOR
+                    | TileType::CLOSEDDOOR
+                    | TileType::LOCKEDDOOR
+                    | TileType::WATER
+                    | TileType::RUBBLE
+            )
+        )
+    }
+}
+
+/// How a map turned out, to check that it can be played.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MapStats {
+    /// Areas that can not be reached from each other. A good map has exactly one.
+    pub regions: usize,
+    /// How much of the map can be walked around in, from 0 to 1.
+    pub floor_ratio: f64,
+}
+
+impl MapStats {
+    /// Stats for a map of `size`, counting the tiles `is_open` says can be walked through.
+    pub fn of(size: (usize, usize), is_open: impl Fn(Point) -> bool) -> MapStats {
+        let regions = find_regions(size, is_open);
+        let open: usize = regions.iter().map(|region| region.len()).sum();
+        MapStats {
+            regions: regions.len(),
+            floor_ratio: open as f64 / (size.0 * size.1).max(1) as f64,
+        }
+    }
+}
+
+/// Splits the tiles of a map of `size` that `is_open` says can be walked through into
+/// areas that can only be reached from inside, biggest first.
+pub fn find_regions(size: (usize, usize), is_open: impl Fn(Point) -> bool) -> Vec<Vec<Point>> {
+    let mut seen: HashSet<Point> = HashSet::new();
+    let mut regions: Vec<Vec<Point>> = Vec::new();
+    // Row by row, so regions of the same size always come out in the same order
+    for y in 0..size.1 {
+        for x in 0..size.0 {
+            let start = Point::new(x, y);
+            if !is_open(start) || !seen.insert(start) {
+                continue;
+            }
+            let mut region = vec![start];
+            let mut open = vec![start];
+            while let Some(point) = open.pop() {
+                for (dx, dy) in DIRECTIONS {
+                    let (x, y) = (point.x as i32 + dx, point.y as i32 + dy);
+                    if x < 0 || y < 0 || x as usize >= size.0 || y as usize >= size.1 {
+                        continue;
+                    }
+                    let next = Point::new(x as usize, y as usize);
+                    if is_open(next) && seen.insert(next) {
+                        region.push(next);
+                        open.push(next);
+                    }
+                }
+            }
+            regions.push(region);
+        }
+    }
+    regions.sort_by_key(|region| std::cmp::Reverse(region.len()));
+    regions
+}
+
+pub trait MapGenerator {
+    /// Lays out a map of `size` tiles. The same seed always gives the same map.
+    fn generate(&self, size: (usize, usize), seed: u64) -> GeneratedMap;
+}
+
+/// The generators there are, by the name used with --generator.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GeneratorKind {
+    Bsp,
+    Caves,
+    DrunkardsWalk,
+    RoomsAndMazes,
+}
+
+impl GeneratorKind {
+    pub const ALL: [GeneratorKind; 4] = [
+        GeneratorKind::Bsp,
+        GeneratorKind::Caves,
+        GeneratorKind::DrunkardsWalk,
+        GeneratorKind::RoomsAndMazes,
+    ];
+
+    /// The generator for a level at `depth`, when none was picked.
+    /// The first levels are always rooms, after that every floor looks different from the last.
+    pub fn for_depth(depth: u32) -> GeneratorKind {
+        const DEEPER: [GeneratorKind; 4] = [
+            GeneratorKind::RoomsAndMazes,
+            GeneratorKind::Caves,
+            GeneratorKind::DrunkardsWalk,
+            GeneratorKind::Bsp,
+        ];
+        match depth {
+            0..=2 => GeneratorKind::Bsp,
+            _ => DEEPER[(depth as usize - 3) % DEEPER.len()],
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GeneratorKind::Bsp => "bsp",
+            GeneratorKind::Caves => "caves",
+            GeneratorKind::DrunkardsWalk => "drunkard",
+            GeneratorKind::RoomsAndMazes => "mazes",
+        }
+    }
+
+    pub fn generator(&self) -> Box<dyn MapGenerator> {
+        match self {
+            GeneratorKind::Bsp => Box::new(Bsp::default()),
+            GeneratorKind::Caves => Box::new(Caves::default()),
+            GeneratorKind::DrunkardsWalk => Box::new(DrunkardsWalk::default()),
+            GeneratorKind::RoomsAndMazes => Box::new(RoomsAndMazes::default()),
+        }
+    }
+}
+
+impl fmt::Display for GeneratorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for GeneratorKind {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        GeneratorKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == text)
+            .ok_or_else(|| {
+                let names: Vec<&str> = GeneratorKind::ALL.iter().map(|kind| kind.name()).collect();
+                format!(
+                    "unknown generator '{}', expected one of {}",
+                    text,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// Rectangular rooms from binary space partitioning, joined by L shaped corridors.
+pub struct Bsp {
+    pub min_room_size: (usize, usize),
+    pub max_room_size: (usize, usize),
+}
+
+impl Default for Bsp {
+    fn default() -> Self {
+        Bsp {
+            min_room_size: (3, 5),
+            max_room_size: (10, 15),
+        }
+    }
+}
+
+impl MapGenerator for Bsp {
+    fn generate(&self, size: (usize, usize), seed: u64) -> GeneratedMap {
+        let map = BSPMap::new(
+            Point::new(size.0, size.1),
+            SeedableRng::seed_from_u64(seed),
+            Point::new(self.min_room_size.0, self.min_room_size.1),
+            Point::new(self.max_room_size.0, self.max_room_size.1),
+        )
+        .unwrap();
+        let mut tiles: HashMap<Point, TileTypeMap> = HashMap::new();
+        // Convert map_gen_2d tiles to p01_3dr tiles
+        for tile in map.get_tiles() {
+            match tile.1 {
+                map_gen_2d::Tile::Floor => {
+                    tiles.insert(*tile.0, TileTypeMap(TileType::FLOOR));
+                }
+                map_gen_2d::Tile::Wall => {
+                    tiles.insert(*tile.0, TileTypeMap(TileType::WALL));
+                }
+            }
+        }
+        // BSPMap keeps its rooms to itself, so find them again
+        let (rooms, corridors) = find_rooms(&tiles);
+        GeneratedMap {
+            tiles,
+            rooms,
+            corridors,
+            vaults: Vec::new(),
+        }
+    }
+}
+
+/// Open caves from a cellular automaton. Only the biggest cave is kept.
+pub struct Caves {
+    /// Chance of a tile starting out as wall.
+    pub wall_chance: f64,
+    /// Times the walls are smoothed out.
+    pub smoothing: u32,
+}
+
+impl Default for Caves {
+    fn default() -> Self {
+        Caves {
+            wall_chance: 0.45,
+            smoothing: 5,
+        }
+    }
+}
+
+impl MapGenerator for Caves {
+    fn generate(&self, size: (usize, usize), seed: u64) -> GeneratedMap {
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+        let mut grid = Grid::new(size);
+        for (x, y) in grid.inside() {
+            grid.set(x, y, !rng.gen_bool(self.wall_chance));
+        }
+        for _ in 0..self.smoothing {
+            let mut next = Grid::new(size);
+            for (x, y) in grid.inside() {
+                // A tile surrounded by walls becomes wall, anything else opens up
+                let walls = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                    .filter(|(dx, dy)| !grid.is_floor(x as i32 + dx, y as i32 + dy))
+                    .count();
+                next.set(x, y, walls < 5);
+            }
+            grid = next;
+        }
+        grid.keep_largest_region();
+        grid.into_map(Vec::new(), Vec::new())
+    }
+}
+
+/// Winding tunnels dug by walkers stumbling around at random.
+pub struct DrunkardsWalk {
+    /// How much of the map is dug out before the walkers stop.
+    pub floor_ratio: f64,
+    /// Steps a walker takes before it passes out.
+    pub steps: usize,
+}
+
+impl Default for DrunkardsWalk {
+    fn default() -> Self {
+        DrunkardsWalk {
+            floor_ratio: 0.4,
+            steps: 400,
+        }
+    }
+}
+
+impl MapGenerator for DrunkardsWalk {
+    fn generate(&self, size: (usize, usize), seed: u64) -> GeneratedMap {
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+        let mut grid = Grid::new(size);
+        let wanted = (grid.inside().count() as f64 * self.floor_ratio) as usize;
+        let mut dug = vec![Point::new(size.0 / 2, size.1 / 2)];
+        grid.set(size.0 / 2, size.1 / 2, true);
+        while dug.len() < wanted {
+            // Every walker starts somewhere already dug, so it is all one cave
+            let mut at = *dug.choose(&mut rng).unwrap();
+            for _ in 0..self.steps {
+                let (dx, dy) = *DIRECTIONS.choose(&mut rng).unwrap();
+                let (x, y) = (at.x as i32 + dx, at.y as i32 + dy);
+                if x < 1 || y < 1 || x >= size.0 as i32 - 1 || y >= size.1 as i32 - 1 {
+                    continue;
+                }
+                at = Point::new(x as usize, y as usize);
+                if !grid.is_floor(x, y) {
+                    grid.set(at.x, at.y, true);
+                    dug.push(at);
+                    if dug.len() == wanted {
+                        break;
+                    }
+                }
+            }
+        }
+        grid.into_map(Vec::new(), Vec::new())
+    }
+}
+
+/// Rooms scattered around with a maze filling the space in between, joined up and with the
+/// dead ends of the maze filled back in.
+pub struct RoomsAndMazes {
+    /// Times to try placing a room. Rooms that do not fit are skipped.
+    pub room_attempts: u32,
+    /// Largest room side, rooms are between 3 and this many tiles across.
+    pub max_room_size: usize,
+    /// Chance of opening an extra way between two areas that are already joined, so there
+    /// is more than one way around.
+    pub extra_connector_chance: f64,
+}
+
+impl Default for RoomsAndMazes {
+    fn default() -> Self {
+        RoomsAndMazes {
+            room_attempts: 80,
+            max_room_size: 9,
+            extra_connector_chance: 0.04,
+        }
+    }
+}
+
+impl MapGenerator for RoomsAndMazes {
+    fn generate(&self, size: (usize, usize), seed: u64) -> GeneratedMap {
+        let mut rng = ChaCha12Rng::seed_from_u64(seed);
+        let mut grid = Grid::new(size);
+        // Rooms and maze cells sit on odd coordinates, with walls on the even ones
+        let mut region: HashMap<Point, usize> = HashMap::new();
+        let mut regions = 0;
+
+        let mut rooms: Vec<Rect> = Vec::new();
+        for _ in 0..self.room_attempts {
+            let width = rng.gen_range(1..=self.max_room_size / 2) * 2 + 1;
+            let height = rng.gen_range(1..=self.max_room_size / 2) * 2 + 1;
+            if width + 2 > size.0 || height + 2 > size.1 {
+                continue;
+            }
+            let room = Rect {
+                x: rng.gen_range(0..(size.0 - width) / 2) * 2 + 1,
+                y: rng.gen_range(0..(size.1 - height) / 2) * 2 + 1,
+                width,
+                height,
+            };
+            if room.x + room.width >= size.0 - 1
+                || room.y + room.height >= size.1 - 1
+                || rooms.iter().any(|other| room.is_near(other, 1))
+            {
+                continue;
+            }
+            for y in room.y..room.y + room.height {
+                for x in room.x..room.x + room.width {
+                    grid.set(x, y, true);
+                    region.insert(Point::new(x, y), regions);
+                }
+            }
+            rooms.push(room);
+            regions += 1;
+        }
+
+        // Fill the space left over with mazes
+        for y in (1..size.1 - 1).step_by(2) {
+            for x in (1..size.0 - 1).step_by(2) {
+                if grid.is_floor(x as i32, y as i32) {
+                    continue;
+                }
+                grow_maze(&mut grid, &mut region, regions, Point::new(x, y), &mut rng);
+                regions += 1;
+            }
+        }
+
+        // Walls between two different areas can be knocked through to join them
+        let mut connectors: Vec<Point> = grid
+            .inside()
+            .filter(|&(x, y)| !grid.is_floor(x as i32, y as i32))
+            .map(|(x, y)| Point::new(x, y))
+            .filter(|point| touching_regions(point, &region).len() > 1)
+            .collect();
+        connectors.shuffle(&mut rng);
+        let mut joined_to: Vec<usize> = (0..regions).collect();
+        let mut opened: Vec<Point> = Vec::new();
+        for connector in connectors {
+            let mut roots: Vec<usize> = touching_regions(&connector, &region)
+                .into_iter()
+                .map(|id| root(&joined_to, id))
+                .collect();
+            roots.sort();
+            roots.dedup();
+            let joins = roots.len() > 1;
+            let next_to_opening = opened
+                .iter()
+                .any(|open| open.x.abs_diff(connector.x) + open.y.abs_diff(connector.y) < 2);
+            if joins || (!next_to_opening && rng.gen_bool(self.extra_connector_chance)) {
+                for id in roots.iter().skip(1) {
+                    joined_to[*id] = roots[0];
+                }
+                grid.set(connector.x, connector.y, true);
+                opened.push(connector);
+            }
+        }
+
+        // Fill in dead ends until only the paths between rooms are left
+        loop {
+            let dead_ends: Vec<(usize, usize)> = grid
+                .inside()
+                .filter(|&(x, y)| grid.is_floor(x as i32, y as i32))
+                .filter(|&(x, y)| {
+                    DIRECTIONS
+                        .iter()
+                        .filter(|(dx, dy)| !grid.is_floor(x as i32 + dx, y as i32 + dy))
+                        .count()
+                        >= 3
+                })
+                .collect();
+            if dead_ends.is_empty() {
+                break;
+            }
+            for (x, y) in dead_ends {
+                grid.set(x, y, false);
+            }
+        }
+
+        let corridors = grid
+            .inside()
+            .filter(|&(x, y)| grid.is_floor(x as i32, y as i32))
+            .map(|(x, y)| Point::new(x, y))
+            .filter(|point| !rooms.iter().any(|room| room.contains(*point)))
+            .collect();
+        grid.into_map(rooms, corridors)
+    }
+}
+
+// Digs out a maze from `start` with a depth first search, two tiles at a time.
+fn grow_maze(
+    grid: &mut Grid,
+    region: &mut HashMap<Point, usize>,
+    id: usize,
+    start: Point,
+    rng: &mut ChaCha12Rng,
+) {
+    grid.set(start.x, start.y, true);
+    region.insert(start, id);
+    let mut path = vec![start];
+    while let Some(&cell) = path.last() {
+        let mut directions = DIRECTIONS;
+        directions.shuffle(rng);
+        let next = directions.iter().find_map(|(dx, dy)| {
+            let (x, y) = (cell.x as i32 + dx * 2, cell.y as i32 + dy * 2);
+            let inside = x > 0 && y > 0 && x < grid.size.0 as i32 - 1 && y < grid.size.1 as i32 - 1;
+            (inside && !grid.is_floor(x, y)).then_some((*dx, *dy))
+        });
+        let Some((dx, dy)) = next else {
+            path.pop();
+            continue;
+        };
+        for step in 1..=2 {
+            let point = Point::new(
+                (cell.x as i32 + dx * step) as usize,
+                (cell.y as i32 + dy * step) as usize,
+            );
+            grid.set(point.x, point.y, true);
+            region.insert(point, id);
+        }
+        path.push(Point::new(
+            (cell.x as i32 + dx * 2) as usize,
+            (cell.y as i32 + dy * 2) as usize,
+        ));
+    }
+}
+
+// The different areas next to a wall.
+fn touching_regions(point: &Point, region: &HashMap<Point, usize>) -> Vec<usize> {
+    let mut ids: Vec<usize> = DIRECTIONS
+        .iter()
+        .filter_map(|(dx, dy)| {
+            let next = Point::new(
+                (point.x as i32 + dx) as usize,
+                (point.y as i32 + dy) as usize,
+            );
+            region.get(&next).copied()
+        })
+        .collect();
+    ids.sort();
+    ids.dedup();
+    ids
+}
+
+fn root(joined_to: &[usize], mut id: usize) -> usize {
+    while joined_to[id] != id {
+        id = joined_to[id];
+    }
+    id
+}
+
+// Works out rooms from the tiles: floor that is at least two tiles wide is room,
+// the rest is corridor.
+fn find_rooms(tiles: &HashMap<Point, TileTypeMap>) -> (Vec<Rect>, Vec<Point>) {
+    let is_floor = |x: usize, y: usize| {
+        tiles.get(&Point::new(x, y)).map(|tile| tile.0) == Some(TileType::FLOOR)
+    };
+    let mut floor: Vec<Point> = tiles
+        .iter()
+        .filter(|tile| tile.1 .0 == TileType::FLOOR)
+        .map(|tile| *tile.0)
+        .collect();
+    floor.sort_by_key(|point| (point.y, point.x));
+    // Part of a 2x2 square of floor
+    let in_room = |point: &Point| {
+        [(0, 0), (1, 0), (0, 1), (1, 1)].iter().any(|(ox, oy)| {
+            let (Some(left), Some(top)) = (point.x.checked_sub(*ox), point.y.checked_sub(*oy))
+            else {
+                return false;
+            };
+            is_floor(left, top)
+                && is_floor(left + 1, top)
+                && is_floor(left, top + 1)
+                && is_floor(left + 1, top + 1)
+        })
+    };
+    let (room_tiles, corridors): (Vec<Point>, Vec<Point>) = floor.into_iter().partition(in_room);
+
+    let room_tiles: HashSet<Point> = room_tiles.into_iter().collect();
+    let mut sorted: Vec<Point> = room_tiles.iter().copied().collect();
+    sorted.sort_by_key(|point| (point.y, point.x));
+    let mut seen: HashSet<Point> = HashSet::new();
+    let mut rooms = Vec::new();
+    for start in sorted {
+        if !seen.insert(start) {
+            continue;
+        }
+        let (mut min, mut max) = (start, start);
+        let mut open = vec![start];
+        while let Some(point) = open.pop() {
+            min = Point::new(min.x.min(point.x), min.y.min(point.y));
+            max = Point::new(max.x.max(point.x), max.y.max(point.y));
+            for (dx, dy) in DIRECTIONS {
+                let next = Point::new(
+                    (point.x as i32 + dx) as usize,
+                    (point.y as i32 + dy) as usize,
+                );
+                if room_tiles.contains(&next) && seen.insert(next) {
+                    open.push(next);
+                }
+            }
+        }
+        rooms.push(Rect {
+            x: min.x,
+            y: min.y,
+            width: max.x - min.x + 1,
+            height: max.y - min.y + 1,
+        });
+    }
+    (rooms, corridors)
+}
+
+// Which tiles are floor, while a map is being dug out. The edge is always wall.
+struct Grid {
+    size: (usize, usize),
+    floor: Vec<bool>,
+}
+
+impl Grid {
+    fn new(size: (usize, usize)) -> Self {
+        Grid {
+            size,
+            floor: vec![false; size.0 * size.1],
+        }
+    }
+
+    // Every tile but the edge, row by row.
+    fn inside(&self) -> impl Iterator<Item = (usize, usize)> {
+        let (width, height) = self.size;
+        (1..height.saturating_sub(1))
+            .flat_map(move |y| (1..width.saturating_sub(1)).map(move |x| (x, y)))
+    }
+
+    fn is_floor(&self, x: i32, y: i32) -> bool {
+        x >= 0
+            && y >= 0
+            && (x as usize) < self.size.0
+            && (y as usize) < self.size.1
+            && self.floor[y as usize * self.size.0 + x as usize]
+    }
+
+    fn set(&mut self, x: usize, y: usize, floor: bool) {
+        self.floor[y * self.size.0 + x] = floor;
+    }
+
+    // Walls off every area but the biggest one.
+    fn keep_largest_region(&mut self) {
+        let regions = find_regions(self.size, |point| {
+            self.is_floor(point.x as i32, point.y as i32)
+        });
+        self.floor.iter_mut().for_each(|floor| *floor = false);
+        for point in regions.into_iter().next().unwrap_or_default() {
+            self.set(point.x, point.y, true);
+        }
+    }
+
+    // Floor where it was dug out, and walls around it.
+    fn into_map(self, rooms: Vec<Rect>, corridors: Vec<Point>) -> GeneratedMap {
+        let mut tiles = HashMap::new();
+        for y in 0..self.size.1 {
+            for x in 0..self.size.0 {
+                let next_to_floor = (-1..=1)
+                    .any(|dy| (-1..=1).any(|dx| self.is_floor(x as i32 + dx, y as i32 + dy)));
+                if self.is_floor(x as i32, y as i32) {
+                    tiles.insert(Point::new(x, y), TileTypeMap(TileType::FLOOR));
+                } else if next_to_floor {
+                    tiles.insert(Point::new(x, y), TileTypeMap(TileType::WALL));
+                }
+            }
+        }
+        GeneratedMap {
+            tiles,
+            rooms,
+            corridors,
+            vaults: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        map::{Level, DEFAULT_LEVEL_SIZE},
+        vaults::Vaults,
+    };
+
+    #[test]
+    fn generators_are_repeatable() {
+        for kind in GeneratorKind::ALL {
+            let first = kind.generator().generate((50, 50), 3);
+            let second = kind.generator().generate((50, 50), 3);
+            let mut first: Vec<_> = first
+                .tiles
+                .iter()
+                .map(|(p, t)| (p.x, p.y, t.0 as u8))
+                .collect();
+            let mut second: Vec<_> = second
+                .tiles
+                .iter()
+                .map(|(p, t)| (p.x, p.y, t.0 as u8))
+                .collect();
+            first.sort();
+            second.sort();
+            assert_eq!(first, second, "{}", kind);
+        }
+    }
+
+    #[test]
+    fn caves_mazes_and_walks_are_all_one_piece() {
+        for kind in [
+            GeneratorKind::Caves,
+            GeneratorKind::DrunkardsWalk,
+            GeneratorKind::RoomsAndMazes,
+        ] {
+            for seed in 0..5 {
+                let stats = kind.generator().generate((50, 50), seed).stats((50, 50));
+                assert_eq!(stats.regions, 1, "{} seed {}", kind, seed);
+                // Enough room to put everything on
+                assert!(
+                    stats.floor_ratio > 0.1,
+                    "{} seed {}: {:?}",
+                    kind,
+                    seed,
+                    stats
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rooms_and_mazes_reports_its_rooms() {
+        let map = GeneratorKind::RoomsAndMazes
+            .generator()
+            .generate((50, 50), 1);
+        assert!(map.rooms.len() > 3);
+        for room in map.rooms.iter() {
+            for y in room.y..room.y + room.height {
+                for x in room.x..room.x + room.width {
+                    let tile = map.tiles.get(&Point::new(x, y)).map(|tile| tile.0);
+                    assert_eq!(tile, Some(TileType::FLOOR));
+                }
+            }
+        }
+        assert!(map
+            .corridors
+            .iter()
+            .all(|point| !map.rooms.iter().any(|room| room.contains(*point))));
+    }
+
+    #[test]
+    fn tunnels_join_separate_rooms() {
+        let level = Level::from_ascii(&[
+            "########", "#..#####", "#..#####", "######.#", "#.######", "########",
+        ]);
+        let mut map = GeneratedMap {
+            tiles: level.tiles,
+            rooms: Vec::new(),
+            corridors: Vec::new(),
+            vaults: Vec::new(),
+        };
+        assert_eq!(map.stats(level.size).regions, 3);
+        map.connect_regions(level.size);
+        assert_eq!(map.stats(level.size).regions, 1);
+        assert!(!map.corridors.is_empty());
+    }
+
+    #[test]
+    fn every_level_is_one_piece() {
+        for kind in GeneratorKind::ALL {
+            for seed in 0..4 {
+                let level =
+                    Level::generate_with(seed, 3, DEFAULT_LEVEL_SIZE, kind, &Vaults::builtin());
+                let stats = level.stats();
+                assert_eq!(stats.regions, 1, "{} seed {}: {:?}", kind, seed, stats);
+                // The stairs count, so they can be reached from everywhere
+                assert!(level.find_tile(TileType::DOWNSTAIRS).is_some());
+                assert!(level.find_tile(TileType::UPSTAIRS).is_some());
+            }
+        }
+    }
+
+    #[test]
+    fn big_levels_are_one_piece() {
+        for kind in GeneratorKind::ALL {
+            let level = Level::generate_with(1, 3, (160, 100), kind, &Vaults::builtin());
+            assert_eq!(level.size, (160, 100));
+            assert_eq!(level.stats().regions, 1, "{}", kind);
+            // The far side of the level is used too
+            let floor: Vec<&Point> = level
+                .tiles
+                .iter()
+                .filter(|(_, tile)| tile.0 == TileType::FLOOR)
+                .map(|(point, _)| point)
+                .collect();
+            assert!(floor.iter().any(|point| point.x > 110), "{}", kind);
+            assert!(floor.iter().any(|point| point.y > 70), "{}", kind);
+        }
+    }
+
+    #[test]
+    fn generator_names() {
+        for kind in GeneratorKind::ALL {
+            assert_eq!(kind.name().parse::<GeneratorKind>(), Ok(kind));
+        }
+        assert!("maze".parse::<GeneratorKind>().is_err());
+    }
+}
@@ -0,0 +1,523 @@
+// Message log shown beside the map, and a full history viewer.
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    combat::{AttackOutcome, CombatEvent},
+    components::{MainCamera, MobType},
+    health::DeathEvent,
+    keymap::{Action, Keymap},
+    rendering::WINDOWSIZE,
+    resources::GlyphAssets,
+    turns::{GameState, TurnScheduler},
+    ui::{spawn_panel, spawn_text, GlyphText, GLYPH_SIZE},
+};
+
+pub const PANEL_WIDTH: f32 = 240.0;
+const LINE_HEIGHT: f32 = GLYPH_SIZE + 2.0;
+const PANEL_LINES: usize = 40;
+const HISTORY_LINES: usize = 44;
+/// Characters on one line of the history viewer.
+const HISTORY_WIDTH: usize = (WINDOWSIZE.0 / GLYPH_SIZE) as usize - 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MessageCategory {
+    // Attacks made by the player
+    Combat,
+    // The player getting hurt
+    Danger,
+    // Something died
+    Death,
+    // Something new was seen
+    Discovery,
+    Info,
+}
+
+impl MessageCategory {
+    pub fn color(&self) -> Color {
+        match self {
+            MessageCategory::Combat => Color::WHITE,
+            MessageCategory::Danger => Color::rgb(1.0, 0.3, 0.3),
+            MessageCategory::Death => Color::ORANGE,
+            MessageCategory::Discovery => Color::CYAN,
+            MessageCategory::Info => Color::GRAY,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Message {
+    pub text: String,
+    pub category: MessageCategory,
+    /// Turn the message was logged on.
+    pub turn: u32,
+}
+
+#[derive(Resource, Default)]
+pub struct MessageLog {
+    pub messages: Vec<Message>,
+}
+
+/// Send this to add a line to the message log.
+pub struct GameMessage {
+    pub text: String,
+    pub category: MessageCategory,
+}
+
+impl GameMessage {
+    pub fn new(text: impl Into<String>, category: MessageCategory) -> Self {
+        GameMessage {
+            text: text.into(),
+            category,
+        }
+    }
+}
+
+/// Puts "a" or "an" in front of a noun, e.g. "an orc".
+pub fn with_article(noun: &str) -> String {
+    let article = if noun.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        "an"
+    } else {
+        "a"
+    };
+    format!("{} {}", article, noun)
+}
+
+/// One line of the panel beside the map.
+#[derive(Component)]
+pub struct PanelLine(pub usize);
+
+/// Root of the full history viewer.
+#[derive(Component)]
+pub struct HistoryScreen;
+
+/// One line of the full history viewer.
+#[derive(Component)]
+pub struct HistoryLine(pub usize);
+
+/// How many lines the history viewer is scrolled up from the newest message.
+#[derive(Resource, Default)]
+pub struct HistoryScroll(pub usize);
+
+/// Adds new messages to the MessageLog.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CollectMessages;
+
+// ============================
+// ========== PLUGIN ==========
+pub struct MessagePlugin;
+impl Plugin for MessagePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<GameMessage>()
+            .init_resource::<MessageLog>()
+            .add_startup_system(welcome)
+            .add_system(collect_messages.label(CollectMessages));
+    }
+}
+
+/// The panel beside the map and the history viewer.
+pub struct MessagePanelPlugin;
+impl Plugin for MessagePanelPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HistoryScroll>()
+            .add_startup_system(setup)
+            .add_system(update_panel.after(CollectMessages))
+            .add_system_set(
+                SystemSet::on_update(GameState::AwaitingInput).with_system(open_history),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::MessageHistory).with_system(show_history),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::MessageHistory)
+                    .with_system(scroll_history)
+                    .with_system(update_history.after(scroll_history)),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::MessageHistory).with_system(hide_history),
+            );
+    }
+}
+
+// Without a keyboard there is no keymap, the default keys are as good as any
+fn welcome(mut messages: EventWriter<GameMessage>, keymap: Option<Res<Keymap>>) {
+    let keymap = keymap.map_or_else(Keymap::default, |keymap| keymap.clone());
+    messages.send(GameMessage::new(
+        format!(
+            "Welcome to the dungeon. Press {} for help.",
+            keymap.describe(Action::Help)
+        ),
+        MessageCategory::Info,
+    ));
+}
+
+fn setup(
+    mut commands: Commands,
+    atlas: Res<GlyphAssets>,
+    camera_query: Query<Entity, With<MainCamera>>,
+) {
+    let left = WINDOWSIZE.0 / 2.0 - PANEL_WIDTH;
+    let camera = camera_query.single();
+    commands.entity(camera).with_children(|parent| {
+        parent
+            .spawn(SpatialBundle::from_transform(Transform::from_xyz(
+                left, 0.0, -1.5,
+            )))
+            .with_children(|parent| {
+                spawn_panel(
+                    parent,
+                    &atlas,
+                    Color::BLACK,
+                    Vec2::new(PANEL_WIDTH / 2.0, 0.0),
+                    Vec2::new(PANEL_WIDTH, WINDOWSIZE.1),
+                );
+                for i in 0..PANEL_LINES {
+                    spawn_text(
+                        parent,
+                        "",
+                        Color::WHITE,
+                        Vec2::new(
+                            GLYPH_SIZE,
+                            WINDOWSIZE.1 / 2.0 - GLYPH_SIZE - i as f32 * LINE_HEIGHT,
+                        ),
+                    )
+                    .insert(PanelLine(i));
+                }
+            });
+    });
+}
+
+fn collect_messages(
+    mut log: ResMut<MessageLog>,
+    scheduler: Res<TurnScheduler>,
+    mut messages: EventReader<GameMessage>,
+    mut combat_events: EventReader<CombatEvent>,
+    mut death_events: EventReader<DeathEvent>,
+) {
+    let turn = scheduler.turn;
+    for message in messages.iter() {
+        log.messages.push(Message {
+            text: message.text.clone(),
+            category: message.category,
+            turn,
+        });
+    }
+    for event in combat_events.iter() {
+        let (text, category) = describe_attack(event);
+        log.messages.push(Message {
+            text,
+            category,
+            turn,
+        });
+    }
+    for event in death_events.iter() {
+        let text = if event.kind == MobType::PLAYER {
+            "You die...".to_string()
+        } else {
+            format!("The {} dies.", event.kind.name())
+        };
+        log.messages.push(Message {
+            text,
+            category: MessageCategory::Death,
+            turn,
+        });
+    }
+}
+
+fn describe_attack(event: &CombatEvent) -> (String, MessageCategory) {
+    if event.attacker_kind == MobType::PLAYER {
+        let target = event.target_kind.name();
+        let text = match event.outcome {
+            AttackOutcome::Miss => format!("You miss the {}.", target),
+            AttackOutcome::Hit { damage: 0, .. } => {
+                format!("Your blow glances off the {}.", target)
+            }
+            AttackOutcome::Hit { critical: true, .. } => {
+                format!("You critically hit the {}!", target)
+            }
+            AttackOutcome::Hit { .. } => format!("You hit the {}.", target),
+        };
+        (text, MessageCategory::Combat)
+    } else {
+        let attacker = event.attacker_kind.name();
+        let text = match event.outcome {
+            AttackOutcome::Miss => format!("The {} misses you.", attacker),
+            AttackOutcome::Hit { damage: 0, .. } => {
+                format!("The {} hits you but does no damage.", attacker)
+            }
+            AttackOutcome::Hit { critical: true, .. } => {
+                format!("The {} critically hits you!", attacker)
+            }
+            AttackOutcome::Hit { .. } => format!("The {} hits you.", attacker),
+        };
+        (text, MessageCategory::Danger)
+    }
+}
+
+// Splits text into lines of at most `width` characters, breaking on spaces.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for (i, word) in text.split(' ').enumerate() {
+        if i > 0 {
+            // Spaces before the first word are kept, they line up the turn numbers
+            if !line.trim().is_empty() && line.len() + 1 + word.len() > width {
+                lines.push(std::mem::take(&mut line));
+            } else {
+                line.push(' ');
+            }
+        }
+        line.push_str(word);
+    }
+    lines.push(line);
+    lines
+}
+
+// Every message wrapped to fit `width`, oldest first. The history viewer prefixes the turn.
+fn wrapped_lines(log: &MessageLog, width: usize, show_turns: bool) -> Vec<(String, Color)> {
+    log.messages
+        .iter()
+        .flat_map(|message| {
+            let text = if show_turns {
+                format!("{:>5} {}", message.turn, message.text)
+            } else {
+                message.text.clone()
+            };
+            wrap(&text, width)
+                .into_iter()
+                .map(|line| (line, message.category.color()))
+        })
+        .collect()
+}
+
+// The `count` lines ending `scroll` lines before the newest.
+fn visible_lines(lines: &[(String, Color)], scroll: usize, count: usize) -> &[(String, Color)] {
+    let end = lines.len().saturating_sub(scroll);
+    let start = end.saturating_sub(count);
+    &lines[start..end]
+}
+
+// Writes the lines ending `scroll` lines before the newest into the text lines.
+fn fill_lines<'a>(
+    lines: &[(String, Color)],
+    scroll: usize,
+    text_lines: impl Iterator<Item = (usize, Mut<'a, GlyphText>)>,
+    count: usize,
+) {
+    let visible = visible_lines(lines, scroll, count);
+    for (i, mut text) in text_lines {
+        let (line, color) = visible
+            .get(i)
+            .cloned()
+            .unwrap_or((String::new(), Color::WHITE));
+        if text.text != line || text.color != color {
+            text.text = line;
+            text.color = color;
+        }
+    }
+}
+
+fn update_panel(log: Res<MessageLog>, mut line_query: Query<(&PanelLine, &mut GlyphText)>) {
+    if !log.is_changed() {
+        return;
+    }
+    let width = ((PANEL_WIDTH - 2.0 * GLYPH_SIZE) / GLYPH_SIZE) as usize;
+    let lines = wrapped_lines(&log, width, false);
+    fill_lines(
+        &lines,
+        0,
+        line_query.iter_mut().map(|(line, text)| (line.0, text)),
+        PANEL_LINES,
+    );
+}
+
+fn open_history(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut state: ResMut<State<GameState>>,
+    mut scroll: ResMut<HistoryScroll>,
+) {
+    if keymap.just_pressed(Action::MessageHistory, &keyboard_input) {
+        keyboard_input.clear();
+        scroll.0 = 0;
+        state.push(GameState::MessageHistory).unwrap();
+    }
+}
+
+fn show_history(
+    mut commands: Commands,
+    atlas: Res<GlyphAssets>,
+    camera_query: Query<Entity, With<MainCamera>>,
+) {
+    let camera = camera_query.single();
+    commands.entity(camera).with_children(|parent| {
+        parent
+            .spawn((
+                HistoryScreen,
+                SpatialBundle::from_transform(Transform::from_xyz(0.0, 0.0, -0.4)),
+            ))
+            .with_children(|parent| {
+                spawn_panel(
+                    parent,
+                    &atlas,
+                    Color::BLACK,
+                    Vec2::ZERO,
+                    Vec2::new(WINDOWSIZE.0, WINDOWSIZE.1),
+                );
+                let left = -WINDOWSIZE.0 / 2.0 + GLYPH_SIZE;
+                let top = WINDOWSIZE.1 / 2.0 - GLYPH_SIZE;
+                spawn_text(
+                    parent,
+                    "Message history - Up/Down/PageUp/PageDown to scroll, Esc to close",
+                    Color::YELLOW,
+                    Vec2::new(left, top),
+                );
+                for i in 0..HISTORY_LINES {
+                    spawn_text(
+                        parent,
+                        "",
+                        Color::WHITE,
+                        Vec2::new(left, top - (i + 2) as f32 * LINE_HEIGHT),
+                    )
+                    .insert(HistoryLine(i));
+                }
+            });
+    });
+}
+
+fn scroll_history(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut state: ResMut<State<GameState>>,
+    mut scroll: ResMut<HistoryScroll>,
+    log: Res<MessageLog>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape)
+        || keymap.just_pressed(Action::MessageHistory, &keyboard_input)
+    {
+        keyboard_input.clear();
+        state.pop().unwrap();
+        return;
+    }
+    let max_scroll = max_scroll(&log);
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        scroll.0 += 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        scroll.0 = scroll.0.saturating_sub(1);
+    }
+    if keyboard_input.just_pressed(KeyCode::PageUp) {
+        scroll.0 += HISTORY_LINES;
+    }
+    if keyboard_input.just_pressed(KeyCode::PageDown) {
+        scroll.0 = scroll.0.saturating_sub(HISTORY_LINES);
+    }
+    scroll.0 = scroll.0.min(max_scroll);
+}
+
+// How far the history can be scrolled up before the oldest line is at the top.
+fn max_scroll(log: &MessageLog) -> usize {
+    wrapped_lines(log, HISTORY_WIDTH, true)
+        .len()
+        .saturating_sub(HISTORY_LINES)
+}
+
+fn update_history(
+    log: Res<MessageLog>,
+    scroll: Res<HistoryScroll>,
+    mut line_query: Query<(&HistoryLine, &mut GlyphText)>,
+) {
+    let lines = wrapped_lines(&log, HISTORY_WIDTH, true);
+    fill_lines(
+        &lines,
+        scroll.0,
+        line_query.iter_mut().map(|(line, text)| (line.0, text)),
+        HISTORY_LINES,
+    );
+}
+
+fn hide_history(mut commands: Commands, screen_query: Query<Entity, With<HistoryScreen>>) {
+    for screen in screen_query.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(text: &str, category: MessageCategory, turn: u32) -> Message {
+        Message {
+            text: text.to_string(),
+            category,
+            turn,
+        }
+    }
+
+    #[test]
+    fn articles() {
+        assert_eq!(with_article("orc"), "an orc");
+        assert_eq!(with_article("goblin"), "a goblin");
+    }
+
+    #[test]
+    fn long_messages_wrap_between_words() {
+        assert_eq!(
+            wrap("The goblin hits you but does no damage.", 16),
+            ["The goblin hits", "you but does no", "damage."]
+        );
+        // A word longer than a line gets a line to itself
+        assert_eq!(wrap("a verylongword b", 5), ["a", "verylongword", "b"]);
+        assert_eq!(wrap("", 5), [""]);
+    }
+
+    #[test]
+    fn lines_keep_the_color_of_their_message() {
+        let log = MessageLog {
+            messages: vec![
+                message("You hit the orc.", MessageCategory::Combat, 3),
+                message("The orc critically hits you!", MessageCategory::Danger, 12),
+            ],
+        };
+        let lines = wrapped_lines(&log, 20, false);
+        let colors: Vec<Color> = lines.iter().map(|line| line.1).collect();
+        assert_eq!(
+            colors,
+            [
+                MessageCategory::Combat.color(),
+                MessageCategory::Danger.color(),
+                MessageCategory::Danger.color(),
+            ]
+        );
+        // The history puts the turn in front
+        let lines = wrapped_lines(&log, 40, true);
+        assert_eq!(lines[0].0, "    3 You hit the orc.");
+        assert_eq!(lines[1].0, "   12 The orc critically hits you!");
+    }
+
+    #[test]
+    fn scrolling_stops_at_the_oldest_line() {
+        let log = MessageLog {
+            messages: (0..HISTORY_LINES + 10)
+                .map(|turn| message("Hello.", MessageCategory::Info, turn as u32))
+                .collect(),
+        };
+        let lines = wrapped_lines(&log, HISTORY_WIDTH, true);
+        assert_eq!(max_scroll(&log), 10);
+
+        let newest = visible_lines(&lines, 0, HISTORY_LINES);
+        assert_eq!(newest.len(), HISTORY_LINES);
+        assert_eq!(newest.last(), lines.last());
+
+        let oldest = visible_lines(&lines, max_scroll(&log), HISTORY_LINES);
+        assert_eq!(oldest.len(), HISTORY_LINES);
+        assert_eq!(oldest.first(), lines.first());
+
+        // A short log fits without scrolling
+        let short = MessageLog {
+            messages: vec![message("Hello.", MessageCategory::Info, 1)],
+        };
+        assert_eq!(max_scroll(&short), 0);
+        assert_eq!(visible_lines(&lines[..1], 0, HISTORY_LINES).len(), 1);
+    }
+}
@@ -0,0 +1,212 @@
+// Command line options.
+use std::path::PathBuf;
+
+use bevy::prelude::*;
+
+use crate::{
+    map::{MAX_LEVEL_SIDE, MIN_LEVEL_SIDE},
+    mapgen::GeneratorKind,
+};
+
+pub const USAGE: &str = "Usage: p01-rogue [--seed <u64>] [--replace-save] [--generator <name>]
+                 [--size <w>x<h>] [--record <file>] [--replay <file>] [--headless]
+
+Options:
+    --seed <u64>        Build the dungeon from this seed instead of a random one
+    --replace-save      Let a --seed game be saved over the saved game
+    --generator <name>  Build every level with one generator: bsp, caves, drunkard or mazes
+    --size <w>x<h>      Width and height of every level in tiles, from 30 up to 400, e.g. 200x120
+    --record <file>     Record the session here instead of last-session.replay
+    --replay <file>     Play back a recorded session
+    --headless          Play the --replay recording without a window and print the result
+    -h, --help          Print this message";
+
+#[derive(Resource, Debug, Default)]
+pub struct Options {
+    /// Seed for the first dungeon. Picked at random when not given.
+    pub seed: Option<u64>,
+    /// A new game started with `seed` may be saved over the saved game.
+    pub replace_save: bool,
+    /// Generator for every level. Picked by depth when not given.
+    pub generator: Option<GeneratorKind>,
+    /// Width and height of every level. The default size when not given.
+    pub size: Option<(usize, usize)>,
+    /// Where to record the players actions.
+    pub record: Option<PathBuf>,
+    /// Recording to play back instead of taking input.
+    pub replay: Option<PathBuf>,
+    /// Run the replay without a window.
+    pub headless: bool,
+    /// Print the usage and quit.
+    pub help: bool,
+}
+
+impl Options {
+    /// Parses the arguments after the program name. Err holds a message for the user.
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            // Allow both `--seed 5` and `--seed=5`
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) => (flag, Some(value.to_string())),
+                None => (arg.as_str(), None),
+            };
+            match flag {
+                "--seed" => {
+                    let value = inline_value
+                        .or_else(|| args.next())
+                        .ok_or("--seed needs a value")?;
+                    let seed = value
+                        .parse()
+                        .map_err(|_| format!("invalid seed '{}', expected a u64", value))?;
+                    options.seed = Some(seed);
+                }
+                "--generator" => {
+                    let value = inline_value
+                        .or_else(|| args.next())
+                        .ok_or("--generator needs a name")?;
+                    options.generator = Some(value.parse()?);
+                }
+                "--size" => {
+                    let value = inline_value
+                        .or_else(|| args.next())
+                        .ok_or("--size needs a width and height, e.g. 200x120")?;
+                    options.size = Some(parse_size(&value)?);
+                }
+                "--record" | "--replay" => {
+                    let value = inline_value
+                        .or_else(|| args.next())
+                        .ok_or(format!("{} needs a file", flag))?;
+                    if flag == "--record" {
+                        options.record = Some(value.into());
+                    } else {
+                        options.replay = Some(value.into());
+                    }
+                }
+                "--replace-save" => options.replace_save = true,
+                "--headless" => options.headless = true,
+                "-h" | "--help" => options.help = true,
+                _ => return Err(format!("unknown argument '{}'\n\n{}", arg, USAGE)),
+            }
+        }
+        if options.headless && options.replay.is_none() {
+            return Err("--headless needs a recording to play, pass it with --replay".into());
+        }
+        Ok(options)
+    }
+
+    /// What these options ask for that a saved game with `generator` and `size` does not
+    /// have, as messages for the player. A saved game keeps its levels, so only a new game
+    /// can change them.
+    pub fn conflicts_with_save(
+        &self,
+        generator: Option<GeneratorKind>,
+        size: (usize, usize),
+    ) -> Vec<String> {
+        let mut conflicts = Vec::new();
+        if let Some(wanted) = self.generator.filter(|wanted| Some(*wanted) != generator) {
+            let saved = generator.map_or("picks them by depth".to_string(), |generator| {
+                format!("uses {}", generator)
+            });
+            conflicts.push(format!(
+                "--generator {} was ignored, the saved game {}. Pass --seed to start a new one.",
+                wanted, saved
+            ));
+        }
+        if let Some((width, height)) = self.size.filter(|wanted| *wanted != size) {
+            conflicts.push(format!(
+                "--size {}x{} was ignored, the saved game is {}x{}. Pass --seed to start a new one.",
+                width, height, size.0, size.1
+            ));
+        }
+        conflicts
+    }
+}
+
+// Reads a level size written as `<width>x<height>`.
+fn parse_size(value: &str) -> Result<(usize, usize), String> {
+    let invalid = || format!("invalid size '{}', expected e.g. 200x120", value);
+    let (width, height) = value.split_once('x').ok_or_else(invalid)?;
+    let width: usize = width.parse().map_err(|_| invalid())?;
+    let height: usize = height.parse().map_err(|_| invalid())?;
+    let sides = MIN_LEVEL_SIDE..=MAX_LEVEL_SIDE;
+    if !sides.contains(&width) || !sides.contains(&height) {
+        return Err(format!(
+            "invalid size '{}', width and height go from {} to {}",
+            value, MIN_LEVEL_SIDE, MAX_LEVEL_SIDE
+        ));
+    }
+    Ok((width, height))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &str) -> Result<Options, String> {
+        Options::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn seeds() {
+        assert_eq!(parse("--seed 42").unwrap().seed, Some(42));
+        assert_eq!(parse("--seed=42").unwrap().seed, Some(42));
+        assert_eq!(parse("").unwrap().seed, None);
+        for bad in [
+            "--seed",
+            "--seed -1",
+            "--seed abc",
+            "--seed 18446744073709551616",
+        ] {
+            assert!(parse(bad).is_err(), "'{}' was accepted", bad);
+        }
+        assert!(!parse("--seed 42").unwrap().replace_save);
+        assert!(parse("--seed 42 --replace-save").unwrap().replace_save);
+    }
+
+    #[test]
+    fn generators() {
+        for kind in GeneratorKind::ALL {
+            let options = parse(&format!("--generator {}", kind)).unwrap();
+            assert_eq!(options.generator, Some(kind));
+        }
+        assert!(parse("--generator").is_err());
+        assert!(parse("--generator dungeon").is_err());
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("200x120"), Ok((200, 120)));
+        assert_eq!(parse_size("30x400"), Ok((30, 400)));
+        assert_eq!(parse_size("400x30"), Ok((400, 30)));
+        for bad in [
+            "29x50", "50x29", "401x50", "50x401", "0x0", "200", "200x", "x120", "ax5",
+        ] {
+            assert!(parse_size(bad).is_err(), "'{}' was accepted", bad);
+        }
+        assert_eq!(parse("--size=60x40").unwrap().size, Some((60, 40)));
+        assert!(parse("--size").is_err());
+    }
+
+    #[test]
+    fn options_a_save_can_not_follow() {
+        let options = parse("--generator caves --size 100x80").unwrap();
+        assert_eq!(
+            options
+                .conflicts_with_save(Some(GeneratorKind::Caves), (100, 80))
+                .len(),
+            0
+        );
+        assert_eq!(options.conflicts_with_save(None, (100, 80)).len(), 1);
+        assert_eq!(
+            options
+                .conflicts_with_save(Some(GeneratorKind::Bsp), (50, 50))
+                .len(),
+            2
+        );
+        assert!(parse("")
+            .unwrap()
+            .conflicts_with_save(None, (50, 50))
+            .is_empty());
+    }
+}
@@ -0,0 +1,325 @@
+// Pathfinding over the level tiles.
+// A* for point to point paths and Dijkstra maps for distance fields.
+// https://www.redblobgames.com/pathfinding/a-star/introduction.html
+// https://www.roguebasin.com/index.php/The_Incredible_Power_of_Dijkstra_Maps
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use crate::{
+    components::Position,
+    direction::{self, can_step, Direction},
+    map::Level,
+    turns::ACTION_COST,
+};
+
+// Tiles next to a position that can be walked onto, counting closed doors, diagonals
+// included where they do not cut a corner. Blocked tiles are skipped unless they are the goal.
+fn neighbours<'a>(
+    position: Position,
+    level: &'a Level,
+    blocked: &'a HashSet<Position>,
+    goal: Option<Position>,
+) -> impl Iterator<Item = Position> + 'a {
+    direction::neighbours(level, position)
+        .map(|(_, next)| next)
+        .filter(move |next| {
+            level.is_passable(next.x, next.y) && (!blocked.contains(next) || Some(*next) == goal)
+        })
+}
+
+/// Quickest path from start to goal, not including start. Slow tiles like water cost more.
+/// Positions in `blocked` (e.g. other mobs) are treated as walls, except for the goal itself.
+/// Returns None if there is no path.
+pub fn a_star(
+    level: &Level,
+    start: Position,
+    goal: Position,
+    blocked: &HashSet<Position>,
+) -> Option<Vec<Position>> {
+    if start == goal {
+        return Some(Vec::new());
+    }
+    let mut open = BinaryHeap::new();
+    let mut came_from: HashMap<Position, Position> = HashMap::new();
+    let mut cost: HashMap<Position, i32> = HashMap::new();
+    cost.insert(start, 0);
+    open.push(Reverse((
+        start.distance(&goal) * ACTION_COST,
+        0,
+        start.x,
+        start.y,
+    )));
+
+    while let Some(Reverse((_, g, x, y))) = open.pop() {
+        let current = Position { x, y };
+        if current == goal {
+            // Walk back to the start
+            let mut path = vec![current];
+            let mut step = current;
+            while let Some(&previous) = came_from.get(&step) {
+                if previous == start {
+                    break;
+                }
+                path.push(previous);
+                step = previous;
+            }
+            path.reverse();
+            return Some(path);
+        }
+        // Skip stale entries
+        if g > cost[&current] {
+            continue;
+        }
+        for next in neighbours(current, level, blocked, Some(goal)) {
+            let next_cost = g + level.move_cost(next.x, next.y);
+            if cost.get(&next).is_none_or(|&old| next_cost < old) {
+                cost.insert(next, next_cost);
+                came_from.insert(next, current);
+                open.push(Reverse((
+                    next_cost + next.distance(&goal) * ACTION_COST,
+                    next_cost,
+                    next.x,
+                    next.y,
+                )));
+            }
+        }
+    }
+    None
+}
+
+/// Distance from every reachable tile to the closest goal, in energy like `a_star` counts
+/// it, so slow tiles are further away.
+pub struct DijkstraMap {
+    pub distances: HashMap<Position, i32>,
+}
+
+impl DijkstraMap {
+    /// Build a map where every goal starts at distance 0.
+    pub fn new(level: &Level, goals: &[Position], blocked: &HashSet<Position>) -> Self {
+        let goals: Vec<(Position, i32)> = goals.iter().map(|goal| (*goal, 0)).collect();
+        DijkstraMap::with_costs(level, &goals, blocked)
+    }
+
+    /// Build a map where each goal starts at its own value.
+    /// Goals are allowed to be in `blocked`.
+    pub fn with_costs(
+        level: &Level,
+        goals: &[(Position, i32)],
+        blocked: &HashSet<Position>,
+    ) -> Self {
+        let mut distances: HashMap<Position, i32> = HashMap::new();
+        let mut open = BinaryHeap::new();
+        for (goal, value) in goals {
+            if distances.get(goal).is_none_or(|&old| *value < old) {
+                distances.insert(*goal, *value);
+                open.push(Reverse((*value, goal.x, goal.y)));
+            }
+        }
+        while let Some(Reverse((distance, x, y))) = open.pop() {
+            let current = Position { x, y };
+            if distance > distances[&current] {
+                continue;
+            }
+            for next in neighbours(current, level, blocked, None) {
+                let next_distance = distance + level.move_cost(next.x, next.y);
+                if distances.get(&next).is_none_or(|&old| next_distance < old) {
+                    distances.insert(next, next_distance);
+                    open.push(Reverse((next_distance, next.x, next.y)));
+                }
+            }
+        }
+        DijkstraMap { distances }
+    }
+
+    /// Build a map that leads away from the goals of this map.
+    /// Following it downhill runs away, but prefers escape routes over dead ends.
+    pub fn flee(&self, level: &Level, blocked: &HashSet<Position>) -> Self {
+        let goals: Vec<(Position, i32)> = self
+            .distances
+            .iter()
+            .map(|(position, distance)| (*position, -(distance * 6) / 5))
+            .collect();
+        DijkstraMap::with_costs(level, &goals, blocked)
+    }
+
+    pub fn distance(&self, position: &Position) -> Option<i32> {
+        self.distances.get(position).copied()
+    }
+
+    /// The neighbouring tile that gets closest to a goal, if any is closer than standing still.
+    pub fn downhill(
+        &self,
+        level: &Level,
+        position: &Position,
+        blocked: &HashSet<Position>,
+    ) -> Option<Position> {
+        let mut best = self.distance(position).unwrap_or(i32::MAX);
+        let mut step = None;
+        for direction in Direction::ALL {
+            let next = direction.step(position);
+            if blocked.contains(&next) || !can_step(level, position, direction) {
+                continue;
+            }
+            if let Some(distance) = self.distance(&next) {
+                if distance < best {
+                    best = distance;
+                    step = Some(next);
+                }
+            }
+        }
+        step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::direction::is_next_to;
+
+    fn pos(x: i32, y: i32) -> Position {
+        Position { x, y }
+    }
+
+    #[test]
+    fn a_star_straight_line() {
+        let level = Level::from_ascii(&["#######", "#.....#", "#######"]);
+        let path = a_star(&level, pos(1, 1), pos(5, 1), &HashSet::new()).unwrap();
+        assert_eq!(path, vec![pos(2, 1), pos(3, 1), pos(4, 1), pos(5, 1)]);
+    }
+
+    #[test]
+    fn a_star_same_tile() {
+        let level = Level::from_ascii(&["###", "#.#", "###"]);
+        assert_eq!(
+            a_star(&level, pos(1, 1), pos(1, 1), &HashSet::new()),
+            Some(Vec::new())
+        );
+    }
+
+    #[test]
+    fn a_star_goes_around_walls() {
+        let level = Level::from_ascii(&[
+            "#######", //
+            "#..#..#", //
+            "#..#..#", //
+            "#.....#", //
+            "#######",
+        ]);
+        let path = a_star(&level, pos(1, 1), pos(5, 1), &HashSet::new()).unwrap();
+        // Diagonal steps cut across the rooms, but not around the end of the wall
+        assert_eq!(path.len(), 6);
+        assert_eq!(*path.last().unwrap(), pos(5, 1));
+        assert!(path.iter().all(|step| level.is_walkable(step.x, step.y)));
+        // Every step is next to the one before it
+        let mut previous = pos(1, 1);
+        for step in path {
+            assert!(is_next_to(&level, &previous, &step));
+            previous = step;
+        }
+    }
+
+    #[test]
+    fn a_star_goes_around_slow_tiles() {
+        let level = Level::from_ascii(&[
+            "#######", //
+            "#.;;;.#", //
+            "#.....#", //
+            "#######",
+        ]);
+        let path = a_star(&level, pos(1, 1), pos(5, 1), &HashSet::new()).unwrap();
+        assert_eq!(path.len(), 4);
+        assert!(path.iter().all(|step| step.y == 2 || step.x == 5));
+    }
+
+    #[test]
+    fn a_star_no_path() {
+        let level = Level::from_ascii(&["#####", "#.#.#", "#####"]);
+        assert_eq!(a_star(&level, pos(1, 1), pos(3, 1), &HashSet::new()), None);
+    }
+
+    #[test]
+    fn a_star_mobs_block_corridor_but_not_goal() {
+        let level = Level::from_ascii(&["######", "#....#", "######"]);
+        let blocked: HashSet<Position> = [pos(2, 1), pos(4, 1)].into_iter().collect();
+        assert_eq!(a_star(&level, pos(1, 1), pos(4, 1), &blocked), None);
+        assert_eq!(
+            a_star(&level, pos(3, 1), pos(4, 1), &blocked),
+            Some(vec![pos(4, 1)])
+        );
+        // Without mobs as obstacles the corridor is open
+        assert!(a_star(&level, pos(1, 1), pos(4, 1), &HashSet::new()).is_some());
+    }
+
+    #[test]
+    fn dijkstra_multiple_goals() {
+        let level = Level::from_ascii(&["#########", "#.......#", "#########"]);
+        let map = DijkstraMap::new(&level, &[pos(1, 1), pos(7, 1)], &HashSet::new());
+        assert_eq!(map.distance(&pos(1, 1)), Some(0));
+        assert_eq!(map.distance(&pos(3, 1)), Some(2 * ACTION_COST));
+        assert_eq!(map.distance(&pos(4, 1)), Some(3 * ACTION_COST));
+        assert_eq!(map.distance(&pos(6, 1)), Some(ACTION_COST));
+        // Walls are never reached
+        assert_eq!(map.distance(&pos(0, 1)), None);
+    }
+
+    #[test]
+    fn dijkstra_slow_tiles_are_further() {
+        let level = Level::from_ascii(&["#######", "#.;~..#", "#######"]);
+        let map = DijkstraMap::new(&level, &[pos(1, 1)], &HashSet::new());
+        assert_eq!(map.distance(&pos(2, 1)), Some(level.move_cost(2, 1)));
+        assert_eq!(
+            map.distance(&pos(5, 1)),
+            Some(level.move_cost(2, 1) + level.move_cost(3, 1) + 2 * ACTION_COST)
+        );
+    }
+
+    #[test]
+    fn dijkstra_unreachable_and_blocked() {
+        let level = Level::from_ascii(&["#######", "#..#..#", "#######"]);
+        let blocked: HashSet<Position> = [pos(2, 1)].into_iter().collect();
+        let map = DijkstraMap::new(&level, &[pos(1, 1)], &blocked);
+        assert_eq!(map.distance(&pos(2, 1)), None);
+        assert_eq!(map.distance(&pos(4, 1)), None);
+    }
+
+    #[test]
+    fn dijkstra_downhill_reaches_goal() {
+        let level = Level::from_ascii(&[
+            "######", //
+            "#....#", //
+            "#.##.#", //
+            "#....#", //
+            "######",
+        ]);
+        let map = DijkstraMap::new(&level, &[pos(4, 3)], &HashSet::new());
+        let mut position = pos(1, 1);
+        let mut steps = 0;
+        while let Some(next) = map.downhill(&level, &position, &HashSet::new()) {
+            position = next;
+            steps += 1;
+        }
+        assert_eq!(position, pos(4, 3));
+        assert_eq!(steps, 5);
+    }
+
+    #[test]
+    fn flee_map_leads_away() {
+        let level = Level::from_ascii(&["##########", "#........#", "##########"]);
+        let map = DijkstraMap::new(&level, &[pos(3, 1)], &HashSet::new());
+        let flee = map.flee(&level, &HashSet::new());
+        // Standing right of the threat, the way out is further right
+        assert_eq!(
+            flee.downhill(&level, &pos(4, 1), &HashSet::new()),
+            Some(pos(5, 1))
+        );
+        // Standing left of it, running left is further from the threat
+        assert_eq!(
+            flee.downhill(&level, &pos(2, 1), &HashSet::new()),
+            Some(pos(1, 1))
+        );
+    }
+}
@@ -2,13 +2,23 @@ use bevy::prelude::*;
 use map_gen_2d::Point;
 
 use crate::{
+    actions::{ActionInput, PlayerAction},
+    combat::{melee, CombatEvent, CombatStats, Dice},
     components::{Mob, MobType, Position, POV},
-    health::Health,
-    map::Level,
+    direction::{can_step, Direction},
+    doors::{open_door, DoorOutcome},
+    health::{Health, LastHitBy},
+    items::{Inventory, INVENTORY_CAPACITY},
+    lighting::LightSource,
+    map::{Level, TileChanged},
+    messages::{GameMessage, MessageCategory},
     resources::GlyphAssets,
-    tiles::{TileType, TileTypeMap, TILESIZE},
+    tiles::{TileType, TILESIZE},
+    turns::{end_player_turn, Energy, GameState, Speed, ACTION_COST, NORMAL_SPEED},
 };
 
+pub const PLAYER_HEALTH: i32 = 20;
+
 #[derive(Component, Default, Debug)]
 pub struct Player;
 
@@ -20,6 +30,13 @@ pub struct PlayerBundle {
     position: Position,
     pov: POV,
     mob: Mob,
+    health: Health,
+    last_hit_by: LastHitBy,
+    stats: CombatStats,
+    energy: Energy,
+    speed: Speed,
+    inventory: Inventory,
+    lantern: LightSource,
 }
 
 impl PlayerBundle {
@@ -31,7 +48,7 @@ impl PlayerBundle {
                     translation: Vec3 {
                         x: location.0 as f32 * TILESIZE as f32,
                         y: location.1 as f32 * TILESIZE as f32,
-                        z: 0.0,
+                        z: 1.0,
                     },
                     scale: Vec3 {
                         x: TILESIZE as f32 / 8.0,
@@ -57,18 +74,48 @@ impl PlayerBundle {
                 range: 8,
             },
             mob: Mob(MobType::PLAYER),
+            health: Health(PLAYER_HEALTH),
+            last_hit_by: LastHitBy(None),
+            stats: CombatStats {
+                attack: 3,
+                defense: 2,
+                armor: 0,
+                damage: Dice::new(1, 6, 0),
+            },
+            // The player always gets the first move
+            energy: Energy(ACTION_COST),
+            speed: Speed(NORMAL_SPEED),
+            inventory: Inventory::new(INVENTORY_CAPACITY),
+            lantern: LightSource {
+                color: (1.0, 0.9, 0.6),
+                radius: 7,
+                falloff: 1.0,
+            },
         }
     }
 }
 
+/// Spawns the player at startup. Monsters are spawned after this, so turn order ties
+/// between them are broken the same way every game.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SpawnPlayer;
+
 pub struct PlayerPlugin;
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup).add_system(movement);
+        app.add_startup_system(setup.label(SpawnPlayer))
+            .add_system_set(
+                SystemSet::on_update(GameState::AwaitingInput)
+                    .with_system(movement.after(ActionInput)),
+            );
     }
 }
 
 fn setup(mut commands: Commands, atlas: Res<GlyphAssets>, map: Res<Level>) {
+    spawn_player(&mut commands, &atlas, &map);
+}
+
+pub fn spawn_player(commands: &mut Commands, atlas: &GlyphAssets, map: &Level) {
     for mob in map.mobs.iter() {
         if mob.1 == MobType::PLAYER {
             commands.spawn(PlayerBundle::new(
@@ -79,105 +126,96 @@ fn setup(mut commands: Commands, atlas: Res<GlyphAssets>, map: Res<Level>) {
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn movement(
-    mut player_query: Query<(&mut Position, &mut Transform), With<Player>>,
-    keyboard_input: Res<Input<KeyCode>>,
-    map: Res<Level>,
-    mut mob_query: Query<(&Position, &Mob, &mut Health), Without<Player>>,
+    mut player_query: Query<
+        (
+            &mut Position,
+            &mut Transform,
+            &mut Energy,
+            Entity,
+            &CombatStats,
+            &mut Inventory,
+        ),
+        With<Player>,
+    >,
+    mut actions: EventReader<PlayerAction>,
+    mut map: ResMut<Level>,
+    mut mob_query: Query<(&Position, &Mob, &mut Health, Entity, &CombatStats), Without<Player>>,
+    mut state: ResMut<State<GameState>>,
+    mut combat_events: EventWriter<CombatEvent>,
+    mut messages: EventWriter<GameMessage>,
+    mut tile_changes: EventWriter<TileChanged>,
 ) {
-    for mut player in player_query.iter_mut() {
-        if keyboard_input.just_pressed(KeyCode::W) {
-            match map
-                .tiles
-                .get(&Point::new(player.0.x as usize, player.0.y as usize + 1))
-            {
-                Some(TileTypeMap(TileType::WALL)) => { //nothing
-                }
-                _ => {
-                    let mut is_attack = false;
-                    for mut mob in mob_query.iter_mut() {
-                        if mob.0.x == player.0.x && mob.0.y == player.0.y + 1 {
-                            // Attack mob
-                            is_attack = true;
-                            mob.2.0 = mob.2.0 - 1;
-                            break;
-                        }
-                    }
-                    if !is_attack {
-                        player.0.y += 1;
-                        player.1.translation.y += 1.0 * TILESIZE as f32;
-                    }
-                }
+    for action in actions.iter() {
+        let direction = match *action {
+            PlayerAction::Move { dx, dy } => {
+                // Only ever one step, to a neighbouring tile
+                let Some(direction) = Direction::from_delta(dx, dy) else {
+                    continue;
+                };
+                Some(direction)
             }
-        } else if keyboard_input.just_pressed(KeyCode::A) {
-            match map
-                .tiles
-                .get(&Point::new(player.0.x as usize - 1, player.0.y as usize))
-            {
-                Some(TileTypeMap(TileType::WALL)) => { //nothing
-                }
-                _ => {
-                    let mut is_attack = false;
-                    for mut mob in mob_query.iter_mut() {
-                        if mob.0.x == player.0.x - 1 && mob.0.y == player.0.y {
-                            // Attack mob
-                            is_attack = true;
-                            mob.2.0 = mob.2.0 - 1;
-                            break;
-                        }
-                    }
-                    if !is_attack {
-                        player.0.x -= 1;
-                        player.1.translation.x -= 1.0 * TILESIZE as f32;
-                    }
-                }
+            // Waiting is a move that goes nowhere
+            PlayerAction::Wait => None,
+            _ => continue,
+        };
+        for mut player in player_query.iter_mut() {
+            let Some(direction) = direction else {
+                end_player_turn(&mut player.2, &mut state);
+                continue;
+            };
+            // Squeezing past a corner does not use up a turn either
+            if !can_step(&map, &player.0, direction) {
+                continue;
             }
-        } else if keyboard_input.just_pressed(KeyCode::S) {
-            match map
-                .tiles
-                .get(&Point::new(player.0.x as usize, player.0.y as usize - 1))
-            {
-                Some(TileTypeMap(TileType::WALL)) => { //nothing
-                }
-                _ => {
-                    let mut is_attack = false;
-                    for mut mob in mob_query.iter_mut() {
-                        if mob.0.x == player.0.x && mob.0.y == player.0.y - 1 {
-                            // Attack mob
-                            is_attack = true;
-                            mob.2.0 = mob.2.0 - 1;
-                            break;
+            let target = direction.step(&player.0);
+            let (dx, dy) = direction.delta();
+            let point = Point::new(target.x as usize, target.y as usize);
+            match map.tile_at(target.x, target.y) {
+                Some(TileType::CLOSEDDOOR | TileType::LOCKEDDOOR) => {
+                    let (text, category) = match open_door(&mut map, point, Some(&mut player.5)) {
+                        DoorOutcome::Opened => ("You open the door.", MessageCategory::Info),
+                        DoorOutcome::Unlocked => (
+                            "You unlock the door with a key.",
+                            MessageCategory::Discovery,
+                        ),
+                        DoorOutcome::Locked => {
+                            messages.send(GameMessage::new(
+                                "The door is locked. You need a key.",
+                                MessageCategory::Info,
+                            ));
+                            continue;
                         }
-                    }
-                    if !is_attack {
-                        player.0.y -= 1;
-                        player.1.translation.y -= 1.0 * TILESIZE as f32;
-                    }
+                    };
+                    messages.send(GameMessage::new(text, category));
+                    tile_changes.send(TileChanged(point));
+                    end_player_turn(&mut player.2, &mut state);
+                    continue;
                 }
+                // Bumping into a wall or the edge of the map does not use up a turn
+                _ if !map.is_passable_for_player(target.x, target.y) => continue,
+                _ => {}
             }
-        } else if keyboard_input.just_pressed(KeyCode::D) {
-            match map
-                .tiles
-                .get(&Point::new(player.0.x as usize + 1, player.0.y as usize))
-            {
-                Some(TileTypeMap(TileType::WALL)) => { //nothing
+            match mob_query.iter_mut().find(|mob| *mob.0 == target) {
+                Some(mut mob) => {
+                    // Attack mob
+                    combat_events.send(melee(
+                        (player.3, MobType::PLAYER, player.4),
+                        (mob.3, mob.1 .0.clone(), mob.4),
+                        &mut mob.2,
+                        &mut map.rng,
+                    ));
                 }
-                _ => {
-                    let mut is_attack = false;
-                    for mut mob in mob_query.iter_mut() {
-                        if mob.0.x == player.0.x + 1 && mob.0.y == player.0.y {
-                            // Attack mob
-                            mob.2.0 = mob.2.0 - 1;
-                            is_attack = true;
-                            break;
-                        }
-                    }
-                    if !is_attack {
-                        player.0.x += 1;
-                        player.1.translation.x += 1.0 * TILESIZE as f32;
-                    }
+                None => {
+                    // Water and rubble take longer to get through
+                    player.2 .0 -= map.move_cost(target.x, target.y) - ACTION_COST;
+                    *player.0 = target;
+                    player.1.translation.x += dx as f32 * TILESIZE as f32;
+                    player.1.translation.y += dy as f32 * TILESIZE as f32;
                 }
             }
+            end_player_turn(&mut player.2, &mut state);
         }
     }
 }
@@ -1,171 +1,196 @@
-// Responsible for keeping camera on player
-// Updating lighting
-// Despawning tile under enemies and players
+// Responsible for keeping camera on player, inside the level and clear of the UI panels
+// Drawing what the player can see and remembers
+// Every cell of the level has one sprite, spawned with the level and hidden until the player
+// sees it. Sprites are recolored in place, so the number of entities does not grow as the
+// player explores.
+use std::collections::{HashMap, HashSet};
+
 use crate::{
-    components::{LitTile, MainCamera, Mob, MobType, Position, POV},
-    map::Level,
+    components::{MainCamera, Mob, Position, POV},
+    lighting::LightMap,
+    map::{Level, TileChanged},
+    messages::{with_article, GameMessage, MessageCategory, PANEL_WIDTH},
     player::Player,
     resources::GlyphAssets,
-    tiles::{FloorBundle, TileType, TileTypeMap, WallBundle, TILESIZE},
+    tiles::{Tile, TileBundle, TILESIZE},
+    ui::GLYPH_SIZE,
+    vision::VisionUpdate,
 };
 use bevy::prelude::*;
 use map_gen_2d::Point;
 
 pub const WINDOWSIZE: (f32, f32) = (800.0, 500.0);
+/// Height of the status lines along the top of the window, kept clear of the map.
+pub const STATUS_HEIGHT: f32 = 3.0 * GLYPH_SIZE;
+pub const CAMERA_Z: f32 = 999.0;
+/// How quickly the camera catches up with the player, higher is quicker.
+const CAMERA_SPEED: f32 = 10.0;
+/// The camera jumps instead of sliding when it is this far behind, e.g. on another level.
+const CAMERA_JUMP: f32 = WINDOWSIZE.1 / 2.0;
+
+/// The sprite of every cell of the level on screen.
+#[derive(Resource, Default)]
+pub struct TileSprites {
+    pub sprites: HashMap<Point, Entity>,
+    // Cells the player sees right now
+    lit: HashSet<Point>,
+    // Every sprite needs its color set, e.g. after a new level was shown
+    repaint: bool,
+}
+
 pub struct RenderingPlugin;
 impl Plugin for RenderingPlugin {
     fn build(&self, app: &mut App) {
-        app.add_system_set_to_stage(
-            CoreStage::PostUpdate,
-            SystemSet::new()
-                .with_system(update_camera_position)
-                .with_system(update_tile_vis_and_explore.after(update_camera_position))
-                .with_system(update_tiles.after(update_tile_vis_and_explore))
-                .with_system(update_mob_vis.after(update_tiles)),
-        );
+        app.init_resource::<TileSprites>()
+            .add_system_set_to_stage(
+                CoreStage::PostUpdate,
+                SystemSet::new()
+                    .with_system(update_camera_position)
+                    .with_system(update_tiles.after(VisionUpdate))
+                    .with_system(update_mob_vis.after(update_tiles)),
+            )
+            .add_system(show_level)
+            .add_system_to_stage(
+                CoreStage::PostUpdate,
+                redraw_changed_tiles.before(update_tiles),
+            );
     }
 }
 
-fn update_tile_vis_and_explore(
-    mut mob_query: Query<(&Position, &mut POV, &Mob), (Changed<Position>, With<Mob>)>,
-    mut map: ResMut<Level>,
+// Spawns the sprites of a level once the ones of the last level are gone, at the start of
+// the game, on another level and after a restart.
+fn show_level(
+    mut commands: Commands,
+    atlas: Res<GlyphAssets>,
+    map: Res<Level>,
+    mut sprites: ResMut<TileSprites>,
+    tile_query: Query<(), With<Tile>>,
 ) {
-    // Need to calculate what the mob can see.
-    for (position, mut pov, mob) in mob_query.iter_mut() {
-        pov.visible_tiles.clear();
-        pov.newly_revealed_tiles.clear();
-        // Calculate visible tiles near position.
-        // loop over an range x range loop centered around player.
-        for x in (position.x - pov.range)..(position.x + pov.range) {
-            for y in (position.y - pov.range)..(position.y + pov.range) {
-                if let Some(light) =
-                    bresenham_line_enhanced(&(position.x, position.y), &(x, y), &map)
-                {
-                    pov.visible_tiles.push((Position { x, y }, light));
-
-                    if mob.0 == MobType::PLAYER
-                        && map.revealed_tiles.insert(Point {
-                            x: x as usize,
-                            y: y as usize,
-                        }) == true
-                    {
-                        pov.newly_revealed_tiles.push(Position { x, y })
-                    }
-                }
-            }
-        }
-        pov.visible_tiles.retain(|p| {
-            p.0.x >= 0
-                && p.0.x < map.size.0.try_into().unwrap()
-                && p.0.y >= 0
-                && p.0.y < map.size.1.try_into().unwrap()
-        });
+    if !tile_query.is_empty() {
+        return;
     }
+    sprites.sprites = spawn_tiles(&mut commands, &atlas, &map);
+    sprites.lit.clear();
+    sprites.repaint = true;
+}
+
+/// Spawns a hidden sprite for every cell of the level.
+pub fn spawn_tiles(
+    commands: &mut Commands,
+    atlas: &GlyphAssets,
+    map: &Level,
+) -> HashMap<Point, Entity> {
+    map.tiles
+        .iter()
+        .map(|(point, tile)| {
+            let entity = commands
+                .spawn(TileBundle::new(
+                    (point.x, point.y),
+                    atlas.atlas.clone(),
+                    tile.0,
+                ))
+                .id();
+            (*point, entity)
+        })
+        .collect()
 }
 
 fn update_tiles(
-    mut commands: Commands,
-    atlas: Res<GlyphAssets>,
-    tile_query: Query<(Entity, With<LitTile>)>,
-    player_query: Query<(&Position, &POV), (Changed<Position>, With<Player>)>,
-    mob_query: Query<&Position, With<Mob>>,
+    mut sprites: ResMut<TileSprites>,
+    // What the player sees changes when they move or a door opens
+    player_query: Query<&POV, With<Player>>,
+    changed_query: Query<(), (Changed<POV>, With<Player>)>,
+    mut tile_query: Query<(&Tile, &mut TextureAtlasSprite, &mut Visibility)>,
     map: Res<Level>,
+    light_map: Res<LightMap>,
 ) {
-    for player in player_query.iter() {
-        for entity in tile_query.iter() {
-            // Despawn all lit-tiles
-            commands.entity(entity.0).despawn();
-        }
-        // Spawn lit tiles
-        for tile in player.1.visible_tiles.iter() {
-            let color: Color;
-            // HARD CODE LIGHT LEVEL TO COLOR
-            if tile.1 == 0 {
-                color = Color::rgb(1.0, 1.0, 0.0);
-            } else if tile.1 == 1 {
-                color = Color::rgb(1.0, 1.0, 0.0);
-            } else if tile.1 == 2 {
-                color = Color::rgb(1.0, 1.0, 0.0);
-            } else if tile.1 == 3 {
-                color = Color::rgb(1.0 - (0.1 * 3.0), 1.0 - (0.1 * 3.0), 0.0 + (0.05 * 3.0));
-            } else if tile.1 == 4 {
-                color = Color::rgb(1.0 - (0.1 * 3.0), 1.0 - (0.1 * 3.0), 0.0 + (0.05 * 3.0));
-            } else if tile.1 == 5 {
-                color = Color::rgb(1.0 - (0.1 * 5.0), 1.0 - (0.1 * 5.0), 0.0 + (0.05 * 5.0));
-            } else if tile.1 == 6 {
-                color = Color::rgb(1.0 - (0.1 * 5.0), 1.0 - (0.1 * 5.0), 0.0 + (0.05 * 5.0));
-            } else {
-                color = Color::rgb(1.0 - (0.1 * 5.0), 1.0 - (0.1 * 5.0), 0.0 + (0.05 * 5.0));
-            }
-            // Make sure we do not spawn tiles underneath mob or player
-            if mob_query
-                .iter()
-                .any(|&mob| mob.x != tile.0.x && mob.y != tile.0.y)
-            {
-                match map.tiles.get(&Point {
-                    x: tile.0.x as usize,
-                    y: tile.0.y as usize,
-                }) {
-                    Some(TileTypeMap(TileType::WALL)) => {
-                        commands
-                            .spawn(WallBundle::new(
-                                (tile.0.x.try_into().unwrap(), tile.0.y.try_into().unwrap()),
-                                atlas.atlas.clone(),
-                                color,
-                            ))
-                            .insert(LitTile);
-                    }
-                    Some(TileTypeMap(TileType::FLOOR)) => {
-                        commands
-                            .spawn(FloorBundle::new(
-                                (tile.0.x.try_into().unwrap(), tile.0.y.try_into().unwrap()),
-                                atlas.atlas.clone(),
-                                color,
-                            ))
-                            .insert(LitTile);
-                    }
-                    None => {
-                        //nothing
-                    }
-                }
+    if changed_query.is_empty() && !sprites.repaint {
+        return;
+    }
+    let TileSprites {
+        sprites,
+        lit,
+        repaint,
+    } = &mut *sprites;
+    // Tiles that are no longer seen are drawn as remembered
+    let remembered: Vec<Point> = if *repaint {
+        map.revealed_tiles.iter().copied().collect()
+    } else {
+        lit.drain().collect()
+    };
+    *repaint = false;
+    lit.clear();
+    for point in remembered {
+        let Some(mut tile) = sprites
+            .get(&point)
+            .and_then(|&entity| tile_query.get_mut(entity).ok())
+        else {
+            continue;
+        };
+        tile.1.color = tile.0 .0.color(Color::GRAY);
+        tile.2.is_visible = map.revealed_tiles.contains(&point);
+    }
+    for pov in player_query.iter() {
+        for (position, _) in pov.visible_tiles.iter() {
+            if position.x < 0 || position.y < 0 {
+                continue;
             }
+            let point = Point::new(position.x as usize, position.y as usize);
+            let Some(mut tile) = sprites
+                .get(&point)
+                .and_then(|&entity| tile_query.get_mut(entity).ok())
+            else {
+                continue;
+            };
+            // Colored by whatever light reaches it
+            let light = light_map.color_at(position.x, position.y, Color::WHITE);
+            tile.1.color = tile.0 .0.color(light);
+            tile.2.is_visible = true;
+            lit.insert(point);
         }
+    }
+}
 
-        // Spawn newly revealed tiles
-        for tile in player.1.newly_revealed_tiles.iter() {
-            match map.tiles.get(&Point {
-                x: tile.x as usize,
-                y: tile.y as usize,
-            }) {
-                Some(TileTypeMap(TileType::WALL)) => {
-                    commands.spawn(WallBundle::new(
-                        (tile.x.try_into().unwrap(), tile.y.try_into().unwrap()),
-                        atlas.atlas.clone(),
-                        Color::GRAY,
-                    ));
-                }
-                Some(TileTypeMap(TileType::FLOOR)) => {
-                    commands.spawn(FloorBundle::new(
-                        (tile.x.try_into().unwrap(), tile.y.try_into().unwrap()),
-                        atlas.atlas.clone(),
-                        Color::GRAY,
-                    ));
-                }
-                None => {
-                    //nothing
-                }
-            }
+// Changes the glyph of tiles that changed, e.g. a door that was opened. Tiles the player sees
+// are recolored by update_tiles, since what the player sees changes with them.
+fn redraw_changed_tiles(
+    map: Res<Level>,
+    sprites: Res<TileSprites>,
+    mut tile_query: Query<(&mut Tile, &mut TextureAtlasSprite)>,
+    mut tile_changes: EventReader<TileChanged>,
+) {
+    for TileChanged(point) in tile_changes.iter() {
+        let (Some(&entity), Some(tile_type)) = (sprites.sprites.get(point), map.tiles.get(point))
+        else {
+            continue;
+        };
+        if let Ok(mut tile) = tile_query.get_mut(entity) {
+            tile.0 .0 = tile_type.0;
+            tile.1.index = tile_type.0.glyph();
+            tile.1.color = tile_type.0.color(Color::GRAY);
         }
     }
 }
 
 fn update_mob_vis(
-    player_query: Query<(&Position, &POV), (Changed<Position>, With<Player>)>,
-    mut mob_query: Query<(&Position, &mut TextureAtlasSprite), (With<Mob>, Without<Player>)>,
+    player_query: Query<(&Position, &POV), With<Player>>,
+    moved_query: Query<(), (Changed<Position>, With<Mob>)>,
+    mut mob_query: Query<(&Position, &mut TextureAtlasSprite, &Mob), Without<Player>>,
+    mut messages: EventWriter<GameMessage>,
 ) {
+    // Enemies move on their own, so check whenever anything has moved
+    if moved_query.is_empty() {
+        return;
+    }
     for player in player_query.iter() {
         for mut mob in mob_query.iter_mut() {
             if player.1.visible_tiles.iter().any(|&tile| tile.0 == *mob.0) {
+                if mob.1.color.a() == 0.0 {
+                    messages.send(GameMessage::new(
+                        format!("You see {}.", with_article(mob.2 .0.name())),
+                        MessageCategory::Discovery,
+                    ));
+                }
                 mob.1.color.set_a(1.0);
             } else {
                 mob.1.color.set_a(0.0);
@@ -174,166 +199,85 @@ fn update_mob_vis(
     }
 }
 
-// Returns none if line is out of range / not visible
-// Returns some(i32) where i32 is light level
-// https://sites.google.com/site/jicenospam/visibilitydetermination
-// https://en.wikipedia.org/wiki/Bresenham%27s_line_algorithm#Algorithm_for_integer_arithmetic
-fn bresenham_line_enhanced(
-    position: &(i32, i32),
-    tile_position: &(i32, i32),
-    map: &Level,
-) -> Option<i32> {
-    // position = (x0,y0) and tile_position = (x1,y1)
-
-    if (tile_position.1 - position.1).abs() < (tile_position.0 - position.0) {
-        if position.0 > tile_position.0 {
-            return bresenham_line_enhanced_low(tile_position, position, map);
-        } else {
-            return bresenham_line_enhanced_low(position, tile_position, map);
-        }
-    } else {
-        if position.1 > tile_position.1 {
-            return bresenham_line_enhanced_high(tile_position, position, map);
+/// Where the camera goes to show the player at `player` on a level of `size`. The player is
+/// kept in the middle of the part of the window left for the map, but the view stops at the
+/// edges of the level. Levels smaller than the view are shown in the middle of it.
+pub fn camera_target(player: Position, size: (usize, usize)) -> Vec2 {
+    let tile = TILESIZE as f32;
+    let view = Vec2::new(WINDOWSIZE.0 - PANEL_WIDTH, WINDOWSIZE.1 - STATUS_HEIGHT);
+    // From the middle of the window to the middle of the map view
+    let offset = Vec2::new(-PANEL_WIDTH / 2.0, -STATUS_HEIGHT / 2.0);
+    // Edges of the level, tile positions are the middle of the tile
+    let low = Vec2::splat(-tile / 2.0);
+    let high = Vec2::new(size.0 as f32, size.1 as f32) * tile - tile / 2.0;
+    let player = Vec2::new(player.x as f32, player.y as f32) * tile;
+    let follow = |player: f32, low: f32, high: f32, view: f32| {
+        if high - low <= view {
+            (low + high) / 2.0
         } else {
-            return bresenham_line_enhanced_high(position, tile_position, map);
+            player.clamp(low + view / 2.0, high - view / 2.0)
         }
-    }
+    };
+    let center = Vec2::new(
+        follow(player.x, low.x, high.x, view.x),
+        follow(player.y, low.y, high.y, view.y),
+    );
+    center - offset
 }
 
-fn bresenham_line_enhanced_high(
-    position: &(i32, i32),
-    tile_position: &(i32, i32),
-    map: &Level,
-) -> Option<i32> {
-    // Make sure all positions are positive
-    if position.0 < 0 || position.1 < 0 || tile_position.0 < 0 || tile_position.1 < 0 {
-        return None;
-    }
-    // If we were passed a wall, obivously we can see the wall so pass Some(distance)
-    if let Some(tile) = map.tiles.get(&Point {
-        x: position.0.try_into().unwrap(),
-        y: position.1.try_into().unwrap(),
-    }) {
-        if tile.0 == TileType::WALL {
-            let distance =
-                (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
-            return Some(distance);
-        }
-    }
-    // Algorithm
-    let mut dx: i32 = tile_position.0 - position.0;
-    let dy: i32 = tile_position.1 - position.1;
-    let mut xi: i32 = 1;
-    if dx < 0 {
-        xi = -1;
-        dx = -dx
-    }
-    let mut d: i32 = (2 * dx) - dy;
-    let mut x: i32 = position.0;
-    for y in position.1..=tile_position.1 {
-        // If this is a wall, we can not see the next tile.
-        if let Some(tile) = map.tiles.get(&Point {
-            x: x.try_into().unwrap(),
-            y: y.try_into().unwrap(),
-        }) {
-            if tile.0 == TileType::WALL && x == tile_position.0 && y == tile_position.1 {
-                let distance =
-                    (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
-                return Some(distance);
-            } else if tile.0 == TileType::WALL && x == position.0 && y == position.1 {
-                let distance =
-                    (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
-                return Some(distance);
-            } else if tile.0 == TileType::WALL {
-                return None;
-            }
-        } else {
-            return None;
-        }
-        // Progress algorithm
-        if d > 0 {
-            x = x + xi;
-            d = d + (2 * (dx - dy))
-        } else {
-            d = d + 2 * dx;
-        }
+fn update_camera_position(
+    time: Res<Time>,
+    map: Res<Level>,
+    player_query: Query<&Position, With<Player>>,
+    mut camera_query: Query<&mut Transform, (With<MainCamera>, Without<Player>)>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    let target = camera_target(*player, map.size).extend(CAMERA_Z);
+    let mut camera_transform = camera_query.single_mut();
+    let distance = camera_transform.translation.distance(target);
+    if !(0.5..=CAMERA_JUMP).contains(&distance) {
+        camera_transform.translation = target;
+    } else {
+        // Slides most of the way quickly and then eases in, at any frame rate
+        let step = 1.0 - (-CAMERA_SPEED * time.delta_seconds()).exp();
+        camera_transform.translation = camera_transform.translation.lerp(target, step);
     }
-    // If we have gotten here, we have succesfully reached the tile.
-    let distance = (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
-    Some(distance)
 }
 
-fn bresenham_line_enhanced_low(
-    position: &(i32, i32),
-    tile_position: &(i32, i32),
-    map: &Level,
-) -> Option<i32> {
-    // Make sure all positions are postive
-    if position.0 < 0 || position.1 < 0 || tile_position.0 < 0 || tile_position.1 < 0 {
-        return None;
-    }
-    // If we were passed a wall, obivously we can see the wall so pass Some(distance)
-    if let Some(tile) = map.tiles.get(&Point {
-        x: position.0.try_into().unwrap(),
-        y: position.1.try_into().unwrap(),
-    }) {
-        if tile.0 == TileType::WALL {
-            let distance =
-                (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
-            return Some(distance);
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target(x: i32, y: i32, size: (usize, usize)) -> Vec2 {
+        camera_target(Position { x, y }, size)
     }
-    let dx: i32 = tile_position.0 - position.0;
-    let mut dy: i32 = tile_position.1 - position.1;
-    let mut yi: i32 = 1;
-    if dy < 0 {
-        yi = -1;
-        dy = -dy
+
+    #[test]
+    fn camera_follows_the_player_away_from_the_edges() {
+        let middle = target(100, 100, (200, 200));
+        let moved = target(101, 99, (200, 200));
+        assert_eq!(
+            moved - middle,
+            Vec2::new(TILESIZE as f32, -(TILESIZE as f32))
+        );
     }
-    let mut d: i32 = (2 * dy) - dx;
-    let mut y: i32 = position.1;
-    for x in position.0..=tile_position.0 {
-        if let Some(tile) = map.tiles.get(&Point {
-            x: x.try_into().unwrap(),
-            y: y.try_into().unwrap(),
-        }) {
-            if tile.0 == TileType::WALL && x == tile_position.0 && y == tile_position.1 {
-                let distance =
-                    (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
-                return Some(distance);
-            } else if tile.0 == TileType::WALL && x == position.0 && y == position.1 {
-                let distance =
-                    (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
-                return Some(distance);
-            } else if tile.0 == TileType::WALL {
-                return None;
-            }
-        } else {
-            return None;
-        }
-        // Progress algorithm
-        if d > 0 {
-            y = y + yi;
-            d = d + (2 * (dy - dx))
-        } else {
-            d = d + 2 * dy;
-        }
+
+    #[test]
+    fn camera_stops_at_the_edges_of_the_level() {
+        assert_eq!(target(0, 0, (200, 200)), target(5, 5, (200, 200)));
+        assert_eq!(target(199, 199, (200, 200)), target(194, 196, (200, 200)));
+        // The bottom left corner of the level is at the bottom left of the map view
+        let corner = target(0, 0, (200, 200));
+        let window_left = corner.x - WINDOWSIZE.0 / 2.0;
+        let window_bottom = corner.y - WINDOWSIZE.1 / 2.0;
+        assert_eq!(window_left, -(TILESIZE as f32) / 2.0);
+        assert_eq!(window_bottom, -(TILESIZE as f32) / 2.0);
     }
-    // If we have gotten here, we have succesfully reached the tile.
-    let distance = (position.0 - tile_position.0).abs() + (position.1 - tile_position.1).abs();
-    Some(distance)
-}
 
-fn update_camera_position(
-    player_query: Query<&Position, (Changed<Position>, With<Player>)>,
-    mut camera_query: Query<&mut Transform, (With<MainCamera>, Without<Player>)>,
-) {
-    for player_pos in player_query.iter() {
-        let mut camera_transform = camera_query.single_mut();
-        camera_transform.translation = Vec3::new(
-            player_pos.x as f32 * TILESIZE as f32,
-            player_pos.y as f32 * TILESIZE as f32,
-            999.0,
-        );
+    #[test]
+    fn small_levels_stay_in_the_middle() {
+        assert_eq!(target(1, 1, (30, 30)), target(28, 28, (30, 30)));
     }
 }
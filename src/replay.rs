@@ -0,0 +1,366 @@
+// Recording the players actions and playing them back.
+// A recording is a text file. The first line holds the version, dungeon seed, generator and
+// level size, and every line after it is one PlayerAction, all written as RON. Starting from
+// the same seed and sending the same actions plays out the same game.
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    actions::{ActionInput, PlayerAction},
+    components::MainCamera,
+    map::Dungeon,
+    mapgen::GeneratorKind,
+    messages::{GameMessage, MessageCategory},
+    options::Options,
+    rendering::WINDOWSIZE,
+    save::{data_path, ResumedGame},
+    turns::GameState,
+    ui::{GlyphText, GLYPH_SIZE},
+};
+
+/// Bump this whenever the recording format, level generation or the rules change. An old
+/// recording would still load, but play out in a different dungeon.
+pub const RECORDING_VERSION: u32 = 7;
+/// Actions played per second when a replay starts.
+const DEFAULT_SPEED: f32 = 4.0;
+const MAX_SPEED: f32 = 64.0;
+const MIN_SPEED: f32 = 0.5;
+
+#[derive(Serialize, Deserialize)]
+struct RecordingHeader {
+    version: u32,
+    seed: u64,
+    /// None if the generator is picked by depth.
+    generator: Option<GeneratorKind>,
+    size: (usize, usize),
+}
+
+pub struct Recording {
+    pub seed: u64,
+    pub generator: Option<GeneratorKind>,
+    pub size: (usize, usize),
+    pub actions: Vec<PlayerAction>,
+}
+
+impl Recording {
+    pub fn parse(text: &str) -> Result<Recording, String> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header = lines.next().ok_or("the recording is empty")?;
+        let header: RecordingHeader =
+            ron::from_str(header).map_err(|err| format!("line 1: {}", err))?;
+        if header.version != RECORDING_VERSION {
+            return Err(format!(
+                "recording is version {}, this build reads version {}",
+                header.version, RECORDING_VERSION
+            ));
+        }
+        let actions = lines
+            .enumerate()
+            .map(|(i, line)| ron::from_str(line).map_err(|err| format!("line {}: {}", i + 2, err)))
+            .collect::<Result<_, _>>()?;
+        Ok(Recording {
+            seed: header.seed,
+            generator: header.generator,
+            size: header.size,
+            actions,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Recording, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        Recording::parse(&text)
+    }
+}
+
+/// Writes every action the player takes to the recording file.
+#[derive(Resource)]
+pub struct Recorder {
+    path: PathBuf,
+    /// Seed of the game being recorded, None until the first action.
+    seed: Option<u64>,
+    file: Option<BufWriter<File>>,
+}
+
+impl Recorder {
+    pub fn new(path: PathBuf) -> Self {
+        Recorder {
+            path,
+            seed: None,
+            file: None,
+        }
+    }
+
+    // Starts the file over for a new game.
+    fn start(&mut self, dungeon: &Dungeon) -> std::io::Result<()> {
+        self.seed = Some(dungeon.seed);
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = BufWriter::new(File::create(&self.path)?);
+        let header = RecordingHeader {
+            version: RECORDING_VERSION,
+            seed: dungeon.seed,
+            generator: dungeon.generator,
+            size: dungeon.size,
+        };
+        writeln!(file, "{}", ron::to_string(&header).unwrap())?;
+        file.flush()?;
+        self.file = Some(file);
+        Ok(())
+    }
+
+    fn record(&mut self, action: &PlayerAction) -> std::io::Result<()> {
+        if let Some(file) = self.file.as_mut() {
+            writeln!(file, "{}", ron::to_string(action).unwrap())?;
+            // Flush right away so a crash still leaves a useful recording
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Plays back a recording in place of the keyboard.
+#[derive(Resource)]
+pub struct Replay {
+    pub actions: Vec<PlayerAction>,
+    /// Index of the next action to send.
+    pub next: usize,
+    pub paused: bool,
+    /// Send one action even though the replay is paused.
+    pub step: bool,
+    /// Actions per second.
+    pub speed: f32,
+    pub timer: Timer,
+}
+
+impl Replay {
+    pub fn new(actions: Vec<PlayerAction>) -> Self {
+        Replay {
+            actions,
+            next: 0,
+            paused: false,
+            step: false,
+            speed: DEFAULT_SPEED,
+            timer: Timer::from_seconds(1.0 / DEFAULT_SPEED, TimerMode::Repeating),
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.actions.len()
+    }
+}
+
+/// Shows how far along the replay is.
+#[derive(Component)]
+pub struct ReplayText;
+
+// ============================
+// ========== PLUGIN ==========
+pub struct ReplayPlugin;
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup)
+            .add_system(record_actions.after(ActionInput))
+            .add_system(replay_controls)
+            .add_system(update_replay_text)
+            .add_system_set(
+                SystemSet::on_update(GameState::AwaitingInput)
+                    .with_system(play_back.label(ActionInput)),
+            );
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    options: Res<Options>,
+    replay: Option<Res<Replay>>,
+    camera_query: Query<Entity, With<MainCamera>>,
+) {
+    if replay.is_none() {
+        let path = options
+            .record
+            .clone()
+            .unwrap_or_else(|| data_path().join("last-session.replay"));
+        commands.insert_resource(Recorder::new(path));
+        return;
+    }
+    let camera = camera_query.single();
+    commands.entity(camera).with_children(|parent| {
+        parent.spawn((
+            GlyphText::new("", Color::YELLOW),
+            ReplayText,
+            SpatialBundle::from_transform(Transform::from_xyz(
+                -WINDOWSIZE.0 / 2.0 + GLYPH_SIZE,
+                WINDOWSIZE.1 / 2.0 - GLYPH_SIZE * 2.5,
+                -1.0,
+            )),
+        ));
+    });
+}
+
+fn record_actions(
+    recorder: Option<ResMut<Recorder>>,
+    dungeon: Res<Dungeon>,
+    resumed: Option<Res<ResumedGame>>,
+    mut actions: EventReader<PlayerAction>,
+) {
+    // A game loaded from a save can not be replayed from its seed
+    let (Some(mut recorder), None) = (recorder, resumed) else {
+        actions.clear();
+        return;
+    };
+    for action in actions.iter() {
+        let mut result = Ok(());
+        if recorder.seed != Some(dungeon.seed) {
+            result = recorder.start(&dungeon);
+        }
+        result = result.and_then(|_| recorder.record(action));
+        if let Err(err) = result {
+            error!(
+                "Could not record to {}, recording stopped: {}",
+                recorder.path.display(),
+                err
+            );
+            recorder.file = None;
+        }
+    }
+}
+
+fn replay_controls(keyboard_input: Res<Input<KeyCode>>, replay: Option<ResMut<Replay>>) {
+    let Some(mut replay) = replay else {
+        return;
+    };
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        replay.paused = !replay.paused;
+    }
+    if keyboard_input.any_just_pressed([KeyCode::Period, KeyCode::Right]) {
+        replay.paused = true;
+        replay.step = true;
+    }
+    let speed = if keyboard_input.just_pressed(KeyCode::Up) {
+        (replay.speed * 2.0).min(MAX_SPEED)
+    } else if keyboard_input.just_pressed(KeyCode::Down) {
+        (replay.speed / 2.0).max(MIN_SPEED)
+    } else {
+        return;
+    };
+    replay.speed = speed;
+    replay
+        .timer
+        .set_duration(std::time::Duration::from_secs_f32(1.0 / speed));
+}
+
+fn play_back(
+    time: Res<Time>,
+    replay: Option<ResMut<Replay>>,
+    mut actions: EventWriter<PlayerAction>,
+    mut messages: EventWriter<GameMessage>,
+) {
+    let Some(mut replay) = replay else {
+        return;
+    };
+    if replay.is_finished() {
+        return;
+    }
+    replay.timer.tick(time.delta());
+    let due = !replay.paused && replay.timer.just_finished();
+    if !due && !replay.step {
+        return;
+    }
+    replay.step = false;
+    let action = replay.actions[replay.next];
+    replay.next += 1;
+    actions.send(action);
+    if replay.is_finished() {
+        messages.send(GameMessage::new("End of replay.", MessageCategory::Info));
+    }
+}
+
+fn update_replay_text(
+    replay: Option<Res<Replay>>,
+    mut text_query: Query<&mut GlyphText, With<ReplayText>>,
+) {
+    let Some(replay) = replay else {
+        return;
+    };
+    if !replay.is_changed() {
+        return;
+    }
+    let status = if replay.is_finished() {
+        "done"
+    } else if replay.paused {
+        "paused"
+    } else {
+        "playing"
+    };
+    let text = format!(
+        "REPLAY {}/{} x{} {}  Space pause  . step  Up/Down speed",
+        replay.next,
+        replay.actions.len(),
+        replay.speed,
+        status
+    );
+    for mut replay_text in text_query.iter_mut() {
+        if replay_text.text != text {
+            replay_text.text = text.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(version: u32) -> String {
+        format!(
+            "(version: {}, seed: 42, generator: None, size: (50, 50))",
+            version
+        )
+    }
+
+    #[test]
+    fn parse_recording() {
+        let text = format!(
+            "{}\nMove(dx: 0, dy: 1)\n\nPickUp\nDrop(2)\nDescend\n",
+            header(RECORDING_VERSION)
+        );
+        let recording = Recording::parse(&text).unwrap();
+        assert_eq!(recording.seed, 42);
+        assert_eq!(recording.size, (50, 50));
+        assert_eq!(
+            recording.actions,
+            vec![
+                PlayerAction::Move { dx: 0, dy: 1 },
+                PlayerAction::PickUp,
+                PlayerAction::Drop(2),
+                PlayerAction::Descend,
+            ]
+        );
+    }
+
+    #[test]
+    fn bad_lines_are_reported() {
+        let text = format!("{}\nPickUp\nJump\n", header(RECORDING_VERSION));
+        let Err(err) = Recording::parse(&text) else {
+            panic!("a broken recording was read");
+        };
+        assert!(err.starts_with("line 3"));
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let Err(err) = Recording::parse(&header(RECORDING_VERSION - 1)) else {
+            panic!("a recording from another version was read");
+        };
+        assert!(err.contains("version"), "{}", err);
+        // Nothing in the header is optional
+        let text = format!("(version: {}, seed: 42)", RECORDING_VERSION);
+        assert!(Recording::parse(&text).is_err());
+    }
+}
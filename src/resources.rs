@@ -1,6 +1,19 @@
+use std::path::{Path, PathBuf};
+
 use bevy::prelude::*;
 
 #[derive(Resource)]
 pub struct GlyphAssets {
     pub atlas: Handle<TextureAtlas>,
-}
\ No newline at end of file
+}
+/// Where a file under assets/ is: next to the game if it came with an assets folder, else in
+/// the source tree it was built from.
+pub fn asset_path(name: &str) -> PathBuf {
+    let next_to_game = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.parent()?.join("assets")));
+    next_to_game
+        .filter(|dir| dir.is_dir())
+        .unwrap_or_else(|| Path::new(env!("CARGO_MANIFEST_DIR")).join("assets"))
+        .join(name)
+}
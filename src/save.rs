@@ -0,0 +1,577 @@
+// Saving the game when it is closed and picking it back up on the next start.
+// The save is a RON file in the users data directory. It starts with a version number,
+// and saves written by another version are not loaded. The game is only saved while it is
+// the players turn, with the monsters still to act this round saved in order, so a resumed
+// game hands out turns just as the saved one would have.
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::PathBuf,
+};
+
+use bevy::{app::AppExit, ecs::system::SystemState, prelude::*, window::WindowCloseRequested};
+use map_gen_2d::Point;
+use rand_chacha::ChaCha12Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ai::Brain,
+    components::{Mob, MobType, Position},
+    enemies::{spawn_enemy, Bestiary, Enemy},
+    health::{Health, LastHitBy},
+    items::{Inventory, Item, ItemType, INVENTORY_CAPACITY},
+    keymap::{Action, Keymap},
+    lighting::LightKind,
+    map::{Dungeon, Level},
+    mapgen::GeneratorKind,
+    messages::{Message, MessageCategory, MessageLog},
+    options::Options,
+    player::{Player, PlayerBundle},
+    replay::Replay,
+    resources::GlyphAssets,
+    terrain::Burning,
+    tiles::{glyph_tile, tile_glyph, TileTypeMap},
+    turns::{Energy, GameState, TurnScheduler},
+};
+
+/// Bump this whenever the save format changes.
+pub const SAVE_VERSION: u32 = 4;
+
+/// Only read to check the version before reading the rest.
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveFile {
+    pub version: u32,
+    /// Seed of the dungeon, so a save can be traced back to how it started.
+    pub seed: u64,
+    /// Generator picked for every level, None if they are picked by depth.
+    pub generator: Option<GeneratorKind>,
+    /// Width and height of the levels.
+    pub level_size: (usize, usize),
+    pub scheduler: SavedScheduler,
+    /// The level the player is on.
+    pub level: SavedLevel,
+    /// Other levels the player has visited.
+    pub visited_levels: Vec<SavedLevel>,
+    pub player: SavedPlayer,
+    pub enemies: Vec<SavedEnemy>,
+    pub messages: Vec<Message>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedLevel {
+    pub depth: u32,
+    pub size: (usize, usize),
+    /// One string per row, see `tiles::tile_glyph`.
+    pub tiles: Vec<String>,
+    pub revealed_tiles: Vec<(usize, usize)>,
+    pub rng: ChaCha12Rng,
+    pub mobs: Vec<((usize, usize), MobType)>,
+    pub items: Vec<((usize, usize), ItemType)>,
+    pub lights: Vec<((usize, usize), LightKind)>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedScheduler {
+    pub turn: u32,
+    pub ticks: u64,
+    /// Monsters still to act this round, in order, as indices into `SaveFile::enemies`.
+    pub ready: Vec<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedPlayer {
+    pub position: Position,
+    pub health: i32,
+    pub energy: i32,
+    pub inventory: Vec<ItemType>,
+    pub last_hit_by: Option<MobType>,
+    /// Turns left to burn, see `terrain::Burning`.
+    pub burning: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SavedEnemy {
+    pub kind: MobType,
+    pub position: Position,
+    pub health: i32,
+    pub energy: i32,
+    pub brain: Brain,
+    pub burning: Option<i32>,
+}
+
+/// Present while playing a game that was loaded from a save.
+#[derive(Resource)]
+pub struct ResumedGame;
+
+/// Present when a new game was started next to a saved one, which it is not saved over.
+#[derive(Resource)]
+pub struct KeptSave;
+
+/// Mobs read from a save, waiting for the glyph atlas to be spawned with.
+#[derive(Resource)]
+pub struct LoadedMobs {
+    pub player: SavedPlayer,
+    pub enemies: Vec<SavedEnemy>,
+    /// See `SavedScheduler::ready`.
+    pub ready: Vec<usize>,
+}
+
+// ============================
+// ========== PLUGIN ==========
+pub struct SavePlugin;
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system_to_stage(StartupStage::PreStartup, load_game)
+            .add_startup_system(spawn_loaded_mobs)
+            .add_system_set(
+                SystemSet::on_update(GameState::AwaitingInput).with_system(save_and_quit),
+            )
+            .add_system(quit_when_closed)
+            .add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(delete_save))
+            .add_system_to_stage(CoreStage::Last, save_on_exit);
+    }
+}
+
+/// Where the game keeps its files, in the users data directory if there is one.
+pub fn data_path() -> PathBuf {
+    dirs::data_dir()
+        .map(|dir| dir.join("p01-rogue"))
+        .unwrap_or_default()
+}
+
+pub fn save_path() -> PathBuf {
+    data_path().join("save.ron")
+}
+
+impl SavedLevel {
+    pub fn from_level(level: &Level) -> Self {
+        // The generator can put walls just outside of `size`, so cover every tile
+        let width = level
+            .tiles
+            .keys()
+            .map(|point| point.x + 1)
+            .max()
+            .unwrap_or(0);
+        let height = level
+            .tiles
+            .keys()
+            .map(|point| point.y + 1)
+            .max()
+            .unwrap_or(0);
+        let tiles = (0..height)
+            .map(|y| {
+                (0..width)
+                    .map(|x| tile_glyph(level.tiles.get(&Point::new(x, y))))
+                    .collect()
+            })
+            .collect();
+        let mut revealed_tiles: Vec<(usize, usize)> = level
+            .revealed_tiles
+            .iter()
+            .map(|point| (point.x, point.y))
+            .collect();
+        revealed_tiles.sort();
+        SavedLevel {
+            depth: level.depth,
+            size: level.size,
+            tiles,
+            revealed_tiles,
+            rng: level.rng.clone(),
+            mobs: level
+                .mobs
+                .iter()
+                .map(|mob| ((mob.0.x, mob.0.y), mob.1.clone()))
+                .collect(),
+            items: level
+                .items
+                .iter()
+                .map(|item| ((item.0.x, item.0.y), item.1))
+                .collect(),
+            lights: level
+                .lights
+                .iter()
+                .map(|light| ((light.0.x, light.0.y), light.1))
+                .collect(),
+        }
+    }
+
+    pub fn into_level(self) -> Level {
+        let mut tiles = HashMap::new();
+        for (y, row) in self.tiles.iter().enumerate() {
+            for (x, glyph) in row.chars().enumerate() {
+                if let Some(tile) = glyph_tile(glyph) {
+                    tiles.insert(Point::new(x, y), TileTypeMap(tile));
+                }
+            }
+        }
+        Level {
+            tiles,
+            size: self.size,
+            revealed_tiles: self
+                .revealed_tiles
+                .into_iter()
+                .map(|(x, y)| Point::new(x, y))
+                .collect::<HashSet<_>>(),
+            rng: self.rng,
+            mobs: self
+                .mobs
+                .into_iter()
+                .map(|((x, y), mob)| (Point::new(x, y), mob))
+                .collect(),
+            items: self
+                .items
+                .into_iter()
+                .map(|((x, y), item)| (Point::new(x, y), item))
+                .collect(),
+            lights: self
+                .lights
+                .into_iter()
+                .map(|((x, y), light)| (Point::new(x, y), light))
+                .collect(),
+            depth: self.depth,
+        }
+    }
+}
+
+/// Reads a save, refusing saves written by a different version.
+pub fn read_save(text: &str) -> Result<SaveFile, String> {
+    let header: SaveHeader = ron::from_str(text).map_err(|err| err.to_string())?;
+    if header.version != SAVE_VERSION {
+        return Err(format!(
+            "save is version {}, this build reads version {}",
+            header.version, SAVE_VERSION
+        ));
+    }
+    ron::from_str(text).map_err(|err| err.to_string())
+}
+
+pub fn write_save(save: &SaveFile) -> Result<String, String> {
+    ron::ser::to_string_pretty(save, ron::ser::PrettyConfig::default())
+        .map_err(|err| err.to_string())
+}
+
+fn load_game(
+    mut commands: Commands,
+    options: Res<Options>,
+    replay: Option<Res<Replay>>,
+    mut log: ResMut<MessageLog>,
+) {
+    let path = save_path();
+    // Asking for a seed means asking for a new game
+    if options.seed.is_some() {
+        if !options.replace_save && path.exists() {
+            commands.insert_resource(KeptSave);
+            // A replay is never saved anyway
+            if replay.is_none() {
+                let text = format!(
+                    "This game will not be saved, there is a saved game in {}. Pass --replace-save to save over it.",
+                    path.display()
+                );
+                warn!("{}", text);
+                log.messages.push(Message {
+                    text,
+                    category: MessageCategory::Info,
+                    turn: 0,
+                });
+            }
+        }
+        return;
+    }
+    let Ok(text) = fs::read_to_string(&path) else {
+        return;
+    };
+    let mut save = match read_save(&text) {
+        Ok(save) => save,
+        Err(err) => {
+            warn!("Could not load {}: {}", path.display(), err);
+            return;
+        }
+    };
+    info!("Loaded {} (seed {})", path.display(), save.seed);
+    for conflict in options.conflicts_with_save(save.generator, save.level_size) {
+        warn!("{}", conflict);
+        save.messages.push(Message {
+            text: conflict,
+            category: MessageCategory::Info,
+            turn: save.scheduler.turn,
+        });
+    }
+    commands.add(|world: &mut World| resume_game(world, save));
+}
+
+/// Puts a saved game in place of whatever `world` holds. The mobs are spawned by
+/// `spawn_loaded_mobs` in the next startup stage, once there is a glyph atlas.
+pub fn resume_game(world: &mut World, save: SaveFile) {
+    let mut dungeon = Dungeon::new(save.seed);
+    dungeon.generator = save.generator;
+    dungeon.size = save.level_size;
+    for level in save.visited_levels {
+        dungeon.store_level(level.into_level());
+    }
+    world.insert_resource(save.level.into_level());
+    world.insert_resource(dungeon);
+    world.insert_resource(TurnScheduler {
+        turn: save.scheduler.turn,
+        ticks: save.scheduler.ticks,
+        ..default()
+    });
+    world.insert_resource(MessageLog {
+        messages: save.messages,
+    });
+    world.insert_resource(ResumedGame);
+    world.insert_resource(LoadedMobs {
+        player: save.player,
+        enemies: save.enemies,
+        ready: save.scheduler.ready,
+    });
+}
+
+pub fn spawn_loaded_mobs(
+    mut commands: Commands,
+    atlas: Res<GlyphAssets>,
+    bestiary: Res<Bestiary>,
+    mut scheduler: ResMut<TurnScheduler>,
+    loaded: Option<Res<LoadedMobs>>,
+) {
+    let Some(loaded) = loaded else {
+        return;
+    };
+    let player = &loaded.player;
+    let entity = commands
+        .spawn(PlayerBundle::new(
+            (player.position.x, player.position.y),
+            atlas.atlas.clone(),
+        ))
+        .insert((
+            Health(player.health),
+            Energy(player.energy),
+            LastHitBy(player.last_hit_by.clone()),
+            Inventory {
+                items: player.inventory.clone(),
+                capacity: INVENTORY_CAPACITY,
+            },
+        ))
+        .id();
+    if let Some(turns) = player.burning {
+        commands.entity(entity).insert(Burning(turns));
+    }
+    let mut enemies = Vec::new();
+    for enemy in loaded.enemies.iter() {
+        let location = (enemy.position.x, enemy.position.y);
+        let entity = spawn_enemy(&mut commands, &atlas, &bestiary, &enemy.kind, location);
+        if let Some(entity) = entity {
+            commands.entity(entity).insert((
+                Health(enemy.health),
+                Energy(enemy.energy),
+                enemy.brain,
+            ));
+            if let Some(turns) = enemy.burning {
+                commands.entity(entity).insert(Burning(turns));
+            }
+        }
+        enemies.push(entity);
+    }
+    // Let the monsters that had not acted yet finish the round
+    scheduler.ready = loaded
+        .ready
+        .iter()
+        .filter_map(|&i| enemies.get(i).copied().flatten())
+        .collect();
+    commands.remove_resource::<LoadedMobs>();
+}
+
+fn save_and_quit(
+    keyboard_input: Res<Input<KeyCode>>,
+    keymap: Res<Keymap>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if keymap.just_pressed(Action::SaveAndQuit, &keyboard_input) {
+        // The save itself is written on the way out
+        exit.send(AppExit);
+    }
+}
+
+// Closing the window waits for the monsters to finish their turns, so the game is never
+// saved halfway through them.
+fn quit_when_closed(
+    mut close_requests: EventReader<WindowCloseRequested>,
+    mut closing: Local<bool>,
+    state: Res<State<GameState>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if close_requests.iter().count() > 0 {
+        *closing = true;
+    }
+    if *closing && *state.current() != GameState::MonsterTurn {
+        exit.send(AppExit);
+    }
+}
+
+// A dead character can not be resumed.
+fn delete_save(replay: Option<Res<Replay>>, kept: Option<Res<KeptSave>>) {
+    // Whatever happens in a replay or next to a kept save has nothing to do with the save
+    if replay.is_some() || kept.is_some() {
+        return;
+    }
+    let _ = fs::remove_file(save_path());
+}
+
+type SaveParams<'w, 's> = (
+    Res<'w, Level>,
+    Res<'w, Dungeon>,
+    Res<'w, TurnScheduler>,
+    Res<'w, MessageLog>,
+    Query<
+        'w,
+        's,
+        (
+            &'static Position,
+            &'static Health,
+            &'static Energy,
+            &'static Inventory,
+            &'static LastHitBy,
+            Option<&'static Burning>,
+        ),
+        With<Player>,
+    >,
+    Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Position,
+            &'static Mob,
+            &'static Health,
+            &'static Energy,
+            &'static Brain,
+            Option<&'static Burning>,
+        ),
+        With<Enemy>,
+    >,
+    Query<'w, 's, (&'static Position, &'static Item)>,
+);
+
+/// The game in `world` as it would be saved, or None if there is no player.
+pub fn save_game(world: &mut World) -> Option<SaveFile> {
+    let mut params: SystemState<SaveParams> = SystemState::new(world);
+    let (map, dungeon, scheduler, log, player_query, enemy_query, item_query) = params.get(world);
+    let player = player_query.get_single().ok()?;
+    // The live entities are the truth for the current level
+    let mut level = SavedLevel::from_level(&map);
+    level.mobs.clear();
+    level.items = item_query
+        .iter()
+        .map(|item| ((item.0.x as usize, item.0.y as usize), item.1 .0))
+        .collect();
+    let mut visited_levels: Vec<SavedLevel> = dungeon
+        .levels
+        .values()
+        .map(SavedLevel::from_level)
+        .collect();
+    visited_levels.sort_by_key(|level| level.depth);
+    let alive: Vec<_> = enemy_query.iter().filter(|enemy| enemy.3 .0 > 0).collect();
+    let ready = scheduler
+        .ready
+        .iter()
+        .filter_map(|entity| alive.iter().position(|enemy| enemy.0 == *entity))
+        .collect();
+    Some(SaveFile {
+        version: SAVE_VERSION,
+        seed: dungeon.seed,
+        generator: dungeon.generator,
+        level_size: dungeon.size,
+        scheduler: SavedScheduler {
+            turn: scheduler.turn,
+            ticks: scheduler.ticks,
+            ready,
+        },
+        level,
+        visited_levels,
+        player: SavedPlayer {
+            position: *player.0,
+            health: player.1 .0,
+            energy: player.2 .0,
+            inventory: player.3.items.clone(),
+            last_hit_by: player.4 .0.clone(),
+            burning: player.5.map(|burning| burning.0),
+        },
+        enemies: alive
+            .iter()
+            .map(|enemy| SavedEnemy {
+                kind: enemy.2 .0.clone(),
+                position: *enemy.1,
+                health: enemy.3 .0,
+                energy: enemy.4 .0,
+                brain: *enemy.5,
+                burning: enemy.6.map(|burning| burning.0),
+            })
+            .collect(),
+        messages: log.messages.clone(),
+    })
+}
+
+fn save_on_exit(world: &mut World) {
+    if world.resource::<Events<AppExit>>().is_empty() {
+        return;
+    }
+    let state = *world.resource::<State<GameState>>().current();
+    if state == GameState::GameOver
+        || world.contains_resource::<Replay>()
+        || world.contains_resource::<KeptSave>()
+    {
+        return;
+    }
+    let Some(save) = save_game(world) else {
+        return;
+    };
+    let path = save_path();
+    let result = write_save(&save).and_then(|text| {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        }
+        fs::write(&path, text).map_err(|err| err.to_string())
+    });
+    match result {
+        Ok(()) => info!("Saved to {}", path.display()),
+        Err(err) => error!("Could not save to {}: {}", path.display(), err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn level_round_trip() {
+        let mut level = Level::generate(3, 2);
+        level.revealed_tiles.insert(Point::new(1, 1));
+        let saved = ron::to_string(&SavedLevel::from_level(&level)).unwrap();
+        let mut loaded = ron::from_str::<SavedLevel>(&saved).unwrap().into_level();
+        assert_eq!(loaded.depth, 2);
+        assert_eq!(loaded.size, level.size);
+        assert_eq!(loaded.revealed_tiles, level.revealed_tiles);
+        assert_eq!(loaded.mobs, level.mobs);
+        assert_eq!(loaded.tiles.len(), level.tiles.len());
+        for (point, tile) in level.tiles.iter() {
+            assert!(loaded.tiles[point].0 == tile.0);
+        }
+        // The rng carries on where it left off
+        assert_eq!(loaded.rng.gen::<u64>(), level.rng.gen::<u64>());
+    }
+
+    #[test]
+    fn other_versions_are_refused() {
+        let text = format!("(version: {}, seed: 1)", SAVE_VERSION + 1);
+        let Err(err) = read_save(&text) else {
+            panic!("a save from another version was read");
+        };
+        assert!(err.contains("version"));
+    }
+}
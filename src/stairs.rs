@@ -0,0 +1,272 @@
+// Moving between dungeon levels.
+// The level being left is stored in the Dungeon with its monsters and items where they
+// were, and all of its entities are despawned before the next level is spawned.
+// Levels are changed by taking the stairs, or by falling into a chasm.
+use bevy::{ecs::system::SystemParam, prelude::*};
+use map_gen_2d::Point;
+
+use crate::{
+    actions::{ActionInput, PlayerAction},
+    components::{Mob, Position},
+    enemies::{spawn_enemies, Bestiary, Enemy},
+    health::Health,
+    items::{spawn_items, Item},
+    lighting::{spawn_lights, FixedLight},
+    map::{random_floor_point, Dungeon, Level},
+    messages::{GameMessage, MessageCategory},
+    player::Player,
+    resources::GlyphAssets,
+    tiles::{Tile, TileType, TileTypeMap, TILESIZE},
+    turns::{end_player_turn, Energy, GameState, TurnLabel, TurnScheduler},
+    vaults::Vaults,
+};
+
+/// Sent after the player arrives on another level.
+pub struct LevelChanged;
+
+// ============================
+// ========== PLUGIN ==========
+pub struct StairsPlugin;
+impl Plugin for StairsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<LevelChanged>()
+            .add_system_set(
+                SystemSet::on_update(GameState::AwaitingInput)
+                    .with_system(use_stairs.after(ActionInput)),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::MonsterTurn)
+                    .with_system(fall_into_chasm.before(TurnLabel::Schedule)),
+            );
+    }
+}
+
+/// Everything that changes when the player goes to another level, apart from the player.
+#[allow(clippy::type_complexity)]
+#[derive(SystemParam)]
+pub struct LevelTravel<'w, 's> {
+    commands: Commands<'w, 's>,
+    atlas: Res<'w, GlyphAssets>,
+    bestiary: Res<'w, Bestiary>,
+    vaults: Res<'w, Vaults>,
+    map: ResMut<'w, Level>,
+    dungeon: ResMut<'w, Dungeon>,
+    scheduler: ResMut<'w, TurnScheduler>,
+    enemy_query: Query<
+        'w,
+        's,
+        (&'static Position, &'static Mob, &'static Health),
+        (With<Enemy>, Without<Player>),
+    >,
+    item_query: Query<'w, 's, (&'static Position, &'static Item), Without<Player>>,
+    level_entity_query:
+        Query<'w, 's, Entity, Or<(With<Enemy>, With<Tile>, With<Item>, With<FixedLight>)>>,
+    level_changes: EventWriter<'w, 's, LevelChanged>,
+}
+
+impl LevelTravel<'_, '_> {
+    /// Stores the current level in the dungeon and swaps in the one at `depth`, with its
+    /// monsters and items. Returns the new level, so the player can be placed on it.
+    pub fn go_to(&mut self, depth: u32) -> &mut Level {
+        // Remember what was left behind
+        self.map.mobs = self
+            .enemy_query
+            .iter()
+            .filter(|enemy| enemy.2 .0 > 0)
+            .map(|enemy| {
+                (
+                    Point::new(enemy.0.x as usize, enemy.0.y as usize),
+                    enemy.1 .0.clone(),
+                )
+            })
+            .collect();
+        self.map.items = self
+            .item_query
+            .iter()
+            .map(|item| (Point::new(item.0.x as usize, item.0.y as usize), item.1 .0))
+            .collect();
+        for entity in self.level_entity_query.iter() {
+            self.commands.entity(entity).despawn_recursive();
+        }
+        let level = self.dungeon.take_level(depth, &self.bestiary, &self.vaults);
+        let old_level = std::mem::replace(&mut *self.map, level);
+        self.dungeon.store_level(old_level);
+
+        spawn_enemies(&mut self.commands, &self.atlas, &self.bestiary, &self.map);
+        spawn_items(&mut self.commands, &self.atlas, &self.map);
+        spawn_lights(&mut self.commands, &self.map);
+        self.level_changes.send(LevelChanged);
+        // Anyone still waiting for a turn was on the old level
+        self.scheduler.ready.clear();
+        &mut self.map
+    }
+}
+
+fn use_stairs(
+    mut actions: EventReader<PlayerAction>,
+    mut state: ResMut<State<GameState>>,
+    mut travel: LevelTravel,
+    mut player_query: Query<(&mut Position, &mut Transform, &mut Energy), With<Player>>,
+    mut messages: EventWriter<GameMessage>,
+) {
+    let Some(stairs) = actions.iter().find_map(|action| match action {
+        PlayerAction::Descend => Some(TileType::DOWNSTAIRS),
+        PlayerAction::Ascend => Some(TileType::UPSTAIRS),
+        _ => None,
+    }) else {
+        return;
+    };
+    for (mut position, mut transform, mut energy) in player_query.iter_mut() {
+        let here = travel
+            .map
+            .tiles
+            .get(&Point::new(position.x as usize, position.y as usize));
+        if !matches!(here, Some(TileTypeMap(tile)) if *tile == stairs) {
+            let direction = if stairs == TileType::DOWNSTAIRS {
+                "down"
+            } else {
+                "up"
+            };
+            messages.send(GameMessage::new(
+                format!("There are no stairs {} here.", direction),
+                MessageCategory::Info,
+            ));
+            continue;
+        }
+        let (depth, arrive_on) = if stairs == TileType::DOWNSTAIRS {
+            (travel.map.depth + 1, TileType::UPSTAIRS)
+        } else {
+            (travel.map.depth - 1, TileType::DOWNSTAIRS)
+        };
+        let map = travel.go_to(depth);
+
+        // Arrive on the stairs leading back
+        let arrival = map.find_tile(arrive_on).unwrap();
+        position.x = arrival.x as i32;
+        position.y = arrival.y as i32;
+        transform.translation.x = position.x as f32 * TILESIZE as f32;
+        transform.translation.y = position.y as f32 * TILESIZE as f32;
+
+        let verb = if stairs == TileType::DOWNSTAIRS {
+            "descend"
+        } else {
+            "climb"
+        };
+        messages.send(GameMessage::new(
+            format!("You {} to depth {}.", verb, map.depth),
+            MessageCategory::Discovery,
+        ));
+        end_player_turn(&mut energy, &mut state);
+    }
+}
+
+// Runs as soon as the player's turn is over, before anyone on the old level gets to move.
+#[allow(clippy::type_complexity)]
+fn fall_into_chasm(
+    mut travel: LevelTravel,
+    mut player_query: Query<(&mut Position, &mut Transform), (Changed<Position>, With<Player>)>,
+    mut messages: EventWriter<GameMessage>,
+) {
+    for (mut position, mut transform) in player_query.iter_mut() {
+        if travel.map.tile_at(position.x, position.y) != Some(TileType::CHASM) {
+            continue;
+        }
+        let depth = travel.map.depth + 1;
+        let map = travel.go_to(depth);
+
+        // Land anywhere nobody is standing
+        let landing = loop {
+            let point = random_floor_point(map);
+            if !map.mobs.iter().any(|mob| mob.0 == point) {
+                break point;
+            }
+        };
+        position.x = landing.x as i32;
+        position.y = landing.y as i32;
+        transform.translation.x = position.x as f32 * TILESIZE as f32;
+        transform.translation.y = position.y as f32 * TILESIZE as f32;
+        messages.send(GameMessage::new(
+            format!("You fall into the chasm and land on depth {}.", map.depth),
+            MessageCategory::Danger,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::turns::ACTION_COST;
+
+    // Takes the stairs, then hands the turn straight back.
+    fn take_stairs(app: &mut App, action: PlayerAction) {
+        app.world
+            .resource_mut::<Events<PlayerAction>>()
+            .send(action);
+        app.update();
+        app.world
+            .resource_mut::<State<GameState>>()
+            .set(GameState::AwaitingInput)
+            .unwrap();
+        app.update();
+    }
+
+    fn revealed(app: &App) -> HashSet<Point> {
+        app.world.resource::<Level>().revealed_tiles.clone()
+    }
+
+    fn depth(app: &App) -> u32 {
+        app.world.resource::<Level>().depth
+    }
+
+    #[test]
+    fn explored_tiles_are_kept_across_the_stairs() {
+        let mut level = Level::from_ascii(&["#####", "#...#", "#####"]);
+        level
+            .tiles
+            .insert(Point::new(1, 1), TileTypeMap(TileType::DOWNSTAIRS));
+        level.revealed_tiles = level.tiles.keys().copied().collect();
+        let mut app = App::new();
+        app.add_state(GameState::AwaitingInput)
+            .add_event::<GameMessage>()
+            .add_event::<PlayerAction>()
+            .add_event::<LevelChanged>()
+            .init_resource::<TurnScheduler>()
+            .insert_resource(GlyphAssets {
+                atlas: Handle::default(),
+            })
+            .insert_resource(level)
+            .insert_resource(Dungeon::new(5))
+            .insert_resource(Bestiary::builtin())
+            .insert_resource(Vaults::builtin())
+            .add_system_set(SystemSet::on_update(GameState::AwaitingInput).with_system(use_stairs));
+        app.world.spawn((
+            Player,
+            Position { x: 1, y: 1 },
+            Transform::default(),
+            Energy(ACTION_COST),
+        ));
+        app.update();
+        let first = revealed(&app);
+
+        take_stairs(&mut app, PlayerAction::Descend);
+        assert_eq!(depth(&app), 2);
+        assert!(revealed(&app).is_empty());
+        // Look around a little
+        let arrival = app.world.resource::<Level>().find_tile(TileType::UPSTAIRS);
+        app.world
+            .resource_mut::<Level>()
+            .revealed_tiles
+            .insert(arrival.unwrap());
+        let second = revealed(&app);
+
+        take_stairs(&mut app, PlayerAction::Ascend);
+        assert_eq!(depth(&app), 1);
+        assert_eq!(revealed(&app), first);
+
+        take_stairs(&mut app, PlayerAction::Descend);
+        assert_eq!(depth(&app), 2);
+        assert_eq!(revealed(&app), second);
+    }
+}
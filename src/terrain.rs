@@ -0,0 +1,111 @@
+// What happens to mobs that step onto water, lava and the like.
+// Lava sets them on fire and deep water puts the fire out. How slow water and rubble are lives
+// with the level (Level::move_cost), and falling into a chasm is done by the stairs.
+use bevy::prelude::*;
+
+use crate::{
+    components::{Mob, MobType, Position},
+    health::{Health, LastHitBy},
+    map::Level,
+    messages::{GameMessage, MessageCategory},
+    tiles::TileType,
+    turns::{GameState, TurnLabel},
+};
+
+/// Damage taken from stepping into lava.
+const LAVA_DAMAGE: i32 = 4;
+/// Turns a mob keeps burning for after stepping into lava.
+const BURN_TURNS: i32 = 3;
+
+/// On fire. Takes 1 damage every turn, for the number of turns left.
+#[derive(Component)]
+pub struct Burning(pub i32);
+
+// ============================
+// ========== PLUGIN ==========
+pub struct TerrainPlugin;
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(GameState::MonsterTurn).with_system(burn))
+            .add_system_set(
+                SystemSet::on_update(GameState::MonsterTurn)
+                    .with_system(enter_terrain.before(TurnLabel::Schedule)),
+            );
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn enter_terrain(
+    mut commands: Commands,
+    map: Res<Level>,
+    mut mob_query: Query<
+        (
+            Entity,
+            &Position,
+            &Mob,
+            &mut Health,
+            Option<&mut LastHitBy>,
+            Option<&Burning>,
+        ),
+        Changed<Position>,
+    >,
+    mut messages: EventWriter<GameMessage>,
+) {
+    for (entity, position, mob, mut health, last_hit_by, burning) in mob_query.iter_mut() {
+        let is_player = mob.0 == MobType::PLAYER;
+        match map.tile_at(position.x, position.y) {
+            Some(TileType::LAVA) => {
+                health.0 -= LAVA_DAMAGE;
+                commands.entity(entity).insert(Burning(BURN_TURNS));
+                // No monster is to blame for this one
+                if let Some(mut last_hit_by) = last_hit_by {
+                    last_hit_by.0 = None;
+                }
+                if is_player {
+                    messages.send(GameMessage::new(
+                        "The lava sets you on fire!",
+                        MessageCategory::Danger,
+                    ));
+                }
+            }
+            Some(TileType::WATER) if burning.is_some() => {
+                commands.entity(entity).remove::<Burning>();
+                if is_player {
+                    messages.send(GameMessage::new(
+                        "The water puts out the flames.",
+                        MessageCategory::Info,
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Runs once for every turn the player takes.
+fn burn(
+    mut commands: Commands,
+    mut burning_query: Query<(
+        Entity,
+        &mut Burning,
+        &mut Health,
+        &Mob,
+        Option<&mut LastHitBy>,
+    )>,
+    mut messages: EventWriter<GameMessage>,
+) {
+    for (entity, mut burning, mut health, mob, last_hit_by) in burning_query.iter_mut() {
+        health.0 -= 1;
+        burning.0 -= 1;
+        if let Some(mut last_hit_by) = last_hit_by {
+            last_hit_by.0 = None;
+        }
+        // Kept on the dead, it is what killed them
+        if burning.0 <= 0 && health.0 > 0 {
+            commands.entity(entity).remove::<Burning>();
+        }
+        if mob.0 == MobType::PLAYER {
+            messages.send(GameMessage::new("You burn.", MessageCategory::Danger));
+        }
+    }
+}
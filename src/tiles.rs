@@ -1,11 +1,110 @@
 use bevy::prelude::*;
 
-use crate::components::{Collision};
-pub const TILESIZE : usize = 12;
-#[derive(PartialEq, Eq, Copy, Clone)]
+pub const TILESIZE: usize = 12;
+#[allow(clippy::upper_case_acronyms)]
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum TileType {
     WALL,
     FLOOR,
+    DOWNSTAIRS,
+    UPSTAIRS,
+    OPENDOOR,
+    CLOSEDDOOR,
+    // Needs a key to open
+    LOCKEDDOOR,
+    // Deep water, slow to wade through but puts out fires
+    WATER,
+    // Burns whatever steps in
+    LAVA,
+    // Drops whatever steps in to the next level
+    CHASM,
+    // Slow to climb over
+    RUBBLE,
+}
+
+impl TileType {
+    /// Index of the tile's glyph in the font atlas.
+    pub fn glyph(&self) -> usize {
+        match self {
+            TileType::WALL => 35,
+            TileType::FLOOR => 250,
+            TileType::DOWNSTAIRS => 62,
+            TileType::UPSTAIRS => 60,
+            TileType::OPENDOOR => 39,
+            TileType::CLOSEDDOOR => 43,
+            // A door with a bar across it
+            TileType::LOCKEDDOOR => 216,
+            TileType::WATER | TileType::LAVA => 247,
+            TileType::CHASM => 176,
+            TileType::RUBBLE => 59,
+        }
+    }
+
+    /// The tile's own color, as it looks under white light.
+    pub fn base_color(&self) -> Color {
+        match self {
+            TileType::WALL => Color::rgb(0.85, 0.8, 0.7),
+            TileType::FLOOR => Color::rgb(0.6, 0.6, 0.6),
+            TileType::DOWNSTAIRS | TileType::UPSTAIRS => Color::WHITE,
+            TileType::OPENDOOR | TileType::CLOSEDDOOR => Color::rgb(0.75, 0.5, 0.25),
+            TileType::LOCKEDDOOR => Color::rgb(0.9, 0.75, 0.3),
+            TileType::WATER => Color::rgb(0.2, 0.4, 1.0),
+            TileType::LAVA => Color::rgb(1.0, 0.35, 0.0),
+            TileType::CHASM => Color::rgb(0.35, 0.3, 0.45),
+            TileType::RUBBLE => Color::rgb(0.6, 0.45, 0.3),
+        }
+    }
+
+    /// How the tile looks under a light of color `light`. The base color is dimmed by how
+    /// bright the light is and takes on half of its tint, so water stays blue by torchlight
+    /// and everything is black where there is no light.
+    pub fn color(&self, light: Color) -> Color {
+        let base = self.base_color();
+        let brightness = light.r().max(light.g()).max(light.b());
+        let blend = |own: f32, light: f32| own * (brightness + light) / 2.0;
+        Color::rgb(
+            blend(base.r(), light.r()),
+            blend(base.g(), light.g()),
+            blend(base.b(), light.b()),
+        )
+    }
+}
+
+/// The character a tile is written as in save files, a space where there is no tile.
+pub fn tile_glyph(tile: Option<&TileTypeMap>) -> char {
+    match tile.map(|tile| tile.0) {
+        Some(TileType::WALL) => '#',
+        Some(TileType::FLOOR) => '.',
+        Some(TileType::DOWNSTAIRS) => '>',
+        Some(TileType::UPSTAIRS) => '<',
+        Some(TileType::OPENDOOR) => '\'',
+        Some(TileType::CLOSEDDOOR) => '+',
+        Some(TileType::LOCKEDDOOR) => '=',
+        Some(TileType::WATER) => '~',
+        Some(TileType::LAVA) => '^',
+        Some(TileType::CHASM) => '_',
+        Some(TileType::RUBBLE) => ';',
+        None => ' ',
+    }
+}
+
+/// The tile a glyph stands for, the other way around from `tile_glyph`.
+/// Saves and vault files use the same glyphs.
+pub fn glyph_tile(glyph: char) -> Option<TileType> {
+    match glyph {
+        '#' => Some(TileType::WALL),
+        '.' => Some(TileType::FLOOR),
+        '>' => Some(TileType::DOWNSTAIRS),
+        '<' => Some(TileType::UPSTAIRS),
+        '\'' => Some(TileType::OPENDOOR),
+        '+' => Some(TileType::CLOSEDDOOR),
+        '=' => Some(TileType::LOCKEDDOOR),
+        '~' => Some(TileType::WATER),
+        '^' => Some(TileType::LAVA),
+        '_' => Some(TileType::CHASM),
+        ';' => Some(TileType::RUBBLE),
+        _ => None,
+    }
 }
 
 pub struct TileTypeMap(pub TileType);
@@ -13,21 +112,26 @@ pub struct TileTypeMap(pub TileType);
 #[derive(Component)]
 pub struct Tile(pub TileType);
 
+/// The sprite of one map cell. It stays for as long as the level is shown, and changes glyph
+/// and color in place.
 #[derive(Bundle)]
-pub struct WallBundle {
+pub struct TileBundle {
     #[bundle]
     sprite_sheet_bundle: SpriteSheetBundle,
-    collision: Collision,
     tile: Tile,
 }
 
-impl WallBundle {
-    pub fn new(location: (usize, usize), texture_atlas_handle: Handle<TextureAtlas>, color : Color) -> WallBundle {
-        WallBundle {
+impl TileBundle {
+    /// Hidden until the player sees it.
+    pub fn new(
+        location: (usize, usize),
+        texture_atlas_handle: Handle<TextureAtlas>,
+        tile_type: TileType,
+    ) -> TileBundle {
+        TileBundle {
             sprite_sheet_bundle: SpriteSheetBundle {
                 sprite: TextureAtlasSprite {
-                    index: 35,
-                    color : color,
+                    index: tile_type.glyph(),
                     ..default()
                 },
                 texture_atlas: texture_atlas_handle,
@@ -37,55 +141,62 @@ impl WallBundle {
                         y: location.1 as f32 * TILESIZE as f32,
                         z: 0.0,
                     },
-                    scale : Vec3 {
-                        x : TILESIZE as f32 / 8.0,
-                        y : TILESIZE as f32 / 8.0,
-                        z : TILESIZE as f32 / 8.0,
+                    scale: Vec3 {
+                        x: TILESIZE as f32 / 8.0,
+                        y: TILESIZE as f32 / 8.0,
+                        z: TILESIZE as f32 / 8.0,
                     },
                     ..default()
                 },
+                visibility: Visibility { is_visible: false },
                 ..default()
             },
-            collision: Collision,
-            tile: Tile(TileType::WALL),
+            tile: Tile(tile_type),
         }
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-#[derive(Bundle)]
-pub struct FloorBundle {
-    #[bundle]
-    sprite_sheet_bundle: SpriteSheetBundle,
-    tile: Tile,
-}
+    const ALL: [TileType; 11] = [
+        TileType::WALL,
+        TileType::FLOOR,
+        TileType::DOWNSTAIRS,
+        TileType::UPSTAIRS,
+        TileType::OPENDOOR,
+        TileType::CLOSEDDOOR,
+        TileType::LOCKEDDOOR,
+        TileType::WATER,
+        TileType::LAVA,
+        TileType::CHASM,
+        TileType::RUBBLE,
+    ];
 
-impl FloorBundle {
-    pub fn new(location: (usize, usize), texture_atlas_handle: Handle<TextureAtlas>, color : Color) -> FloorBundle {
-        FloorBundle {
-            sprite_sheet_bundle: SpriteSheetBundle {
-                sprite: TextureAtlasSprite {
-                    index: 250,
-                    color : color,
-                    ..default()
-                },
-                texture_atlas: texture_atlas_handle,
-                transform: Transform {
-                    translation: Vec3 {
-                        x: location.0 as f32 * TILESIZE as f32,
-                        y: location.1 as f32 * TILESIZE as f32,
-                        z: 0.0,
-                    },
-                    scale : Vec3 {
-                        x : TILESIZE as f32 / 8.0,
-                        y : TILESIZE as f32 / 8.0,
-                        z : TILESIZE as f32 / 8.0,
-                    },
-                    ..default()
-                },
-                ..default()
-            },
-            tile: Tile(TileType::WALL)
+    fn brightness(color: Color) -> f32 {
+        color.r().max(color.g()).max(color.b())
+    }
+
+    #[test]
+    fn every_tile_blends_its_color_with_the_light() {
+        for tile in ALL {
+            assert_eq!(tile.color(Color::WHITE), tile.base_color(), "{:?}", tile);
+            assert_eq!(
+                tile.color(Color::BLACK),
+                Color::rgb(0.0, 0.0, 0.0),
+                "{:?}",
+                tile
+            );
+            let dim = tile.color(Color::rgb(0.2, 0.2, 0.2));
+            assert!(
+                brightness(dim) < brightness(tile.base_color()),
+                "{:?}",
+                tile
+            );
         }
+        // Water stays blue by torchlight
+        let water = TileType::WATER.color(Color::rgb(1.0, 0.6, 0.25));
+        assert!(water.b() > water.r());
     }
 }
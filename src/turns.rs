@@ -0,0 +1,132 @@
+use std::{cmp::Reverse, collections::VecDeque};
+
+use bevy::prelude::*;
+
+use crate::{
+    components::{Mob, Position},
+    player::Player,
+};
+
+/// Energy a mob has to spend to take one action.
+pub const ACTION_COST: i32 = 100;
+/// Energy gained per tick by a mob of normal speed.
+pub const NORMAL_SPEED: i32 = 10;
+
+/// Accumulated energy. A mob may act once it has at least ACTION_COST.
+#[derive(Component)]
+pub struct Energy(pub i32);
+
+/// Energy gained by a mob every tick of game time.
+#[derive(Component)]
+pub struct Speed(pub i32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GameState {
+    // Waiting for the player to issue a command
+    AwaitingInput,
+    // Handing out turns to everything that is not the player
+    MonsterTurn,
+    // The player has died
+    GameOver,
+    // Reading back through old messages
+    MessageHistory,
+    // Looking through or dropping carried items
+    Inventory,
+    // Reading which keys do what
+    Help,
+}
+
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TurnLabel {
+    Schedule,
+    MonsterAct,
+}
+
+/// Grants energy to mobs and hands out turns in order.
+#[derive(Resource, Default)]
+pub struct TurnScheduler {
+    /// Number of turns the player has been given.
+    pub turn: u32,
+    /// Ticks of game time that have elapsed.
+    pub ticks: u64,
+    /// Mobs that have enough energy to act, in the order they will act.
+    pub ready: VecDeque<Entity>,
+}
+
+impl TurnScheduler {
+    /// Returns the next monster in line, or None if it is the players turn
+    /// (or nobody is ready).
+    pub fn next_monster(&mut self, player: Entity) -> Option<Entity> {
+        match self.ready.front() {
+            Some(&entity) if entity != player => self.ready.pop_front(),
+            _ => None,
+        }
+    }
+}
+
+// ============================
+// ========== PLUGIN ==========
+pub struct TurnPlugin;
+impl Plugin for TurnPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state(GameState::AwaitingInput)
+            .init_resource::<TurnScheduler>()
+            .add_system_set(
+                SystemSet::on_update(GameState::MonsterTurn)
+                    .with_system(schedule_turns.label(TurnLabel::Schedule))
+                    .with_system(begin_player_turn.after(TurnLabel::MonsterAct)),
+            );
+    }
+}
+
+/// Spend the energy for one action and hand the turn over to the monsters.
+pub fn end_player_turn(energy: &mut Energy, state: &mut State<GameState>) {
+    energy.0 -= ACTION_COST;
+    state.set(GameState::MonsterTurn).unwrap();
+}
+
+#[allow(clippy::type_complexity)]
+fn schedule_turns(
+    mut scheduler: ResMut<TurnScheduler>,
+    mut mob_query: Query<(Entity, &mut Energy, &Speed, &Position, Option<&Player>), With<Mob>>,
+) {
+    if !scheduler.ready.is_empty() || mob_query.is_empty() {
+        return;
+    }
+    // Advance time until at least one mob can act.
+    while !mob_query.iter().any(|mob| mob.1 .0 >= ACTION_COST) {
+        for (_, mut energy, speed, _, _) in mob_query.iter_mut() {
+            energy.0 += speed.0;
+        }
+        scheduler.ticks += 1;
+    }
+    // Most energy acts first. Ties go to the player, then by where the mobs stand, so the
+    // order does not depend on entity ids and comes out the same after loading a save.
+    let mut ready: Vec<_> = mob_query
+        .iter()
+        .filter(|mob| mob.1 .0 >= ACTION_COST)
+        .map(|mob| {
+            (
+                mob.0,
+                (Reverse(mob.1 .0), mob.4.is_none(), mob.3.y, mob.3.x),
+            )
+        })
+        .collect();
+    ready.sort_by_key(|mob| mob.1);
+    scheduler.ready = ready.into_iter().map(|mob| mob.0).collect();
+}
+
+fn begin_player_turn(
+    mut scheduler: ResMut<TurnScheduler>,
+    mut state: ResMut<State<GameState>>,
+    player_query: Query<Entity, With<Player>>,
+) {
+    let Ok(player) = player_query.get_single() else {
+        return;
+    };
+    if scheduler.ready.front() == Some(&player) {
+        scheduler.ready.pop_front();
+        scheduler.turn += 1;
+        state.set(GameState::AwaitingInput).unwrap();
+    }
+}
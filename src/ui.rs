@@ -0,0 +1,180 @@
+// Text drawn with the CP437 glyph atlas.
+// Text entities are children of the camera, so their transforms are in screen space
+// with (0, 0) in the middle of the window.
+use bevy::{ecs::system::EntityCommands, prelude::*};
+
+use crate::{
+    components::MainCamera,
+    health::Health,
+    map::{Dungeon, Level},
+    player::Player,
+    rendering::{STATUS_HEIGHT, WINDOWSIZE},
+    resources::GlyphAssets,
+};
+
+/// Width and height of a glyph in the atlas.
+pub const GLYPH_SIZE: f32 = 8.0;
+/// CP437 full block, used to draw solid rectangles.
+pub const SOLID_GLYPH: usize = 219;
+
+/// A line of text. Spawned as one glyph sprite per character whenever it changes.
+#[derive(Component)]
+pub struct GlyphText {
+    pub text: String,
+    pub color: Color,
+}
+
+impl GlyphText {
+    pub fn new(text: impl Into<String>, color: Color) -> Self {
+        GlyphText {
+            text: text.into(),
+            color,
+        }
+    }
+}
+
+/// Shows the players health, how deep they are and the dungeon seed.
+#[derive(Component)]
+pub struct StatusText;
+
+// ============================
+// ========== PLUGIN ==========
+pub struct UiPlugin;
+impl Plugin for UiPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(setup)
+            .add_system(update_status)
+            .add_system_to_stage(CoreStage::PostUpdate, draw_glyph_text);
+    }
+}
+
+fn setup(
+    mut commands: Commands,
+    atlas: Res<GlyphAssets>,
+    camera_query: Query<Entity, With<MainCamera>>,
+) {
+    let camera = camera_query.single();
+    commands.entity(camera).with_children(|parent| {
+        // Keeps the map from showing behind the status lines
+        parent
+            .spawn(SpatialBundle::from_transform(Transform::from_xyz(
+                0.0, 0.0, -1.5,
+            )))
+            .with_children(|parent| {
+                spawn_panel(
+                    parent,
+                    &atlas,
+                    Color::BLACK,
+                    Vec2::new(0.0, (WINDOWSIZE.1 - STATUS_HEIGHT) / 2.0),
+                    Vec2::new(WINDOWSIZE.0, STATUS_HEIGHT),
+                );
+            });
+        parent.spawn((
+            GlyphText::new("", Color::WHITE),
+            StatusText,
+            SpatialBundle::from_transform(Transform::from_xyz(
+                -WINDOWSIZE.0 / 2.0 + GLYPH_SIZE,
+                WINDOWSIZE.1 / 2.0 - GLYPH_SIZE,
+                -1.0,
+            )),
+        ));
+    });
+}
+
+/// Spawns a text line at a screen position, on top of the map.
+pub fn spawn_text<'w, 's, 'a>(
+    parent: &'a mut ChildBuilder<'w, 's, '_>,
+    text: impl Into<String>,
+    color: Color,
+    position: Vec2,
+) -> EntityCommands<'w, 's, 'a> {
+    parent.spawn((
+        GlyphText::new(text, color),
+        SpatialBundle::from_transform(Transform::from_xyz(position.x, position.y, 0.1)),
+    ))
+}
+
+/// Spawns a solid rectangle centered on a screen position.
+pub fn spawn_panel(
+    parent: &mut ChildBuilder,
+    atlas: &GlyphAssets,
+    color: Color,
+    position: Vec2,
+    size: Vec2,
+) -> Entity {
+    parent
+        .spawn(SpriteSheetBundle {
+            texture_atlas: atlas.atlas.clone(),
+            sprite: TextureAtlasSprite {
+                index: SOLID_GLYPH,
+                color,
+                ..default()
+            },
+            transform: Transform {
+                translation: position.extend(0.0),
+                scale: (size / GLYPH_SIZE).extend(1.0),
+                ..default()
+            },
+            ..default()
+        })
+        .id()
+}
+
+fn update_status(
+    mut status_query: Query<&mut GlyphText, With<StatusText>>,
+    player_query: Query<&Health, With<Player>>,
+    map: Res<Level>,
+    dungeon: Res<Dungeon>,
+) {
+    for health in player_query.iter() {
+        let text = format!(
+            "HP {}  Depth {}  Seed {}",
+            health.0.max(0),
+            map.depth,
+            dungeon.seed
+        );
+        for mut status in status_query.iter_mut() {
+            // Only touch the text when it changes, so it is not redrawn every frame
+            if status.text != text {
+                status.text = text.clone();
+            }
+        }
+    }
+}
+
+fn draw_glyph_text(
+    mut commands: Commands,
+    atlas: Res<GlyphAssets>,
+    text_query: Query<(Entity, &GlyphText), Changed<GlyphText>>,
+) {
+    for (entity, text) in text_query.iter() {
+        commands.entity(entity).despawn_descendants();
+        commands.entity(entity).with_children(|parent| {
+            for (i, character) in text.text.chars().enumerate() {
+                if character == ' ' {
+                    continue;
+                }
+                parent.spawn(SpriteSheetBundle {
+                    texture_atlas: atlas.atlas.clone(),
+                    sprite: TextureAtlasSprite {
+                        index: glyph_index(character),
+                        color: text.color,
+                        anchor: bevy::sprite::Anchor::TopLeft,
+                        ..default()
+                    },
+                    transform: Transform::from_xyz(i as f32 * GLYPH_SIZE, 0.0, 0.0),
+                    ..default()
+                });
+            }
+        });
+    }
+}
+
+// The atlas is laid out in CP437 order, which matches ASCII for printable characters.
+fn glyph_index(character: char) -> usize {
+    if character.is_ascii() {
+        character as usize
+    } else {
+        '?' as usize
+    }
+}
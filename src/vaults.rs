@@ -0,0 +1,536 @@
+// Hand made set pieces, stamped into levels after they are generated.
+// Every vault is a text file in assets/vaults with a few settings and an ASCII map, see
+// goblin_camp.txt for how they are written. Vaults are turned and mirrored at random, and
+// the monsters and items in them are put on the level like any others.
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
+
+use bevy::prelude::*;
+use map_gen_2d::Point;
+use rand::prelude::*;
+
+use crate::{
+    components::MobType,
+    enemies::Bestiary,
+    items::ItemType,
+    mapgen::{GeneratedMap, Rect},
+    resources::asset_path,
+    tiles::{glyph_tile, TileType, TileTypeMap},
+};
+
+/// Copies of assets/vaults built into the game, for when the folder is not around.
+const BUILTIN_VAULTS: [&str; 3] = [
+    include_str!("../assets/vaults/flooded_shrine.txt"),
+    include_str!("../assets/vaults/goblin_camp.txt"),
+    include_str!("../assets/vaults/orc_armory.txt"),
+];
+/// Most vaults on one level.
+const MAX_VAULTS: usize = 2;
+/// Spots tried for a vault besides the middle of every room. The one that fits best is used.
+const PLACEMENT_TRIES: u32 = 40;
+
+/// What a legend character stands for.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Legend {
+    Monster(String),
+    Item(ItemType),
+}
+
+#[derive(Clone, Debug)]
+pub struct Vault {
+    pub name: String,
+    /// Percent chance of showing up on a level it is allowed on.
+    pub chance: u32,
+    /// Shallowest level it shows up on.
+    pub min_depth: u32,
+    /// Deepest level it shows up on, no limit if None.
+    pub max_depth: Option<u32>,
+    pub legend: HashMap<char, Legend>,
+    /// The map, all rows the same length. A space leaves the level as it was.
+    pub rows: Vec<Vec<char>>,
+}
+
+impl Vault {
+    pub fn parse(text: &str) -> Result<Vault, String> {
+        let mut lines = text.lines();
+        let mut settings: HashMap<&str, &str> = HashMap::new();
+        let mut legend = HashMap::new();
+        for line in lines.by_ref() {
+            let line = line.trim();
+            if line.is_empty() {
+                break;
+            }
+            if line.starts_with("//") {
+                continue;
+            }
+            let (key, value) = line
+                .split_once(':')
+                .ok_or(format!("expected 'setting: value', got '{}'", line))?;
+            let value = value.trim();
+            if key != "legend" {
+                settings.insert(key, value);
+                continue;
+            }
+            let mut parts = value.splitn(3, ' ');
+            let (Some(glyph), Some(kind), Some(name)) = (parts.next(), parts.next(), parts.next())
+            else {
+                return Err(format!(
+                    "expected 'legend: <char> monster|item <name>', got '{}'",
+                    line
+                ));
+            };
+            let mut chars = glyph.chars();
+            let (Some(glyph), None) = (chars.next(), chars.next()) else {
+                return Err(format!("'{}' is not a single character", glyph));
+            };
+            if glyph_tile(glyph).is_some() || glyph == ' ' {
+                return Err(format!("'{}' is already a tile", glyph));
+            }
+            let meaning = match kind {
+                "monster" => Legend::Monster(name.to_string()),
+                "item" => Legend::Item(
+                    ItemType::from_name(name)
+                        .ok_or(format!("there is no item called '{}'", name))?,
+                ),
+                _ => return Err(format!("'{}' should be monster or item", kind)),
+            };
+            legend.insert(glyph, meaning);
+        }
+
+        let number = |key: &str| -> Result<Option<u32>, String> {
+            settings
+                .get(key)
+                .map(|value| {
+                    value
+                        .parse()
+                        .map_err(|_| format!("{} should be a number, got '{}'", key, value))
+                })
+                .transpose()
+        };
+        let name = settings
+            .get("name")
+            .ok_or("the vault has no name")?
+            .to_string();
+        let chance = number("chance")?.ok_or("the vault has no chance")?;
+        if chance > 100 {
+            return Err(format!("chance is in percent, {} is too much", chance));
+        }
+        if let Some(key) = settings
+            .keys()
+            .find(|key| !["name", "chance", "min_depth", "max_depth"].contains(key))
+        {
+            return Err(format!("unknown setting '{}'", key));
+        }
+
+        let mut rows: Vec<Vec<char>> = lines.map(|line| line.chars().collect()).collect();
+        while rows
+            .last()
+            .is_some_and(|row| row.iter().all(|glyph| *glyph == ' '))
+        {
+            rows.pop();
+        }
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        if width == 0 {
+            return Err(format!("'{}' has no map", name));
+        }
+        for (y, row) in rows.iter_mut().enumerate() {
+            row.resize(width, ' ');
+            for glyph in row.iter() {
+                let tile = glyph_tile(*glyph);
+                if matches!(tile, Some(TileType::DOWNSTAIRS | TileType::UPSTAIRS)) {
+                    return Err(format!("row {}: the level puts the stairs in", y + 1));
+                }
+                if tile.is_none() && *glyph != ' ' && !legend.contains_key(glyph) {
+                    return Err(format!("row {}: '{}' is not in the legend", y + 1, glyph));
+                }
+            }
+        }
+        Ok(Vault {
+            name,
+            chance,
+            min_depth: number("min_depth")?.unwrap_or(1),
+            max_depth: number("max_depth")?,
+            legend,
+            rows,
+        })
+    }
+
+    pub fn is_allowed_at(&self, depth: u32) -> bool {
+        depth >= self.min_depth && self.max_depth.is_none_or(|max| depth <= max)
+    }
+
+    /// Width and height, turned by `orientation`.
+    pub fn size(&self, orientation: u8) -> (usize, usize) {
+        let (width, height) = (self.rows[0].len(), self.rows.len());
+        if orientation % 2 == 1 {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// The character at (x, y) after turning the map a quarter clockwise `orientation` times,
+    /// and mirroring it left to right too if `orientation` is 4 or more.
+    pub fn glyph(&self, orientation: u8, x: usize, y: usize) -> char {
+        let (width, height) = (self.rows[0].len(), self.rows.len());
+        let x = if orientation >= 4 {
+            self.size(orientation).0 - 1 - x
+        } else {
+            x
+        };
+        let (from_x, from_y) = match orientation % 4 {
+            0 => (x, y),
+            1 => (y, height - 1 - x),
+            2 => (width - 1 - x, height - 1 - y),
+            _ => (width - 1 - y, x),
+        };
+        self.rows[from_y][from_x]
+    }
+}
+
+/// Every vault that can show up in the dungeon.
+#[derive(Resource, Clone, Debug, Default)]
+pub struct Vaults {
+    pub vaults: Vec<Vault>,
+}
+
+impl Vaults {
+    pub fn parse(texts: &[&str]) -> Result<Vaults, String> {
+        let mut vaults: Vec<Vault> = Vec::new();
+        for text in texts {
+            let vault = Vault::parse(text)?;
+            if vaults.iter().any(|other| other.name == vault.name) {
+                return Err(format!("there are two vaults named '{}'", vault.name));
+            }
+            vaults.push(vault);
+        }
+        Ok(Vaults { vaults })
+    }
+
+    /// Reads every .txt file in assets/vaults, or uses the built in copies if there is no
+    /// such folder or one of the files is broken.
+    pub fn load() -> Vaults {
+        Vaults::read().unwrap_or_else(|err| {
+            warn!(
+                "Could not read the vaults, using the built in ones: {}",
+                err
+            );
+            Vaults::builtin()
+        })
+    }
+
+    fn read() -> Result<Vaults, String> {
+        let dir = asset_path("vaults");
+        let Ok(entries) = fs::read_dir(&dir) else {
+            return Ok(Vaults::builtin());
+        };
+        // By file name, so the vaults are tried in the same order everywhere
+        let mut paths: Vec<_> = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+            .collect();
+        paths.sort();
+        let mut vaults: Vec<Vault> = Vec::new();
+        for path in paths {
+            let text = fs::read_to_string(&path).map_err(|err| err.to_string())?;
+            let vault =
+                Vault::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+            if vaults.iter().any(|other| other.name == vault.name) {
+                return Err(format!(
+                    "{}: there are two vaults named '{}'",
+                    path.display(),
+                    vault.name
+                ));
+            }
+            vaults.push(vault);
+        }
+        Ok(Vaults { vaults })
+    }
+
+    pub fn builtin() -> Vaults {
+        Vaults::parse(&BUILTIN_VAULTS).unwrap()
+    }
+
+    /// Makes sure every monster in the vaults is in the bestiary.
+    pub fn check(&self, bestiary: &Bestiary) -> Result<(), String> {
+        for vault in self.vaults.iter() {
+            for meaning in vault.legend.values() {
+                if let Legend::Monster(name) = meaning {
+                    if bestiary.get(name).is_none() {
+                        return Err(format!(
+                            "'{}' has a monster called '{}' that does not exist",
+                            vault.name, name
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Stamps the vaults that turn up on a level at `depth` into the map, in the rooms or open
+    /// areas they fit best. Returns the monsters and items in them.
+    #[allow(clippy::type_complexity)]
+    pub fn stamp(
+        &self,
+        map: &mut GeneratedMap,
+        size: (usize, usize),
+        depth: u32,
+        rng: &mut impl Rng,
+    ) -> (Vec<(Point, MobType)>, Vec<(Point, ItemType)>) {
+        let mut mobs = Vec::new();
+        let mut items = Vec::new();
+        for vault in self.vaults.iter() {
+            if map.vaults.len() == MAX_VAULTS {
+                break;
+            }
+            if !vault.is_allowed_at(depth) || !rng.gen_ratio(vault.chance, 100) {
+                continue;
+            }
+            let Some((orientation, area)) = place(vault, map, size, rng) else {
+                continue;
+            };
+            for y in 0..area.height {
+                for x in 0..area.width {
+                    let glyph = vault.glyph(orientation, x, y);
+                    let point = Point::new(area.x + x, area.y + y);
+                    let tile = match vault.legend.get(&glyph) {
+                        Some(Legend::Monster(name)) => {
+                            mobs.push((point, MobType::MONSTER(name.clone())));
+                            TileType::FLOOR
+                        }
+                        Some(Legend::Item(item)) => {
+                            items.push((point, *item));
+                            TileType::FLOOR
+                        }
+                        None => match glyph_tile(glyph) {
+                            Some(tile) => tile,
+                            None => continue,
+                        },
+                    };
+                    map.tiles.insert(point, TileTypeMap(tile));
+                }
+            }
+            // Close off any floor at the edge that opens onto nothing
+            for y in area.y - 1..=area.y + area.height {
+                for x in area.x - 1..=area.x + area.width {
+                    map.tiles
+                        .entry(Point::new(x, y))
+                        .or_insert(TileTypeMap(TileType::WALL));
+                }
+            }
+            info!("Stamped the {} into depth {}", vault.name, depth);
+            map.vaults.push(area);
+        }
+        (mobs, items)
+    }
+}
+
+// Picks how to turn the vault and where it goes, trying the middle of every room and then
+// spots anywhere on the map. None if it does not fit on the map.
+fn place(
+    vault: &Vault,
+    map: &GeneratedMap,
+    size: (usize, usize),
+    rng: &mut impl Rng,
+) -> Option<(u8, Rect)> {
+    let corridors: HashSet<Point> = map.corridors.iter().copied().collect();
+    let rooms: Vec<Option<Rect>> = map.rooms.iter().copied().map(Some).collect();
+    let anywhere = (0..PLACEMENT_TRIES).map(|_| None);
+    let mut best: Option<(i32, u8, Rect)> = None;
+    for room in rooms.into_iter().chain(anywhere) {
+        let orientation = rng.gen_range(0..8);
+        let (width, height) = vault.size(orientation);
+        // Keep off the edge of the map
+        if width + 2 > size.0 || height + 2 > size.1 {
+            continue;
+        }
+        let (x, y) = match room {
+            Some(room) => (
+                (room.x + room.width / 2)
+                    .saturating_sub(width / 2)
+                    .clamp(1, size.0 - 1 - width),
+                (room.y + room.height / 2)
+                    .saturating_sub(height / 2)
+                    .clamp(1, size.1 - 1 - height),
+            ),
+            None => (
+                rng.gen_range(1..=size.0 - 1 - width),
+                rng.gen_range(1..=size.1 - 1 - height),
+            ),
+        };
+        let area = Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        if map.vaults.iter().any(|other| other.is_near(&area, 1)) {
+            continue;
+        }
+        let fit = fit(vault, orientation, &area, map, &corridors);
+        if best.as_ref().is_none_or(|best| fit > best.0) {
+            best = Some((fit, orientation, area));
+        }
+    }
+    best.map(|(_, orientation, area)| (orientation, area))
+}
+
+// How well the vault sits at `area`. Every tile of it over open ground counts for it and every
+// tile dug out of rock against, so it lands in rooms and caves rather than solid rock. Ways in
+// that open onto the level count extra, covering corridors counts against.
+fn fit(
+    vault: &Vault,
+    orientation: u8,
+    area: &Rect,
+    map: &GeneratedMap,
+    corridors: &HashSet<Point>,
+) -> i32 {
+    let mut fit = 0;
+    for y in 0..area.height {
+        for x in 0..area.width {
+            let glyph = vault.glyph(orientation, x, y);
+            if glyph == ' ' {
+                continue;
+            }
+            let point = Point::new(area.x + x, area.y + y);
+            fit += if corridors.contains(&point) {
+                -2
+            } else if map.is_open(point) {
+                1
+            } else {
+                -1
+            };
+            if glyph_tile(glyph) == Some(TileType::WALL) {
+                continue;
+            }
+            // The tiles just outside of the vault next to this one
+            let outside = [
+                (x == 0).then(|| Point::new(point.x - 1, point.y)),
+                (y == 0).then(|| Point::new(point.x, point.y - 1)),
+                (x == area.width - 1).then(|| Point::new(point.x + 1, point.y)),
+                (y == area.height - 1).then(|| Point::new(point.x, point.y + 1)),
+            ];
+            for point in outside.into_iter().flatten() {
+                if map.is_open(point) {
+                    fit += 3;
+                }
+            }
+        }
+    }
+    fit
+}
+
+// ============================
+// ========== PLUGIN ==========
+pub struct VaultPlugin;
+impl Plugin for VaultPlugin {
+    fn build(&self, app: &mut App) {
+        // Tests may bring their own vaults
+        if !app.world.contains_resource::<Vaults>() {
+            app.insert_resource(Vaults::load());
+        }
+        let Some(bestiary) = app.world.get_resource::<Bestiary>() else {
+            return;
+        };
+        if let Err(err) = app.world.resource::<Vaults>().check(bestiary) {
+            warn!("Leaving out the vaults: {}", err);
+            app.insert_resource(Vaults::default());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_chacha::ChaCha12Rng;
+
+    use super::*;
+    use crate::mapgen::GeneratorKind;
+
+    const CORNER: &str = "name: corner\nchance: 100\nlegend: g monster goblin\n\n#.\n#g\n";
+
+    #[test]
+    fn vaults_turn_and_mirror() {
+        let vault = Vault::parse(CORNER).unwrap();
+        let rows = |orientation: u8| -> Vec<String> {
+            let (width, height) = vault.size(orientation);
+            (0..height)
+                .map(|y| (0..width).map(|x| vault.glyph(orientation, x, y)).collect())
+                .collect()
+        };
+        assert_eq!(rows(0), ["#.", "#g"]);
+        assert_eq!(rows(1), ["##", "g."]);
+        assert_eq!(rows(2), ["g#", ".#"]);
+        assert_eq!(rows(3), [".g", "##"]);
+        assert_eq!(rows(4), [".#", "g#"]);
+    }
+
+    #[test]
+    fn bad_vaults_are_refused() {
+        assert!(Vault::parse("name: x\nchance: 10\n\n#>#\n").is_err());
+        assert!(Vault::parse("name: x\nchance: 10\n\n#g#\n").is_err());
+        assert!(Vault::parse("name: x\nchance: 10\nlegend: # item potion\n\n#\n").is_err());
+        assert!(Vault::parse("name: x\nchance: 10\nlegend: ! item cake\n\n!\n").is_err());
+        assert!(Vault::parse("name: x\nchance: 10\n").is_err());
+        let vaults = Vaults::parse(&["name: x\nchance: 10\nlegend: t monster dragon\n\nt\n"]);
+        assert!(vaults.unwrap().check(&Bestiary::builtin()).is_err());
+    }
+
+    #[test]
+    fn builtin_vaults_are_valid() {
+        Vaults::builtin().check(&Bestiary::builtin()).unwrap();
+    }
+
+    #[test]
+    fn stamped_vaults_keep_their_monsters() {
+        let vaults = Vaults::parse(&[CORNER]).unwrap();
+        let mut map = GeneratorKind::Bsp.generator().generate((50, 50), 1);
+        let mut rng = ChaCha12Rng::seed_from_u64(1);
+        let (mobs, items) = vaults.stamp(&mut map, (50, 50), 1, &mut rng);
+        assert_eq!(mobs.len(), 1);
+        assert!(items.is_empty());
+        assert_eq!(map.vaults.len(), 1);
+        assert!(map.vaults[0].contains(mobs[0].0));
+        assert_eq!(map.tiles[&mobs[0].0].0, TileType::FLOOR);
+    }
+
+    #[test]
+    fn vaults_go_in_rooms_rather_than_rock() {
+        let vaults = Vaults::parse(&["name: hut\nchance: 100\n\n#.#\n#.#\n###\n"]).unwrap();
+        let room = Rect {
+            x: 20,
+            y: 12,
+            width: 8,
+            height: 6,
+        };
+        for seed in 0..10 {
+            let mut map = GeneratedMap {
+                tiles: HashMap::new(),
+                rooms: vec![room],
+                corridors: Vec::new(),
+                vaults: Vec::new(),
+            };
+            for y in 0..40 {
+                for x in 0..40 {
+                    let point = Point::new(x, y);
+                    let tile = if room.contains(point) {
+                        TileType::FLOOR
+                    } else {
+                        TileType::WALL
+                    };
+                    map.tiles.insert(point, TileTypeMap(tile));
+                }
+            }
+            let mut rng = ChaCha12Rng::seed_from_u64(seed);
+            vaults.stamp(&mut map, (40, 40), 1, &mut rng);
+            let area = map.vaults[0];
+            assert!(room.contains(Point::new(area.x, area.y)));
+            assert!(room.contains(Point::new(
+                area.x + area.width - 1,
+                area.y + area.height - 1
+            )));
+        }
+    }
+}
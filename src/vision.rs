@@ -0,0 +1,72 @@
+// What every mob can see, and which tiles the player has explored.
+// Monsters need this to spot the player, so it runs with or without a window.
+// Monsters see in the dark, the player only sees tiles that are lit.
+use bevy::prelude::*;
+use map_gen_2d::Point;
+
+use crate::{
+    components::{Mob, MobType, Position, POV},
+    fov::field_of_view,
+    lighting::LightMap,
+    map::{Level, TileChanged},
+};
+
+/// Updates every POV. Anything drawing what mobs see runs after this.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VisionUpdate;
+
+// ============================
+// ========== PLUGIN ==========
+pub struct VisionPlugin;
+impl Plugin for VisionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_to_stage(CoreStage::PostUpdate, update_pov.label(VisionUpdate));
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_pov(
+    mut mob_query: Query<(&Position, ChangeTrackers<Position>, &mut POV, &Mob)>,
+    mut map: ResMut<Level>,
+    light_map: Res<LightMap>,
+    mut tile_changes: EventReader<TileChanged>,
+) {
+    // A door opening or closing changes what everyone can see
+    let tiles_changed = tile_changes.iter().count() > 0;
+    // Need to calculate what the mob can see.
+    for (position, moved, mut pov, mob) in mob_query.iter_mut() {
+        let is_player = mob.0 == MobType::PLAYER;
+        let light_changed = is_player && light_map.is_changed();
+        if !tiles_changed && !moved.is_changed() && !light_changed {
+            continue;
+        }
+        pov.visible_tiles.clear();
+        pov.newly_revealed_tiles.clear();
+        // Calculate visible tiles near position.
+        let visible = field_of_view((position.x, position.y), pov.range, |x, y| {
+            map.blocks_sight(x, y)
+        });
+        for (x, y) in visible {
+            if is_player && !light_map.is_lit(x, y) {
+                continue;
+            }
+            let distance = (position.x - x).abs() + (position.y - y).abs();
+            pov.visible_tiles.push((Position { x, y }, distance));
+
+            if is_player
+                && map.revealed_tiles.insert(Point {
+                    x: x as usize,
+                    y: y as usize,
+                })
+            {
+                pov.newly_revealed_tiles.push(Position { x, y })
+            }
+        }
+        pov.visible_tiles.retain(|p| {
+            p.0.x >= 0
+                && p.0.x < map.size.0.try_into().unwrap()
+                && p.0.y >= 0
+                && p.0.y < map.size.1.try_into().unwrap()
+        });
+    }
+}
@@ -0,0 +1,786 @@
+// Drives the game rules through a headless App on hand built levels, one scripted turn at
+// a time, and checks the state the turns leave behind.
+use std::collections::HashSet;
+
+use bevy::prelude::*;
+use map_gen_2d::Point;
+use p01_rogue::{
+    actions::PlayerAction,
+    ai::{AiState, Brain},
+    components::{Mob, MobType, Position, POV},
+    direction::Direction,
+    enemies::{Bestiary, MonsterBundle},
+    fov::field_of_view,
+    game_over::new_game,
+    headless::Simulation,
+    health::Health,
+    items::{Inventory, ItemType, INVENTORY_CAPACITY},
+    lighting::{LightKind, LightMap, LightSource},
+    map::{Dungeon, Level},
+    messages::{Message, MessageCategory, MessageLog},
+    player::{Player, PlayerBundle},
+    replay::Replay,
+    save::{read_save, save_game, write_save},
+    terrain::Burning,
+    tiles::{TileType, TileTypeMap},
+    turns::{Energy, GameState, TurnScheduler},
+};
+
+// Two rooms joined by a gap in the wall at (4, 3).
+const TWO_ROOMS: [&str; 7] = [
+    "#########",
+    "#...#...#",
+    "#...#...#",
+    "#.......#",
+    "#...#...#",
+    "#...#...#",
+    "#########",
+];
+
+// The same rooms with a door in the gap, closed or locked.
+fn rooms_with_door(door: char) -> Level {
+    let middle = TWO_ROOMS[3].replace("#.......#", &format!("#...{}...#", door));
+    let mut rows = TWO_ROOMS.to_vec();
+    rows[3] = &middle;
+    Level::from_ascii(&rows)
+}
+
+fn spawn_player(simulation: &mut Simulation, location: (i32, i32)) -> Entity {
+    let player = simulation
+        .app
+        .world
+        .spawn(PlayerBundle::new(location, Handle::default()))
+        .id();
+    // Let the new player look around before the first action
+    simulation.app.update();
+    player
+}
+
+fn tile(simulation: &Simulation, x: i32, y: i32) -> Option<TileType> {
+    simulation.app.world.resource::<Level>().tile_at(x, y)
+}
+
+fn position(simulation: &Simulation, entity: Entity) -> Position {
+    *simulation.app.world.get::<Position>(entity).unwrap()
+}
+
+fn turn(simulation: &Simulation) -> u32 {
+    simulation.app.world.resource::<TurnScheduler>().turn
+}
+
+fn revealed(simulation: &Simulation) -> HashSet<Position> {
+    simulation
+        .app
+        .world
+        .resource::<Level>()
+        .revealed_tiles
+        .iter()
+        .map(|point| Position {
+            x: point.x as i32,
+            y: point.y as i32,
+        })
+        .collect()
+}
+
+fn is_revealed(simulation: &Simulation, x: usize, y: usize) -> bool {
+    simulation
+        .app
+        .world
+        .resource::<Level>()
+        .revealed_tiles
+        .contains(&Point::new(x, y))
+}
+
+#[test]
+fn walls_block_movement_without_using_a_turn() {
+    let mut simulation = Simulation::from_level(Level::from_ascii(&TWO_ROOMS));
+    let player = spawn_player(&mut simulation, (1, 1));
+
+    simulation.act(PlayerAction::Move { dx: -1, dy: 0 });
+    simulation.act(PlayerAction::Move { dx: 0, dy: -1 });
+
+    assert_eq!(position(&simulation, player), Position { x: 1, y: 1 });
+    assert_eq!(turn(&simulation), 0);
+    assert_eq!(simulation.state(), GameState::AwaitingInput);
+}
+
+#[test]
+fn the_edge_of_the_map_blocks_movement() {
+    // Floor right up to the edge, with nothing beyond it
+    let mut simulation = Simulation::from_level(Level::from_ascii(&["...", "...", "..."]));
+    let player = spawn_player(&mut simulation, (1, 1));
+
+    simulation.act(PlayerAction::step(Direction::West));
+    assert_eq!(position(&simulation, player), Position { x: 0, y: 1 });
+    for direction in [Direction::West, Direction::NorthWest, Direction::SouthWest] {
+        simulation.act(PlayerAction::step(direction));
+    }
+    simulation.act(PlayerAction::step(Direction::North));
+    simulation.act(PlayerAction::step(Direction::North));
+
+    assert_eq!(position(&simulation, player), Position { x: 0, y: 2 });
+    assert_eq!(turn(&simulation), 2);
+    assert_eq!(simulation.state(), GameState::AwaitingInput);
+}
+
+#[test]
+fn moving_onto_floor_takes_a_turn() {
+    let mut simulation = Simulation::from_level(Level::from_ascii(&TWO_ROOMS));
+    let player = spawn_player(&mut simulation, (1, 1));
+
+    simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+    simulation.act(PlayerAction::Move { dx: 0, dy: 1 });
+
+    assert_eq!(position(&simulation, player), Position { x: 2, y: 2 });
+    assert_eq!(turn(&simulation), 2);
+    assert_eq!(simulation.state(), GameState::AwaitingInput);
+}
+
+#[test]
+fn diagonal_moves_do_not_cut_corners() {
+    let mut simulation = Simulation::from_level(Level::from_ascii(&TWO_ROOMS));
+    let player = spawn_player(&mut simulation, (1, 1));
+
+    simulation.act(PlayerAction::step(Direction::NorthEast));
+    assert_eq!(position(&simulation, player), Position { x: 2, y: 2 });
+    assert_eq!(turn(&simulation), 1);
+
+    // The gap at (4, 3) can only be walked into straight on
+    simulation.act(PlayerAction::step(Direction::East));
+    simulation.act(PlayerAction::step(Direction::NorthEast));
+    assert_eq!(position(&simulation, player), Position { x: 3, y: 2 });
+    assert_eq!(turn(&simulation), 2);
+}
+
+#[test]
+fn waiting_passes_a_turn() {
+    let mut simulation = Simulation::from_level(Level::from_ascii(&TWO_ROOMS));
+    let player = spawn_player(&mut simulation, (1, 1));
+
+    simulation.act(PlayerAction::Wait);
+    simulation.act(PlayerAction::Wait);
+
+    assert_eq!(position(&simulation, player), Position { x: 1, y: 1 });
+    assert_eq!(turn(&simulation), 2);
+}
+
+#[test]
+fn bumping_into_a_monster_attacks_it() {
+    let mut simulation = Simulation::from_level(Level::from_ascii(&TWO_ROOMS));
+    let template = Bestiary::builtin().get("goblin").unwrap().clone();
+    let goblin = simulation
+        .app
+        .world
+        .spawn(MonsterBundle::new(&template, (2, 1), Handle::default()))
+        .id();
+    let player = spawn_player(&mut simulation, (1, 1));
+    // Sturdy enough to outlast the goblin whatever the dice say
+    simulation.app.world.get_mut::<Health>(player).unwrap().0 = 1000;
+
+    let mut bumps = 0;
+    while simulation.app.world.get_entity(goblin).is_some() {
+        assert!(
+            bumps < 100,
+            "the goblin did not die after {} attacks",
+            bumps
+        );
+        let before = simulation.app.world.get::<Health>(goblin).unwrap().0;
+        simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+        bumps += 1;
+        // Attacking never moves the player, and the goblin can only lose health
+        assert_eq!(position(&simulation, player), Position { x: 1, y: 1 });
+        if let Some(health) = simulation.app.world.get::<Health>(goblin) {
+            assert!(health.0 <= before);
+        }
+    }
+
+    assert_eq!(turn(&simulation), bumps);
+    let report = simulation.report();
+    assert_eq!(report.kills, 1);
+    assert_eq!(report.monsters, 0);
+    assert!(report.alive);
+}
+
+#[test]
+fn exploring_reveals_tiles_once() {
+    let mut simulation = Simulation::from_level(Level::from_ascii(&TWO_ROOMS));
+    let player = spawn_player(&mut simulation, (1, 1));
+
+    // The near room is seen, the far one is behind the wall
+    assert!(is_revealed(&simulation, 1, 1));
+    assert!(is_revealed(&simulation, 3, 5));
+    assert!(is_revealed(&simulation, 4, 1));
+    assert!(!is_revealed(&simulation, 6, 1));
+    assert!(!is_revealed(&simulation, 7, 1));
+
+    // Walk to the gap, checking the newly revealed tiles are exactly what was added
+    for (dx, dy) in [(1, 0), (1, 0), (0, 1), (0, 1), (1, 0)] {
+        let before = revealed(&simulation);
+        simulation.act(PlayerAction::Move { dx, dy });
+        let added: HashSet<Position> = revealed(&simulation).difference(&before).copied().collect();
+        let pov = simulation.app.world.get::<POV>(player).unwrap();
+        let newly: HashSet<Position> = pov.newly_revealed_tiles.iter().copied().collect();
+        assert_eq!(newly, added);
+        assert_eq!(newly.len(), pov.newly_revealed_tiles.len());
+    }
+    assert_eq!(position(&simulation, player), Position { x: 4, y: 3 });
+    // The far room can be seen through the gap
+    assert!(is_revealed(&simulation, 7, 1));
+    let pov = simulation.app.world.get::<POV>(player).unwrap();
+    assert!(pov
+        .visible_tiles
+        .iter()
+        .any(|tile| tile.0 == Position { x: 7, y: 1 }));
+
+    // Stepping back shows nothing new
+    simulation.act(PlayerAction::Move { dx: -1, dy: 0 });
+    let pov = simulation.app.world.get::<POV>(player).unwrap();
+    assert!(pov.newly_revealed_tiles.is_empty());
+}
+
+// A room, and a closet below it where the player can be put out of sight.
+const ROOM_AND_CLOSET: [&str; 7] = [
+    "##########",
+    "#........#",
+    "#........#",
+    "#........#",
+    "##########",
+    "#..#######",
+    "##########",
+];
+const CLOSET: (i32, i32) = (1, 5);
+
+fn spawn_monster(simulation: &mut Simulation, name: &str, location: (i32, i32)) -> Entity {
+    let template = Bestiary::builtin().get(name).unwrap().clone();
+    simulation
+        .app
+        .world
+        .spawn(MonsterBundle::new(&template, location, Handle::default()))
+        .id()
+}
+
+fn brain(simulation: &Simulation, entity: Entity) -> Brain {
+    *simulation.app.world.get::<Brain>(entity).unwrap()
+}
+
+fn health(simulation: &Simulation, entity: Entity) -> i32 {
+    simulation.app.world.get::<Health>(entity).unwrap().0
+}
+
+// Puts the player somewhere else without using a turn.
+fn teleport(simulation: &mut Simulation, player: Entity, location: (i32, i32)) {
+    *simulation.app.world.get_mut::<Position>(player).unwrap() = Position {
+        x: location.0,
+        y: location.1,
+    };
+    simulation.app.update();
+}
+
+fn distance(a: Position, b: Position) -> i32 {
+    (a.x - b.x).abs().max((a.y - b.y).abs())
+}
+
+#[test]
+fn monsters_hunt_then_search_where_the_player_was_seen() {
+    let mut simulation = Simulation::from_level(Level::from_ascii(&ROOM_AND_CLOSET));
+    let goblin = spawn_monster(&mut simulation, "goblin", (8, 2));
+    let player = spawn_player(&mut simulation, CLOSET);
+    simulation.app.world.get_mut::<Health>(player).unwrap().0 = 1000;
+
+    simulation.act(PlayerAction::Wait);
+    assert_eq!(brain(&simulation, goblin).state, AiState::Idle);
+
+    let seen_at = Position { x: 1, y: 2 };
+    teleport(&mut simulation, player, (seen_at.x, seen_at.y));
+    simulation.act(PlayerAction::Wait);
+    assert_eq!(brain(&simulation, goblin).state, AiState::Hunting);
+    assert_eq!(brain(&simulation, goblin).last_seen, Some(seen_at));
+
+    teleport(&mut simulation, player, CLOSET);
+    simulation.act(PlayerAction::Wait);
+    assert_eq!(brain(&simulation, goblin).state, AiState::Searching);
+    assert_eq!(brain(&simulation, goblin).last_seen, Some(seen_at));
+
+    // It gives up once it gets to where the player was
+    let mut closest = distance(position(&simulation, goblin), seen_at);
+    for _ in 0..20 {
+        if brain(&simulation, goblin).state != AiState::Searching {
+            break;
+        }
+        simulation.act(PlayerAction::Wait);
+        closest = closest.min(distance(position(&simulation, goblin), seen_at));
+    }
+    assert_eq!(brain(&simulation, goblin).state, AiState::Idle);
+    assert_eq!(brain(&simulation, goblin).last_seen, None);
+    assert!(closest <= 1);
+}
+
+#[test]
+fn hurt_goblins_flee_until_out_of_sight() {
+    let mut simulation = Simulation::from_level(Level::from_ascii(&ROOM_AND_CLOSET));
+    let goblin = spawn_monster(&mut simulation, "goblin", (5, 2));
+    let player = spawn_player(&mut simulation, (1, 2));
+    simulation.app.world.get_mut::<Health>(goblin).unwrap().0 = 2;
+
+    let start = distance(position(&simulation, goblin), position(&simulation, player));
+    simulation.act(PlayerAction::Wait);
+    assert_eq!(brain(&simulation, goblin).state, AiState::Fleeing);
+    let fled = distance(position(&simulation, goblin), position(&simulation, player));
+    assert!(fled > start);
+
+    teleport(&mut simulation, player, CLOSET);
+    simulation.act(PlayerAction::Wait);
+    assert_eq!(brain(&simulation, goblin).state, AiState::Idle);
+    assert_eq!(brain(&simulation, goblin).last_seen, None);
+}
+
+#[test]
+fn orcs_never_retreat() {
+    let mut simulation = Simulation::from_level(Level::from_ascii(&ROOM_AND_CLOSET));
+    let orc = spawn_monster(&mut simulation, "orc", (6, 2));
+    let player = spawn_player(&mut simulation, (1, 2));
+    simulation.app.world.get_mut::<Health>(orc).unwrap().0 = 1;
+    simulation.app.world.get_mut::<Health>(player).unwrap().0 = 1000;
+
+    let mut last = distance(position(&simulation, orc), position(&simulation, player));
+    for _ in 0..8 {
+        simulation.act(PlayerAction::Wait);
+        assert_ne!(brain(&simulation, orc).state, AiState::Fleeing);
+        let now = distance(position(&simulation, orc), position(&simulation, player));
+        assert!(now <= last);
+        last = now;
+    }
+    assert_eq!(brain(&simulation, orc).state, AiState::Hunting);
+    assert_eq!(last, 1);
+    assert!(health(&simulation, player) < 1000);
+}
+
+#[test]
+fn restarting_builds_a_new_dungeon() {
+    let mut simulation = Simulation::new(3);
+    let old_tiles = simulation.app.world.resource::<Level>().tiles.len();
+    let old_walls: HashSet<Point> = walls(&simulation);
+    let player = simulation
+        .app
+        .world
+        .query_filtered::<Entity, With<Player>>()
+        .single(&simulation.app.world);
+    simulation.app.world.get_mut::<Health>(player).unwrap().0 = 0;
+    simulation
+        .app
+        .world
+        .resource_mut::<MessageLog>()
+        .messages
+        .push(Message {
+            text: "You die...".to_string(),
+            category: MessageCategory::Danger,
+            turn: 1,
+        });
+    simulation.app.update();
+    assert!(simulation.is_over());
+
+    new_game(&mut simulation.app.world);
+    simulation.app.update();
+
+    assert_eq!(simulation.state(), GameState::AwaitingInput);
+    assert_ne!(simulation.app.world.resource::<Dungeon>().seed, 3);
+    assert!(
+        old_tiles != simulation.app.world.resource::<Level>().tiles.len()
+            || old_walls != walls(&simulation)
+    );
+    // Only what was seen on the new level is in the log
+    let log = simulation.app.world.resource::<MessageLog>();
+    assert!(log.messages.iter().all(|message| message.turn == 0));
+    let report = simulation.report();
+    assert!(report.alive);
+    assert_eq!((report.turn, report.depth, report.kills), (0, 1, 0));
+    let level = simulation.app.world.resource::<Level>();
+    let monsters = level
+        .mobs
+        .iter()
+        .filter(|mob| mob.1 != MobType::PLAYER)
+        .count();
+    assert_eq!(report.monsters, monsters);
+    // The old player is gone
+    let players = simulation
+        .app
+        .world
+        .query_filtered::<Entity, With<Player>>()
+        .iter(&simulation.app.world)
+        .count();
+    assert_eq!(players, 1);
+}
+
+#[test]
+fn restarting_a_replay_hands_the_new_game_to_the_player() {
+    let mut simulation = Simulation::new(3);
+    let mut replay = Replay::new(vec![PlayerAction::Wait; 10]);
+    // Would send the next action on the coming frame
+    replay.paused = true;
+    replay.step = true;
+    simulation.app.world.insert_resource(replay);
+    let player = simulation
+        .app
+        .world
+        .query_filtered::<Entity, With<Player>>()
+        .single(&simulation.app.world);
+    simulation.app.world.get_mut::<Health>(player).unwrap().0 = 0;
+    simulation.app.update();
+    assert!(simulation.is_over());
+
+    new_game(&mut simulation.app.world);
+    for _ in 0..3 {
+        simulation.app.update();
+    }
+
+    assert!(!simulation.app.world.contains_resource::<Replay>());
+    assert_eq!(turn(&simulation), 0);
+    simulation.act(PlayerAction::Wait);
+    assert_eq!(turn(&simulation), 1);
+}
+
+fn walls(simulation: &Simulation) -> HashSet<Point> {
+    let map = simulation.app.world.resource::<Level>();
+    map.tiles
+        .iter()
+        .filter(|tile| tile.1 .0 == TileType::WALL)
+        .map(|tile| *tile.0)
+        .collect()
+}
+
+#[test]
+fn a_full_pack_refuses_more_items() {
+    let row = ".".repeat(INVENTORY_CAPACITY + 3);
+    let mut level = Level::from_ascii(&[&row]);
+    for x in 0..=INVENTORY_CAPACITY {
+        level.items.push((Point::new(x, 0), ItemType::POTION));
+    }
+    let mut simulation = Simulation::from_level(level);
+    let player = spawn_player(&mut simulation, (0, 0));
+    let carried = |simulation: &Simulation| {
+        simulation
+            .app
+            .world
+            .get::<Inventory>(player)
+            .unwrap()
+            .items
+            .len()
+    };
+
+    for _ in 0..INVENTORY_CAPACITY {
+        simulation.act(PlayerAction::PickUp);
+        simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+    }
+    assert_eq!(carried(&simulation), INVENTORY_CAPACITY);
+    let before = turn(&simulation);
+    simulation.act(PlayerAction::PickUp);
+    assert_eq!(carried(&simulation), INVENTORY_CAPACITY);
+    assert_eq!(turn(&simulation), before);
+
+    // Making room lets it be picked up
+    simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+    simulation.act(PlayerAction::Drop(0));
+    simulation.act(PlayerAction::Move { dx: -1, dy: 0 });
+    simulation.act(PlayerAction::PickUp);
+    assert_eq!(carried(&simulation), INVENTORY_CAPACITY);
+    assert_eq!(turn(&simulation), before + 4);
+}
+
+#[test]
+fn explored_tiles_are_kept_across_the_stairs() {
+    let mut level = Level::from_ascii(&TWO_ROOMS);
+    level
+        .tiles
+        .insert(Point::new(1, 1), TileTypeMap(TileType::DOWNSTAIRS));
+    let mut simulation = Simulation::from_level(level);
+    spawn_player(&mut simulation, (1, 1));
+    simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+    simulation.act(PlayerAction::Move { dx: -1, dy: 0 });
+    let first = revealed(&simulation);
+
+    simulation.act(PlayerAction::Descend);
+    assert_eq!(simulation.app.world.resource::<Level>().depth, 2);
+    let second = revealed(&simulation);
+    assert!(!second.is_empty());
+
+    simulation.act(PlayerAction::Ascend);
+    assert_eq!(simulation.app.world.resource::<Level>().depth, 1);
+    assert_eq!(revealed(&simulation), first);
+
+    simulation.act(PlayerAction::Descend);
+    assert_eq!(revealed(&simulation), second);
+}
+
+#[test]
+fn bumping_into_a_closed_door_opens_it() {
+    let mut simulation = Simulation::from_level(rooms_with_door('+'));
+    let player = spawn_player(&mut simulation, (3, 3));
+    // The door hides the other room
+    assert!(!is_revealed(&simulation, 6, 3));
+
+    simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+
+    assert_eq!(tile(&simulation, 4, 3), Some(TileType::OPENDOOR));
+    assert_eq!(position(&simulation, player), Position { x: 3, y: 3 });
+    assert_eq!(turn(&simulation), 1);
+    assert!(is_revealed(&simulation, 6, 3));
+
+    simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+    assert_eq!(position(&simulation, player), Position { x: 4, y: 3 });
+}
+
+#[test]
+fn locked_doors_need_a_key() {
+    let mut simulation = Simulation::from_level(rooms_with_door('='));
+    let player = spawn_player(&mut simulation, (3, 3));
+
+    simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+    assert_eq!(tile(&simulation, 4, 3), Some(TileType::LOCKEDDOOR));
+    assert_eq!(turn(&simulation), 0);
+
+    let mut inventory = simulation.app.world.get_mut::<Inventory>(player).unwrap();
+    inventory.items.push(ItemType::KEY);
+    simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+
+    assert_eq!(tile(&simulation, 4, 3), Some(TileType::OPENDOOR));
+    assert_eq!(turn(&simulation), 1);
+    let inventory = simulation.app.world.get::<Inventory>(player).unwrap();
+    assert!(inventory.items.is_empty());
+}
+
+#[test]
+fn open_doors_can_be_closed() {
+    let mut simulation = Simulation::from_level(rooms_with_door('\''));
+    let player = spawn_player(&mut simulation, (3, 3));
+    assert!(simulation
+        .app
+        .world
+        .get::<POV>(player)
+        .unwrap()
+        .visible_tiles
+        .iter()
+        .any(|tile| tile.0 == Position { x: 6, y: 3 }));
+
+    simulation.act(PlayerAction::CloseDoor);
+
+    assert_eq!(tile(&simulation, 4, 3), Some(TileType::CLOSEDDOOR));
+    assert_eq!(turn(&simulation), 1);
+    assert!(!simulation
+        .app
+        .world
+        .get::<POV>(player)
+        .unwrap()
+        .visible_tiles
+        .iter()
+        .any(|tile| tile.0 == Position { x: 6, y: 3 }));
+
+    // Nothing left to close
+    simulation.act(PlayerAction::CloseDoor);
+    assert_eq!(turn(&simulation), 1);
+}
+
+#[test]
+fn only_lit_tiles_can_be_seen() {
+    let mut simulation = Simulation::from_level(Level::from_ascii(&[
+        "############",
+        "#..........#",
+        "############",
+    ]));
+    let player = spawn_player(&mut simulation, (1, 1));
+    let sees = |simulation: &Simulation, x: i32| {
+        let pov = simulation.app.world.get::<POV>(player).unwrap();
+        pov.visible_tiles
+            .iter()
+            .any(|tile| tile.0 == Position { x, y: 1 })
+    };
+    let lantern = *simulation.app.world.get::<LightSource>(player).unwrap();
+    // In sight but past the lantern
+    assert!(sees(&simulation, 1 + lantern.radius));
+    assert!(!sees(&simulation, 2 + lantern.radius));
+    assert!(!is_revealed(&simulation, 2 + lantern.radius as usize, 1));
+
+    // A torch at the far end lights it up
+    simulation
+        .app
+        .world
+        .spawn((Position { x: 10, y: 1 }, LightKind::TORCH.source()));
+    simulation.app.update();
+    assert!(sees(&simulation, 2 + lantern.radius));
+    assert!(is_revealed(&simulation, 2 + lantern.radius as usize, 1));
+}
+
+fn ticks(simulation: &Simulation) -> u64 {
+    simulation.app.world.resource::<TurnScheduler>().ticks
+}
+
+#[test]
+fn wading_through_water_takes_longer() {
+    let mut simulation = Simulation::from_level(Level::from_ascii(&["######", "#..~~#", "######"]));
+    let player = spawn_player(&mut simulation, (1, 1));
+
+    let start = ticks(&simulation);
+    simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+    let on_floor = ticks(&simulation);
+    simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+    let in_water = ticks(&simulation);
+
+    assert_eq!(position(&simulation, player), Position { x: 3, y: 1 });
+    assert!(in_water - on_floor > on_floor - start);
+}
+
+#[test]
+fn lava_burns_until_water_puts_it_out() {
+    let mut simulation =
+        Simulation::from_level(Level::from_ascii(&["#######", "#.^~..#", "#######"]));
+    let player = spawn_player(&mut simulation, (1, 1));
+    let full = health(&simulation, player);
+
+    simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+    let burned = health(&simulation, player);
+    assert!(burned < full);
+    assert!(simulation.app.world.get::<Burning>(player).is_some());
+
+    simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+    assert!(simulation.app.world.get::<Burning>(player).is_none());
+    let doused = health(&simulation, player);
+
+    simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+    assert_eq!(health(&simulation, player), doused);
+}
+
+#[test]
+fn falling_into_a_chasm_drops_to_the_next_level() {
+    let mut simulation = Simulation::from_level(Level::from_ascii(&["#####", "#._.#", "#####"]));
+    let player = spawn_player(&mut simulation, (1, 1));
+
+    simulation.act(PlayerAction::Move { dx: 1, dy: 0 });
+
+    let map = simulation.app.world.resource::<Level>();
+    assert_eq!(map.depth, 2);
+    let landing = position(&simulation, player);
+    assert_eq!(map.tile_at(landing.x, landing.y), Some(TileType::FLOOR));
+    assert_eq!(simulation.state(), GameState::AwaitingInput);
+}
+
+#[test]
+fn tiles_in_sight_but_out_of_the_light_are_dark() {
+    let mut simulation = Simulation::from_level(Level::from_ascii(&[
+        "############",
+        "#..........#",
+        "############",
+    ]));
+    let player = spawn_player(&mut simulation, (1, 1));
+    let radius = simulation
+        .app
+        .world
+        .get::<LightSource>(player)
+        .unwrap()
+        .radius;
+    let beyond = 2 + radius;
+    let map = simulation.app.world.resource::<Level>();
+    let in_sight = field_of_view((1, 1), 20, |x, y| map.blocks_sight(x, y));
+    assert!(in_sight.contains(&(beyond, 1)));
+
+    // Colored the way the map is drawn
+    let color = |simulation: &Simulation, x: i32| {
+        let light_map = simulation.app.world.resource::<LightMap>();
+        TileType::FLOOR.color(light_map.color_at(x, 1, Color::WHITE))
+    };
+    let near = color(&simulation, 2);
+    assert!(near.r() > 0.0);
+    assert_eq!(color(&simulation, beyond), Color::rgb(0.0, 0.0, 0.0));
+
+    let torch = simulation
+        .app
+        .world
+        .spawn((Position { x: 10, y: 1 }, LightKind::TORCH.source()))
+        .id();
+    simulation.app.update();
+    assert!(color(&simulation, beyond).r() > 0.0);
+
+    simulation.app.world.despawn(torch);
+    simulation.app.update();
+    assert_eq!(color(&simulation, beyond), Color::rgb(0.0, 0.0, 0.0));
+}
+
+// Where the monsters still to act this round stand, in the order they will act.
+fn waiting_monsters(simulation: &mut Simulation) -> Vec<Position> {
+    let world = &mut simulation.app.world;
+    let ready = world.resource::<TurnScheduler>().ready.clone();
+    ready
+        .iter()
+        .map(|entity| *world.get::<Position>(*entity).unwrap())
+        .collect()
+}
+
+// Every mob with where it stands, its health and its energy, sorted by position.
+fn mobs(simulation: &mut Simulation) -> Vec<(Position, i32, i32)> {
+    let world = &mut simulation.app.world;
+    let mut mobs: Vec<_> = world
+        .query_filtered::<(&Position, &Health, &Energy), With<Mob>>()
+        .iter(world)
+        .map(|mob| (*mob.0, mob.1 .0, mob.2 .0))
+        .collect();
+    mobs.sort_by_key(|mob| (mob.0.x, mob.0.y));
+    mobs
+}
+
+#[test]
+fn a_resumed_game_hands_out_turns_like_the_saved_one() {
+    let wander = [(0, 1), (1, 0), (0, -1), (-1, 0)]
+        .iter()
+        .cycle()
+        .map(|&(dx, dy)| PlayerAction::Move { dx, dy });
+    let mut saved = Simulation::new(11);
+    saved.run(wander.clone().take(3));
+    // Saved halfway through a round, with monsters still to act after the player
+    let waiting = waiting_monsters(&mut saved);
+    assert!(!waiting.is_empty());
+
+    let save = save_game(&mut saved.app.world).unwrap();
+    let save = read_save(&write_save(&save).unwrap()).unwrap();
+    let mut resumed = Simulation::from_save(save);
+    assert_eq!(waiting_monsters(&mut resumed), waiting);
+    assert_eq!(mobs(&mut resumed), mobs(&mut saved));
+
+    for action in wander.take(40) {
+        saved.act(action);
+        resumed.act(action);
+        assert_eq!(mobs(&mut resumed), mobs(&mut saved));
+        assert_eq!(turn(&resumed), turn(&saved));
+        assert_eq!(ticks(&resumed), ticks(&saved));
+        assert_eq!(waiting_monsters(&mut resumed), waiting_monsters(&mut saved));
+    }
+}
+
+// Turns left to burn for every burning mob, sorted by position.
+fn burning(simulation: &mut Simulation) -> Vec<(Position, i32)> {
+    let world = &mut simulation.app.world;
+    let mut burning: Vec<_> = world
+        .query::<(&Position, &Burning)>()
+        .iter(world)
+        .map(|mob| (*mob.0, mob.1 .0))
+        .collect();
+    burning.sort_by_key(|mob| (mob.0.x, mob.0.y));
+    burning
+}
+
+#[test]
+fn burning_mobs_are_still_burning_after_a_resume() {
+    let mut saved = Simulation::new(11);
+    let world = &mut saved.app.world;
+    let player = world.query_filtered::<Entity, With<Player>>().single(world);
+    let monster = world
+        .query_filtered::<Entity, (With<Mob>, Without<Player>)>()
+        .iter(world)
+        .next()
+        .unwrap();
+    world.entity_mut(player).insert(Burning(2));
+    world.entity_mut(monster).insert(Burning(3));
+
+    let save = save_game(&mut saved.app.world).unwrap();
+    let save = read_save(&write_save(&save).unwrap()).unwrap();
+    let mut resumed = Simulation::from_save(save);
+    assert_eq!(burning(&mut resumed).len(), 2);
+    assert_eq!(burning(&mut resumed), burning(&mut saved));
+}